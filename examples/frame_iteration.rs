@@ -0,0 +1,68 @@
+//! Times filling a frame buffer through `Frame` against indexing it by hand.
+//!
+//! Looping over `rows` compiles to the same loop as indexing by hand. A `for` loop over
+//! `pixels` does not, since the nested iterator is stepped one pixel at a time, but
+//! `for_each` is as fast as `rows`.
+//!
+//! ```sh
+//! cargo run --release --example frame_iteration
+//! ```
+
+use glazer::Frame;
+use std::hint::black_box;
+use std::time::Instant;
+
+const WIDTH: usize = 1920;
+const HEIGHT: usize = 1080;
+const ITERATIONS: u32 = 200;
+
+fn color(x: usize, y: usize) -> u32 {
+    (x as u32) << 8 | y as u32
+}
+
+fn time(name: &str, pixels: &mut [u32], mut f: impl FnMut(&mut [u32])) {
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        f(black_box(&mut *pixels));
+    }
+    let per_frame = start.elapsed() / ITERATIONS;
+    println!("{name:>8}: {per_frame:?} per frame");
+}
+
+fn main() {
+    let mut pixels = vec![0u32; WIDTH * HEIGHT];
+
+    time("by hand", &mut pixels, |pixels| {
+        for y in 0..HEIGHT {
+            for x in 0..WIDTH {
+                pixels[y * WIDTH + x] = color(x, y);
+            }
+        }
+    });
+    let expected = pixels.clone();
+
+    time("rows", &mut pixels, |pixels| {
+        for (y, row) in Frame::new(pixels, WIDTH, HEIGHT).rows() {
+            for (x, pixel) in row.iter_mut().enumerate() {
+                *pixel = color(x, y);
+            }
+        }
+    });
+    assert_eq!(pixels, expected);
+
+    pixels.fill(0);
+    time("pixels", &mut pixels, |pixels| {
+        for (x, y, pixel) in Frame::new(pixels, WIDTH, HEIGHT).pixels() {
+            *pixel = color(x, y);
+        }
+    });
+    assert_eq!(pixels, expected);
+
+    pixels.fill(0);
+    time("for_each", &mut pixels, |pixels| {
+        Frame::new(pixels, WIDTH, HEIGHT)
+            .pixels()
+            .for_each(|(x, y, pixel)| *pixel = color(x, y));
+    });
+    assert_eq!(pixels, expected);
+}
@@ -0,0 +1,167 @@
+//! Generates a heightmap terrain with a cooperative task, drawing a progress bar while
+//! the scene keeps animating, then scrolls over the finished terrain.
+//!
+//! ```sh
+//! cargo run --release --example heightmap_task
+//! ```
+//!
+//! The task is spawned with `task::spawn_cooperative`, so it runs in slices before each
+//! frame as it would on the web, around 30 frames at the default budget. `task::spawn`
+//! would use a thread here.
+//!
+//! Debug builds load the game from a dylib to hot reload it, so this runs in release.
+
+extern crate alloc;
+
+use glazer::task::{self, Step, Task, TaskContext};
+use glazer::{App, PlatformInput, PlatformUpdate};
+
+const WIDTH: usize = 640;
+const HEIGHT: usize = 360;
+const TERRAIN_SIZE: usize = 768;
+const OCTAVES: u32 = 8;
+
+struct Memory {
+    generating: Option<Task<Vec<f32>>>,
+    terrain: Vec<f32>,
+    frames_generating: u32,
+}
+
+fn handle_input(_: PlatformInput<Memory>) {}
+
+fn update_and_render(update: PlatformUpdate<Memory, [u8; 4]>) {
+    let memory = update.memory;
+    let (width, height) = (update.width, update.height);
+    let frame_buffer = update.frame_buffer;
+
+    if let Some(generating) = &mut memory.generating {
+        memory.frames_generating += 1;
+        if let Some(terrain) = generating.poll() {
+            glazer::log!("terrain generated over {} frames", memory.frames_generating);
+            memory.terrain = terrain;
+            memory.generating = None;
+        }
+    }
+
+    match &memory.generating {
+        Some(generating) => {
+            // Moving stripes, so a stalled frame would be visible.
+            let offset = (update.time * 60.0) as usize;
+            for (i, pixel) in frame_buffer.iter_mut().enumerate() {
+                let (x, y) = (i % width, i / width);
+                let shade = if ((x + y + offset) / 16).is_multiple_of(2) {
+                    24
+                } else {
+                    32
+                };
+                *pixel = [shade, shade, shade + 8, 255];
+            }
+
+            let (bar_x, bar_y, bar_width, bar_height) = (width / 4, height / 2 - 8, width / 2, 16);
+            let filled = (bar_width as f32 * generating.progress()) as usize;
+            for y in bar_y..bar_y + bar_height {
+                for x in bar_x..bar_x + bar_width {
+                    frame_buffer[y * width + x] = if x - bar_x < filled {
+                        [96, 200, 120, 255]
+                    } else {
+                        [64, 64, 72, 255]
+                    };
+                }
+            }
+        }
+        None => {
+            let scroll = (update.time * 40.0) as usize;
+            for (i, pixel) in frame_buffer.iter_mut().enumerate() {
+                let (x, y) = (
+                    (i % width + scroll) % TERRAIN_SIZE,
+                    i / width % TERRAIN_SIZE,
+                );
+                *pixel = shade(memory.terrain[y * TERRAIN_SIZE + x]);
+            }
+        }
+    }
+}
+
+fn shade(height: f32) -> [u8; 4] {
+    let light = (height * 255.0) as u8;
+    if height < 0.45 {
+        [20, 60, 80 + light / 2, 255]
+    } else if height < 0.7 {
+        [40, 80 + light / 2, 40, 255]
+    } else {
+        [light, light, light, 255]
+    }
+}
+
+// Fractal value noise, one row per step so the task can yield between rows.
+fn generate_terrain() -> impl FnMut(&TaskContext) -> Step<Vec<f32>> + Send + 'static {
+    let mut terrain = Vec::with_capacity(TERRAIN_SIZE * TERRAIN_SIZE);
+    move |context| {
+        loop {
+            let y = terrain.len() / TERRAIN_SIZE;
+            terrain.extend((0..TERRAIN_SIZE).map(|x| fractal_noise(x as f32, y as f32)));
+            context.set_progress((y + 1) as f32 / TERRAIN_SIZE as f32);
+            if y + 1 == TERRAIN_SIZE {
+                return Step::Done(core::mem::take(&mut terrain));
+            }
+            if context.should_yield() {
+                return Step::Pending;
+            }
+        }
+    }
+}
+
+fn fractal_noise(x: f32, y: f32) -> f32 {
+    let (mut sum, mut amplitude, mut frequency, mut total) = (0.0, 0.5, 1.0 / 128.0, 0.0);
+    for octave in 0..OCTAVES {
+        sum += value_noise(x * frequency, y * frequency, octave) * amplitude;
+        total += amplitude;
+        amplitude *= 0.5;
+        frequency *= 2.0;
+    }
+    sum / total
+}
+
+// Smoothly interpolated random values at integer coordinates.
+fn value_noise(x: f32, y: f32, seed: u32) -> f32 {
+    let (x0, y0) = (x.floor(), y.floor());
+    let (tx, ty) = (smooth(x - x0), smooth(y - y0));
+    let (x0, y0) = (x0 as u32, y0 as u32);
+    let corner = |dx: u32, dy: u32| lattice(x0 + dx, y0 + dy, seed);
+    let top = corner(0, 0) + (corner(1, 0) - corner(0, 0)) * tx;
+    let bottom = corner(0, 1) + (corner(1, 1) - corner(0, 1)) * tx;
+    top + (bottom - top) * ty
+}
+
+fn smooth(t: f32) -> f32 {
+    t * t * (3.0 - 2.0 * t)
+}
+
+fn lattice(x: u32, y: u32, seed: u32) -> f32 {
+    let mut hash = x.wrapping_mul(0x9E37_79B1) ^ y.wrapping_mul(0x85EB_CA77) ^ seed;
+    hash ^= hash >> 15;
+    hash = hash.wrapping_mul(0x2C1B_3C6D);
+    hash ^= hash >> 12;
+    (hash & 0xFFFF) as f32 / 65535.0
+}
+
+fn main() {
+    let mut frame_buffer = vec![[0u8; 4]; WIDTH * HEIGHT];
+    let memory = Memory {
+        generating: Some(task::spawn_cooperative(generate_terrain())),
+        terrain: Vec::new(),
+        frames_generating: 0,
+    };
+    let app = App::new(
+        memory,
+        &mut frame_buffer,
+        WIDTH,
+        HEIGHT,
+        handle_input,
+        update_and_render,
+        "",
+    );
+    if let Err(err) = app.run() {
+        eprintln!("{err}");
+    }
+}
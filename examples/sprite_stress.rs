@@ -0,0 +1,132 @@
+//! 2,000 rotating, alpha blended sprites, logging the time spent drawing them once a
+//! second.
+//!
+//! ```sh
+//! cargo run --release --example sprite_stress
+//! ```
+//!
+//! Debug builds load the game from a dylib to hot reload it, so this runs in release.
+
+extern crate alloc;
+
+use glazer::dsp::Noise;
+use glazer::sprite::{self, Blend, Sampling, Sprite, Tint, Transform2D};
+use glazer::{App, PlatformInput, PlatformUpdate};
+use std::time::{Duration, Instant};
+
+const WIDTH: usize = 1280;
+const HEIGHT: usize = 720;
+const SPRITES: usize = 2000;
+const SPRITE_SIZE: usize = 24;
+
+struct Ship {
+    transform: Transform2D,
+    // radians per second
+    spin: f32,
+}
+
+struct Memory {
+    pixels: Vec<[u8; 4]>,
+    ships: Vec<Ship>,
+    tint: Tint,
+    draw_time: Duration,
+    frames: u32,
+    since_report: f32,
+}
+
+impl Memory {
+    fn new() -> Self {
+        // A soft edged disc with a bright stripe, so the rotation is visible.
+        let half = SPRITE_SIZE as f32 / 2.0;
+        let pixels = (0..SPRITE_SIZE * SPRITE_SIZE)
+            .map(|i| {
+                let (x, y) = (
+                    (i % SPRITE_SIZE) as f32 + 0.5,
+                    (i / SPRITE_SIZE) as f32 + 0.5,
+                );
+                let distance = ((x - half).powi(2) + (y - half).powi(2)).sqrt();
+                let alpha = ((half - distance) * 255.0).clamp(0.0, 255.0) as u8;
+                let stripe = if (y - half).abs() < 2.0 { 255 } else { 96 };
+                [stripe, 160, 255 - stripe / 2, alpha]
+            })
+            .collect();
+
+        let mut noise = Noise::new(1);
+        let ships = (0..SPRITES)
+            .map(|_| Ship {
+                transform: Transform2D {
+                    pos: [
+                        (noise.sample() * 0.5 + 0.5) * WIDTH as f32,
+                        (noise.sample() * 0.5 + 0.5) * HEIGHT as f32,
+                    ],
+                    rotation: noise.sample() * core::f32::consts::PI,
+                    scale: [1.0 + noise.sample() * 0.5; 2],
+                    origin: [half; 2],
+                },
+                spin: noise.sample() * 3.0,
+            })
+            .collect();
+
+        Self {
+            pixels,
+            ships,
+            tint: Tint::new([1.0, 0.8, 0.6], 0.9),
+            draw_time: Duration::ZERO,
+            frames: 0,
+            since_report: 0.0,
+        }
+    }
+}
+
+fn handle_input(_: PlatformInput<Memory>) {}
+
+fn update_and_render(update: PlatformUpdate<Memory, [u8; 4]>) {
+    let memory = update.memory;
+    let start = Instant::now();
+
+    update.frame_buffer.fill([16, 16, 24, 255]);
+    let sprite =
+        Sprite::new(&memory.pixels, SPRITE_SIZE, SPRITE_SIZE).with_sampling(Sampling::Bilinear);
+    for ship in &mut memory.ships {
+        ship.transform.rotation += ship.spin * update.delta;
+        sprite::draw_sprite(
+            update.frame_buffer,
+            update.width,
+            update.height,
+            &sprite,
+            ship.transform,
+            &memory.tint,
+            Blend::Alpha,
+        );
+    }
+
+    memory.draw_time += start.elapsed();
+    memory.frames += 1;
+    memory.since_report += update.delta;
+    if memory.since_report >= 1.0 {
+        glazer::log!(
+            "{SPRITES} sprites: {:.2}ms per frame, {} frames",
+            memory.draw_time.as_secs_f64() * 1000.0 / memory.frames as f64,
+            memory.frames
+        );
+        memory.draw_time = Duration::ZERO;
+        memory.frames = 0;
+        memory.since_report = 0.0;
+    }
+}
+
+fn main() {
+    let mut frame_buffer = vec![[0u8; 4]; WIDTH * HEIGHT];
+    let app = App::new(
+        Memory::new(),
+        &mut frame_buffer,
+        WIDTH,
+        HEIGHT,
+        handle_input,
+        update_and_render,
+        "",
+    );
+    if let Err(err) = app.run() {
+        eprintln!("{err}");
+    }
+}
@@ -2,103 +2,256 @@ extern crate std;
 
 use core::ffi::CStr;
 use std::boxed::Box;
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell, UnsafeCell};
+use std::collections::VecDeque;
 use std::ffi::c_void;
 use std::ptr::{NonNull, null_mut};
-use std::sync::atomic::{AtomicU64, Ordering};
+use std::rc::Rc;
+use std::string::{String, ToString};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock, TryLockError};
 use std::time::Instant;
-use std::{dbg, format};
+use std::vec::Vec;
+use std::{dbg, format, vec};
 
 use alloc::ffi::CString;
+use objc2::rc::Allocated;
 use objc2::rc::Retained;
-use objc2::runtime::ProtocolObject;
-use objc2::{AnyThread, DefinedClass, MainThreadOnly, define_class, msg_send};
+use objc2::runtime::{AnyClass, AnyObject, ProtocolObject, Sel};
+use objc2::{AnyThread, ClassType, DefinedClass, MainThreadOnly, Message, define_class, msg_send};
 use objc2_app_kit::{
-    NSApplication, NSApplicationActivationPolicy, NSApplicationDelegate,
-    NSApplicationTerminateReply, NSBackingStoreType, NSBitmapImageRep, NSColorSpaceName, NSEvent,
-    NSEventModifierFlags, NSImage, NSView, NSWindow, NSWindowDelegate, NSWindowStyleMask,
+    NSAlert, NSAlertFirstButtonReturn, NSApplication, NSApplicationActivationPolicy,
+    NSApplicationDelegate, NSApplicationTerminateReply, NSBackingStoreType, NSBitmapFormat,
+    NSBitmapImageRep, NSColor, NSColorSpaceName, NSCursor, NSDragOperation, NSDraggingDestination,
+    NSDraggingInfo, NSEvent, NSEventModifierFlags, NSEventPhase, NSEventType,
+    NSFilenamesPboardType, NSImage, NSImageView, NSMenu, NSMenuItem, NSPasteboard,
+    NSPasteboardTypeString, NSPrintOperation, NSRectFill, NSResponder, NSScreen, NSTextInputClient,
+    NSTrackingArea, NSTrackingAreaOptions, NSView, NSWindow, NSWindowAnimationBehavior,
+    NSWindowCollectionBehavior, NSWindowDelegate, NSWindowDidBecomeKeyNotification,
+    NSWindowDidChangeScreenNotification, NSWindowDidResignKeyNotification,
+    NSWindowDidResizeNotification, NSWindowSharingType, NSWindowStyleMask, NSWindowTitleVisibility,
+    NSWorkspace, NSWorkspaceActiveSpaceDidChangeNotification,
 };
 use objc2_audio_toolbox::{
     AURenderCallbackStruct, AudioComponentDescription, AudioComponentFindNext,
-    AudioComponentInstance, AudioComponentInstanceNew, AudioOutputUnitStart, AudioOutputUnitStop,
-    AudioUnitInitialize, AudioUnitRenderActionFlags, AudioUnitSetProperty,
-    kAudioUnitManufacturer_Apple, kAudioUnitProperty_SetRenderCallback,
-    kAudioUnitProperty_StreamFormat, kAudioUnitScope_Global, kAudioUnitScope_Input,
-    kAudioUnitSubType_DefaultOutput, kAudioUnitType_Output,
+    AudioComponentInstance, AudioComponentInstanceDispose, AudioComponentInstanceNew,
+    AudioOutputUnitStart, AudioOutputUnitStop, AudioUnitInitialize, AudioUnitRenderActionFlags,
+    AudioUnitSetProperty, kAudioOutputUnitProperty_CurrentDevice, kAudioUnitManufacturer_Apple,
+    kAudioUnitProperty_SetRenderCallback, kAudioUnitProperty_StreamFormat, kAudioUnitScope_Global,
+    kAudioUnitScope_Input, kAudioUnitSubType_DefaultOutput, kAudioUnitSubType_HALOutput,
+    kAudioUnitType_Output,
 };
 use objc2_core_audio_types::{
     AudioBufferList, AudioStreamBasicDescription, AudioTimeStamp, kAudioFormatLinearPCM,
-    kLinearPCMFormatFlagIsSignedInteger,
+    kLinearPCMFormatFlagIsFloat, kLinearPCMFormatFlagIsPacked, kLinearPCMFormatFlagIsSignedInteger,
 };
 use objc2_foundation::{
-    MainThreadMarker, NSNotification, NSObject, NSObjectProtocol, NSPoint, NSRect, NSSize,
-    NSString, NSTimer, ns_string,
+    MainThreadMarker, NSActivityOptions, NSArray, NSAttributedString, NSAttributedStringKey,
+    NSLocale, NSNotFound, NSNotification, NSNotificationCenter, NSObject, NSObjectProtocol,
+    NSPoint, NSProcessInfo, NSRange, NSRangePointer, NSRect, NSSize, NSString, NSTimer, ns_string,
 };
 
-use crate::{Input, KeyCode, KeyModifiers, PlatformInput, PlatformUpdate};
+#[cfg(feature = "std")]
+use crate::audio_capture::AudioCapture;
+use crate::command::{CAPACITY, Command, CommandQueue, Cursor};
+use crate::debug::{AllocationStats, mach_timebase};
+use crate::event_log::EventLog;
+use crate::gamepad::MAX_GAMEPADS;
+use crate::overlay::PerformanceOverlay;
+use crate::pipeline::upscale_nearest;
+#[cfg(feature = "std")]
+use crate::state_file::StateFile;
+use crate::{
+    App, AudioFormat, AudioRoute, AudioThreadPriority, ColorFilter, ColorMatrixPipeline, Commands,
+    Config, CursorEdges, Error, ErrorAction, FirstPresent, Gamepad, GamepadButton, Gamepads, Input,
+    Insets, KeyCode, KeyModifiers, LatencyMode, LatencyStats, MemoryLayout, MouseButton,
+    MouseInputMode, OpenGLContext, PlatformInput, PlatformUpdate, PostStageFn, PresentFilter,
+    PrintHandler, Rect, RenderPipeline, SampleFormat, Screenshot, ScreenshotStage, ScrollPhase,
+    ViewConfig, post_stage,
+};
 
 enum PlatformRequest<'a> {
     Update(PlatformState<'a>),
     Input(Input),
+    Print(PrintRequest<'a>),
+}
+
+struct PrintRequest<'a> {
+    width: usize,
+    height: usize,
+    pixels: &'a mut Option<Vec<u8>>,
 }
 
 struct PlatformState<'a> {
-    delta: f32,
+    delta: f64,
+    time: f64,
+    seconds_since_last_input: f32,
+    idle: bool,
+    commands: &'a mut CommandQueue,
+    gamepads: &'a Gamepads,
+    mouse_position: (f32, f32),
+    // bottom left of the window in screen coordinates
+    #[cfg(feature = "std")]
+    window_origin: (f64, f64),
     //
     frame_buffer: *mut u8,
     width: usize,
     height: usize,
+    gl_context: Option<&'a OpenGLContext>,
+    safe_area_insets: Insets,
+    // null without `Config::ui_layer`
+    ui_frame_buffer: *mut u8,
+    ui_width: usize,
+    ui_height: usize,
     //
     samples: &'a mut [i16],
     channels: usize,
     sample_rate: f32,
+    audio_format: AudioFormat,
+    //
+    latency: Option<LatencyStats>,
 }
 
-pub fn run<Memory, Pixels>(
-    memory: Memory,
-    frame_buffer: &mut [Pixels],
-    width: usize,
-    height: usize,
-    _handle_input: fn(PlatformInput<Memory>),
-    _update_and_render: fn(PlatformUpdate<Memory, Pixels>),
-    shared_lib_path: &str,
-) where
+pub fn run<Memory, Pixels>(app: App<Memory, Pixels>) -> Result<Memory, Error>
+where
     Pixels: 'static,
     Memory: 'static,
 {
+    let App {
+        memory,
+        frame_buffer,
+        width,
+        height,
+        handle_input: _handle_input,
+        update_and_render: _update_and_render,
+        shared_lib_path,
+        print_handler,
+        view,
+        startup_checks,
+        audio_routes,
+        #[cfg(feature = "std")]
+        state_file,
+        config,
+    } = app;
+
+    let _crash_log_hook = CrashLogHook::install();
+
+    // The shared application is created by its first use, so this must come first.
+    let _: Retained<NSApplication> =
+        unsafe { msg_send![GlazerApplication::class(), sharedApplication] };
+
+    let failures: Vec<String> = startup_checks
+        .iter()
+        .filter_map(|check| check().err())
+        .collect();
+    if !failures.is_empty() {
+        report_startup_failures(&failures);
+        return Err(Error::StartupChecks(failures));
+    }
+
+    #[cfg(feature = "std")]
+    let (mut memory, mut config) = (memory, config);
+    #[cfg(feature = "std")]
+    if let Some(state_file) = &state_file
+        && (config.resume || std::env::args().any(|arg| arg == "--resume"))
+    {
+        // Safe by the contract of `App::with_state_file`.
+        config.window_origin = unsafe { state_file.load(&mut memory) };
+    }
+
+    // Metal reads this when the first device is created.
+    if config.metal_hud {
+        unsafe { std::env::set_var("MTL_HUD_ENABLED", "1") };
+    }
+
     #[cfg(not(debug_assertions))]
-    run_release(
+    let (memory, window_origin) = run_release(
         memory,
         frame_buffer,
         width,
         height,
         _handle_input,
         _update_and_render,
-    );
+        print_handler,
+        view,
+        audio_routes,
+        config,
+        #[cfg(feature = "std")]
+        state_file.clone(),
+    )?;
     #[cfg(debug_assertions)]
-    run_debug(memory, frame_buffer, width, height, shared_lib_path);
+    let (memory, window_origin) = run_debug(
+        memory,
+        frame_buffer,
+        width,
+        height,
+        shared_lib_path,
+        print_handler,
+        view,
+        audio_routes,
+        config,
+        #[cfg(feature = "std")]
+        state_file.clone(),
+    )?;
+    #[cfg(feature = "std")]
+    if let Some(state_file) = &state_file
+        // Safe by the contract of `App::with_state_file`.
+        && let Err(err) = unsafe { state_file.save(&memory, window_origin) }
+    {
+        crate::log!(
+            "ERROR: failed to write state file `{}`: {err}",
+            state_file.path
+        );
+    }
+    #[cfg(not(feature = "std"))]
+    let _ = window_origin;
+    Ok(memory)
 }
 
 #[cfg(not(debug_assertions))]
+#[allow(clippy::too_many_arguments)]
 fn run_release<Memory, Pixels>(
-    mut memory: Memory,
+    memory: Memory,
     frame_buffer: &mut [Pixels],
     width: usize,
     height: usize,
     handle_input: fn(PlatformInput<Memory>),
     update_and_render: fn(PlatformUpdate<Memory, Pixels>),
-) where
+    print_handler: Option<PrintHandler<Memory>>,
+    view: ViewConfig,
+    audio_routes: Vec<AudioRoute>,
+    config: Config,
+    #[cfg(feature = "std")] mut state_file: Option<StateFile>,
+) -> Result<(Memory, (f64, f64)), Error>
+where
     Pixels: 'static,
     Memory: 'static,
 {
+    // Shared with the update closure, which outlives the run loop.
+    let memory = Rc::new(RefCell::new(Some(memory)));
+    let game_memory = memory.clone();
     let pixels_len = frame_buffer.len();
     let update = move |req: PlatformRequest| {
+        let mut memory = game_memory.borrow_mut();
+        // Taken once the run loop returns.
+        let Some(memory) = memory.as_mut() else {
+            return;
+        };
         match req {
             PlatformRequest::Update(state) => {
                 debug_assert!(pixels_len >= state.width * state.height);
+                crate::task::__run_cooperative(crate::task::FRAME_BUDGET_NANOS);
                 update_and_render(PlatformUpdate {
-                    memory: &mut memory,
-                    delta: state.delta,
+                    memory,
+                    delta: state.delta as f32,
+                    delta_f64: state.delta,
+                    time: state.time,
+                    seconds_since_last_input: state.seconds_since_last_input,
+                    idle: state.idle,
+                    commands: Commands::new(state.commands),
+                    gamepads: *state.gamepads,
+                    mouse_x: state.mouse_position.0,
+                    mouse_y: state.mouse_position.1,
                     //
                     frame_buffer: unsafe {
                         core::slice::from_raw_parts_mut(
@@ -108,41 +261,92 @@ fn run_release<Memory, Pixels>(
                     },
                     width: state.width,
                     height: state.height,
+                    gl_context: state.gl_context,
+                    safe_area_insets: state.safe_area_insets,
+                    ui_frame_buffer: (!state.ui_frame_buffer.is_null()).then(|| unsafe {
+                        core::slice::from_raw_parts_mut(
+                            state.ui_frame_buffer as *mut _,
+                            state.ui_width * state.ui_height,
+                        )
+                    }),
+                    ui_width: state.ui_width,
+                    ui_height: state.ui_height,
                     //
                     samples: state.samples,
                     sample_rate: state.sample_rate,
                     channels: state.channels,
-                })
+                    actual_audio_format: state.audio_format,
+                    //
+                    latency: state.latency,
+                });
+                #[cfg(feature = "std")]
+                save_state_periodically(&mut state_file, &config, memory, &state);
+            }
+            PlatformRequest::Input(input) => handle_input(PlatformInput { memory, input }),
+            PlatformRequest::Print(print) => {
+                *print.pixels = print_handler.map(|f| f(memory, print.width, print.height));
             }
-            PlatformRequest::Input(input) => handle_input(PlatformInput {
-                memory: &mut memory,
-                input,
-            }),
         }
     };
-    run_app(frame_buffer.as_mut_ptr() as *mut u8, width, height, update);
+    let window_origin = run_app(
+        frame_buffer.as_mut_ptr() as *mut u8,
+        width,
+        height,
+        view,
+        &audio_routes,
+        config,
+        update,
+    )?;
+    let memory = memory.borrow_mut().take();
+    Ok((
+        memory.expect("memory is only taken after the run loop"),
+        window_origin,
+    ))
 }
 
 #[cfg(debug_assertions)]
+#[allow(clippy::too_many_arguments)]
 pub fn run_debug<Memory, Pixels>(
-    mut memory: Memory,
+    memory: Memory,
     frame_buffer: &mut [Pixels],
     width: usize,
     height: usize,
     shared_lib_path: &str,
-) where
+    print_handler: Option<PrintHandler<Memory>>,
+    view: ViewConfig,
+    audio_routes: Vec<AudioRoute>,
+    config: Config,
+    #[cfg(feature = "std")] mut state_file: Option<StateFile>,
+) -> Result<(Memory, (f64, f64)), Error>
+where
     Pixels: 'static,
     Memory: 'static,
 {
     use alloc::string::ToString;
 
     let shared_lib_path = shared_lib_path.to_string();
-    let mut functions =
-        load_game_dylib::<Memory, Pixels>(&shared_lib_path).expect("failed to load game dylib");
+    let mut functions = loop {
+        if let Some(functions) = load_game_dylib::<Memory, Pixels>(&shared_lib_path) {
+            break functions;
+        }
+        // There is no code to continue with yet.
+        let message = format!("failed to load game dylib `{shared_lib_path}`");
+        if report_error(&config, &message) != ErrorAction::Retry {
+            return Err(Error::Aborted(message));
+        }
+    };
     let mut loaded_instant = std::time::SystemTime::now();
 
+    // Shared with the update closure, which outlives the run loop.
+    let memory = Rc::new(RefCell::new(Some(memory)));
+    let game_memory = memory.clone();
     let pixels_len = frame_buffer.len();
     let update = move |req: PlatformRequest| {
+        let mut memory = game_memory.borrow_mut();
+        // Taken once the run loop returns.
+        let Some(memory) = memory.as_mut() else {
+            return;
+        };
         if let Some(modified) = std::fs::metadata(&shared_lib_path).ok().and_then(|meta| {
             meta.modified().ok().and_then(|modified| {
                 modified
@@ -151,20 +355,48 @@ pub fn run_debug<Memory, Pixels>(
                     .then_some(modified)
             })
         }) {
-            debug_assert_eq!(unsafe { libc::dlclose(functions.dylib) }, 0);
-            debug_assert_eq!(unsafe { libc::dlclose(functions.dylib) }, 0);
-            debug_assert_eq!(unsafe { libc::dlclose(functions.dylib) }, 0);
-            functions = load_game_dylib::<Memory, Pixels>(&shared_lib_path)
-                .expect("failed to load game dylib");
-            loaded_instant = modified;
+            match load_game_dylib::<Memory, Pixels>(&shared_lib_path) {
+                Some(new_functions) => {
+                    if accept_memory_layout(memory, &functions, &new_functions) {
+                        debug_assert_eq!(unsafe { libc::dlclose(functions.dylib) }, 0);
+                        debug_assert_eq!(unsafe { libc::dlclose(functions.dylib) }, 0);
+                        debug_assert_eq!(unsafe { libc::dlclose(functions.dylib) }, 0);
+                        functions = new_functions;
+                    } else {
+                        // Keep running the previously loaded code until the next rebuild.
+                        unsafe { libc::dlclose(new_functions.dylib) };
+                    }
+                    loaded_instant = modified;
+                }
+                None => {
+                    let message = format!("failed to reload game dylib `{shared_lib_path}`");
+                    match report_error(&config, &message) {
+                        // The modified time is left alone, so the next request reloads.
+                        ErrorAction::Retry => {}
+                        // Keep running the previously loaded code until the next rebuild.
+                        ErrorAction::Continue => loaded_instant = modified,
+                        ErrorAction::Abort => panic!("{message}"),
+                    }
+                }
+            }
         }
 
         match req {
             PlatformRequest::Update(state) => {
                 debug_assert!(pixels_len >= state.width * state.height);
+                (functions.run_tasks)(crate::task::FRAME_BUDGET_NANOS);
+                let allocations_before = (functions.allocation_stats)();
                 (functions.update_and_render)(PlatformUpdate {
-                    memory: &mut memory,
-                    delta: state.delta,
+                    memory,
+                    delta: state.delta as f32,
+                    delta_f64: state.delta,
+                    time: state.time,
+                    seconds_since_last_input: state.seconds_since_last_input,
+                    idle: state.idle,
+                    commands: Commands::new(state.commands),
+                    gamepads: *state.gamepads,
+                    mouse_x: state.mouse_position.0,
+                    mouse_y: state.mouse_position.1,
                     //
                     frame_buffer: unsafe {
                         core::slice::from_raw_parts_mut(
@@ -174,25 +406,129 @@ pub fn run_debug<Memory, Pixels>(
                     },
                     width: state.width,
                     height: state.height,
+                    gl_context: state.gl_context,
+                    safe_area_insets: state.safe_area_insets,
+                    ui_frame_buffer: (!state.ui_frame_buffer.is_null()).then(|| unsafe {
+                        core::slice::from_raw_parts_mut(
+                            state.ui_frame_buffer as *mut _,
+                            state.ui_width * state.ui_height,
+                        )
+                    }),
+                    ui_width: state.ui_width,
+                    ui_height: state.ui_height,
                     //
                     samples: state.samples,
                     sample_rate: state.sample_rate,
                     channels: state.channels,
-                })
+                    actual_audio_format: state.audio_format,
+                    //
+                    latency: state.latency,
+                });
+                if config.assert_no_frame_allocations {
+                    let frame = (functions.allocation_stats)() - allocations_before;
+                    assert!(
+                        frame.allocations == 0,
+                        "update_and_render made {} allocations ({} bytes)",
+                        frame.allocations,
+                        frame.bytes
+                    );
+                }
+                #[cfg(feature = "std")]
+                save_state_periodically(&mut state_file, &config, memory, &state);
+            }
+            PlatformRequest::Input(input) => {
+                (functions.handle_input)(PlatformInput { memory, input })
+            }
+            PlatformRequest::Print(print) => {
+                *print.pixels = print_handler.map(|f| f(memory, print.width, print.height));
             }
-            PlatformRequest::Input(input) => (functions.handle_input)(PlatformInput {
-                memory: &mut memory,
-                input,
-            }),
         }
     };
-    run_app(frame_buffer.as_mut_ptr() as *mut u8, width, height, update);
+    let window_origin = run_app(
+        frame_buffer.as_mut_ptr() as *mut u8,
+        width,
+        height,
+        view,
+        &audio_routes,
+        config,
+        update,
+    )?;
+    let memory = memory.borrow_mut().take();
+    Ok((
+        memory.expect("memory is only taken after the run loop"),
+        window_origin,
+    ))
+}
+
+// see `App::with_state_save_interval`
+#[cfg(feature = "std")]
+fn save_state_periodically<Memory>(
+    state_file: &mut Option<StateFile>,
+    config: &Config,
+    memory: &Memory,
+    state: &PlatformState,
+) {
+    if let Some(state_file) = state_file
+        && let Some(interval) = config.state_save_interval
+    {
+        // Safe by the contract of `App::with_state_file`.
+        unsafe { state_file.save_every(interval, state.time, memory, state.window_origin) };
+    }
 }
 
 struct LoadedGameFunctions<Memory, Pixels> {
     dylib: *mut c_void,
     handle_input: fn(PlatformInput<Memory>),
     update_and_render: fn(PlatformUpdate<Memory, Pixels>),
+    allocation_stats: extern "C" fn() -> AllocationStats,
+    // see `glazer::export_tasks`
+    run_tasks: extern "C" fn(u64),
+    // see `glazer::export_memory_layout`
+    memory_layout: Option<MemoryLayout>,
+    migrate_memory: Option<extern "C" fn(*mut u8, u32)>,
+}
+
+/// Whether the `new` dylib can take over `memory` from `old`, migrating it if only the
+/// version changed.
+#[cfg(debug_assertions)]
+fn accept_memory_layout<Memory, Pixels>(
+    memory: &mut Memory,
+    old: &LoadedGameFunctions<Memory, Pixels>,
+    new: &LoadedGameFunctions<Memory, Pixels>,
+) -> bool {
+    let Some(layout) = new.memory_layout else {
+        return true;
+    };
+    let running = MemoryLayout::of::<Memory>(old.memory_layout.map_or(0, |old| old.version));
+    if layout.size != running.size || layout.align != running.align {
+        crate::log!(
+            "ERROR: refusing reload, `Memory` changed from {} bytes aligned to {} to {} bytes \
+             aligned to {}",
+            running.size,
+            running.align,
+            layout.size,
+            layout.align
+        );
+        return false;
+    }
+    if old.memory_layout.is_some() && layout.version != running.version {
+        let Some(migrate_memory) = new.migrate_memory else {
+            crate::log!(
+                "ERROR: refusing reload, `Memory` changed from version {} to {} without a \
+                 migration",
+                running.version,
+                layout.version
+            );
+            return false;
+        };
+        crate::log!(
+            "migrating `Memory` from version {} to {}",
+            running.version,
+            layout.version
+        );
+        migrate_memory(memory as *mut Memory as *mut u8, running.version);
+    }
+    true
 }
 
 fn load_game_dylib<Memory, Pixels>(path: &str) -> Option<LoadedGameFunctions<Memory, Pixels>> {
@@ -219,10 +555,57 @@ fn load_game_dylib<Memory, Pixels>(path: &str) -> Option<LoadedGameFunctions<Mem
                 let handle_input: fn(PlatformInput<Memory>) =
                     unsafe { std::mem::transmute(symbol as *const ()) };
 
+                // If the dylib does not export its counters, only the platform's own
+                // allocations are visible.
+                extern "C" fn platform_allocation_stats() -> AllocationStats {
+                    crate::debug::allocation_stats()
+                }
+                let symbol = unsafe { libc::dlsym(dylib, c"glazer_allocation_stats".as_ptr()) };
+                let allocation_stats = if symbol.is_null() {
+                    platform_allocation_stats
+                } else {
+                    unsafe {
+                        std::mem::transmute::<*const (), extern "C" fn() -> AllocationStats>(
+                            symbol as *const (),
+                        )
+                    }
+                };
+
+                // If the dylib does not export its tasks, only those spawned by the
+                // platform's own copy of glazer run.
+                let symbol = unsafe { libc::dlsym(dylib, c"glazer_run_tasks".as_ptr()) };
+                let run_tasks = if symbol.is_null() {
+                    crate::task::__run_cooperative
+                } else {
+                    unsafe {
+                        std::mem::transmute::<*const (), extern "C" fn(u64)>(symbol as *const ())
+                    }
+                };
+
+                let symbol = unsafe { libc::dlsym(dylib, c"glazer_memory_layout".as_ptr()) };
+                let memory_layout = (!symbol.is_null()).then(|| {
+                    let memory_layout = unsafe {
+                        std::mem::transmute::<*const (), extern "C" fn() -> MemoryLayout>(
+                            symbol as *const (),
+                        )
+                    };
+                    memory_layout()
+                });
+                let symbol = unsafe { libc::dlsym(dylib, c"glazer_migrate_memory".as_ptr()) };
+                let migrate_memory = (!symbol.is_null()).then(|| unsafe {
+                    std::mem::transmute::<*const (), extern "C" fn(*mut u8, u32)>(
+                        symbol as *const (),
+                    )
+                });
+
                 return Some(LoadedGameFunctions {
                     dylib,
                     handle_input,
                     update_and_render,
+                    allocation_stats,
+                    run_tasks,
+                    memory_layout,
+                    migrate_memory,
                 });
             } else {
                 let str = unsafe { CStr::from_ptr(libc::dlerror()) };
@@ -249,23 +632,426 @@ fn load_game_dylib<Memory, Pixels>(path: &str) -> Option<LoadedGameFunctions<Mem
     None
 }
 
+/// Shows every failed `App::with_startup_check` in a single alert.
+fn report_startup_failures(failures: &[String]) {
+    for failure in failures {
+        crate::log!("ERROR: startup check failed: {failure}");
+    }
+
+    let mtm = MainThreadMarker::new().unwrap();
+    unsafe {
+        let alert = NSAlert::new(mtm);
+        alert.setMessageText(ns_string!("The app could not be started"));
+        alert.setInformativeText(&NSString::from_str(&failures.join("\n")));
+        alert.addButtonWithTitle(ns_string!("Quit"));
+        alert.runModal();
+    }
+}
+
+/// Asks the app's error dialog, or an alert, how to proceed after `message`.
+fn report_error(config: &Config, message: &str) -> ErrorAction {
+    crate::log!("ERROR: {message}");
+    if let Some(error_dialog) = config.error_dialog {
+        return error_dialog(message);
+    }
+
+    let mtm = MainThreadMarker::new().unwrap();
+    unsafe {
+        let alert = NSAlert::new(mtm);
+        alert.setMessageText(&NSString::from_str(message));
+        alert.addButtonWithTitle(ns_string!("Retry"));
+        alert.addButtonWithTitle(ns_string!("Abort"));
+        if alert.runModal() == NSAlertFirstButtonReturn {
+            ErrorAction::Retry
+        } else {
+            ErrorAction::Abort
+        }
+    }
+}
+
+/// Calls `f` with each window's content view which responds to `selector`.
+///
+/// In debug builds the free functions are called from the game dylib's copy of glazer, so
+/// the view is found through the shared app and messaged rather than referenced directly.
+fn for_each_game_view(selector: Sel, mut f: impl FnMut(&NSView)) {
+    let Some(mtm) = MainThreadMarker::new() else {
+        return;
+    };
+    for window in NSApplication::sharedApplication(mtm).windows().to_vec() {
+        if let Some(view) = window.contentView()
+            && view.respondsToSelector(selector)
+        {
+            f(&view);
+        }
+    }
+}
+
+pub fn set_ime_area(x: f32, y: f32, w: f32, h: f32) {
+    let area = NSRect::new(
+        NSPoint::new(x as f64, y as f64),
+        NSSize::new(w as f64, h as f64),
+    );
+    for_each_game_view(objc2::sel!(setImeArea:), |view| {
+        let _: () = unsafe { msg_send![view, setImeArea: area] };
+    });
+}
+
+pub fn set_first_present_held(held: bool) {
+    for_each_game_view(objc2::sel!(setFirstPresentHeld:), |view| {
+        let _: () = unsafe { msg_send![view, setFirstPresentHeld: held] };
+    });
+}
+
+pub fn set_window_opacity(opacity: f32) {
+    let opacity = opacity.clamp(0.0, 1.0) as f64;
+    for_each_game_view(objc2::sel!(setWindowOpacity:), |view| {
+        let _: () = unsafe { msg_send![view, setWindowOpacity: opacity] };
+    });
+}
+
+pub fn dump_event_log(path: &str) -> bool {
+    let path = NSString::from_str(path);
+    let mut written = false;
+    for_each_game_view(objc2::sel!(dumpEventLog:), |view| {
+        let view_written: bool = unsafe { msg_send![view, dumpEventLog: &*path] };
+        written |= view_written;
+    });
+    written
+}
+
+#[cfg(feature = "std")]
+pub fn start_audio_capture(path: &str) -> bool {
+    let path = NSString::from_str(path);
+    let mut started = false;
+    for_each_game_view(objc2::sel!(startAudioCapture:), |view| {
+        let view_started: bool = unsafe { msg_send![view, startAudioCapture: &*path] };
+        started |= view_started;
+    });
+    started
+}
+
+#[cfg(feature = "std")]
+pub fn stop_audio_capture() {
+    for_each_game_view(objc2::sel!(stopAudioCapture), |view| {
+        let _: () = unsafe { msg_send![view, stopAudioCapture] };
+    });
+}
+
+#[cfg(feature = "std")]
+pub fn mark_audio_capture(name: &str) {
+    let name = NSString::from_str(name);
+    for_each_game_view(objc2::sel!(markAudioCapture:), |view| {
+        let _: () = unsafe { msg_send![view, markAudioCapture: &*name] };
+    });
+}
+
+pub fn set_present_filter(filter: PresentFilter) {
+    let filter = &filter as *const PresentFilter as *const c_void;
+    for_each_game_view(objc2::sel!(setPresentFilter:), |view| {
+        let _: () = unsafe { msg_send![view, setPresentFilter: filter] };
+    });
+}
+
+pub fn screenshot(stage: ScreenshotStage) -> Option<Screenshot> {
+    let mut request: (ScreenshotStage, Option<Screenshot>) = (stage, None);
+    let request_ptr = &mut request as *mut (ScreenshotStage, Option<Screenshot>) as *mut c_void;
+    for_each_game_view(objc2::sel!(captureScreenshot:), |view| {
+        let _: () = unsafe { msg_send![view, captureScreenshot: request_ptr] };
+    });
+    request.1
+}
+
+pub fn set_cursor_confined(confined: bool) {
+    for_each_game_view(objc2::sel!(setCursorConfined:), |view| {
+        let _: () = unsafe { msg_send![view, setCursorConfined: confined] };
+    });
+}
+
+pub fn set_cursor_visible(visible: bool) {
+    for_each_game_view(objc2::sel!(setCursorVisible:), |view| {
+        let _: () = unsafe { msg_send![view, setCursorVisible: visible] };
+    });
+}
+
+/// See `glazer::CustomCursor`.
+#[derive(Debug, Clone)]
+pub struct CustomCursor(Retained<NSCursor>);
+
+impl CustomCursor {
+    pub fn from_rgba(
+        data: &[u8],
+        width: usize,
+        height: usize,
+        (hotspot_x, hotspot_y): (u32, u32),
+    ) -> Option<Self> {
+        // Image reps created without a format expect premultiplied alpha.
+        let mut pixels = data.to_vec();
+        for pixel in pixels.chunks_exact_mut(4) {
+            let alpha = pixel[3] as u16;
+            for channel in &mut pixel[..3] {
+                *channel = ((*channel as u16 * alpha + 127) / 255) as u8;
+            }
+        }
+        let size = NSSize::new(width as f64, height as f64);
+        let image = image_from_rgba(&pixels, width, height, size)?;
+        let hotspot = NSPoint::new(hotspot_x as f64, hotspot_y as f64);
+        Some(Self(NSCursor::initWithImage_hotSpot(
+            NSCursor::alloc(),
+            &image,
+            hotspot,
+        )))
+    }
+}
+
+pub fn set_custom_cursor(cursor: &CustomCursor) {
+    for_each_game_view(objc2::sel!(setCustomCursor:), |view| {
+        let _: () = unsafe { msg_send![view, setCustomCursor: &*cursor.0] };
+    });
+}
+
+pub fn set_cursor_locked(locked: bool) {
+    for_each_game_view(objc2::sel!(setCursorLocked:), |view| {
+        let _: () = unsafe { msg_send![view, setCursorLocked: locked] };
+    });
+}
+
+pub fn set_content_protection(enabled: bool) {
+    for_each_game_view(objc2::sel!(setContentProtection:), |view| {
+        let _: () = unsafe { msg_send![view, setContentProtection: enabled] };
+    });
+}
+
+pub fn opengl_make_current(context: &OpenGLContext) {
+    let context = unsafe { context.context.cast::<AnyObject>().as_ref() };
+    let _: () = unsafe { msg_send![context, makeCurrentContext] };
+}
+
+pub fn opengl_swap_buffers(context: &OpenGLContext) {
+    let context = unsafe { context.context.cast::<AnyObject>().as_ref() };
+    let _: () = unsafe { msg_send![context, flushBuffer] };
+}
+
+/// Creates the context for `App::with_opengl_context`, or `None` if the app should fall
+/// back to the frame buffer.
+fn init_opengl(config: &Config) -> Option<(Retained<AnyObject>, OpenGLContext)> {
+    loop {
+        match create_opengl_context() {
+            Some(context) => {
+                let handle = OpenGLContext {
+                    context: NonNull::from(&*context).cast(),
+                };
+                return Some((context, handle));
+            }
+            None => {
+                let message = "failed to create OpenGL context";
+                match report_error(config, message) {
+                    ErrorAction::Retry => {}
+                    ErrorAction::Continue => return None,
+                    ErrorAction::Abort => panic!("{message}"),
+                }
+            }
+        }
+    }
+}
+
+#[expect(deprecated)]
+fn create_opengl_context() -> Option<Retained<AnyObject>> {
+    use objc2_app_kit::{
+        NSOpenGLPFAAccelerated, NSOpenGLPFAColorSize, NSOpenGLPFADepthSize,
+        NSOpenGLPFADoubleBuffer, NSOpenGLPFAOpenGLProfile, NSOpenGLProfileVersion3_2Core,
+    };
+
+    let attributes: [u32; 9] = [
+        NSOpenGLPFADoubleBuffer,
+        NSOpenGLPFAAccelerated,
+        NSOpenGLPFAColorSize,
+        24,
+        NSOpenGLPFADepthSize,
+        24,
+        NSOpenGLPFAOpenGLProfile,
+        NSOpenGLProfileVersion3_2Core,
+        0,
+    ];
+    // `objc2-app-kit` does not bind the deprecated `NSOpenGL*` classes.
+    let pixel_format_class = AnyClass::get(c"NSOpenGLPixelFormat")?;
+    let context_class = AnyClass::get(c"NSOpenGLContext")?;
+    unsafe {
+        let pixel_format: Allocated<AnyObject> = msg_send![pixel_format_class, alloc];
+        let pixel_format: Option<Retained<AnyObject>> =
+            msg_send![pixel_format, initWithAttributes: attributes.as_ptr()];
+        let pixel_format = pixel_format?;
+
+        let context: Allocated<AnyObject> = msg_send![context_class, alloc];
+        let share_context: Option<&AnyObject> = None;
+        msg_send![context, initWithFormat: &*pixel_format, shareContext: share_context]
+    }
+}
+
+pub fn system_locale() -> String {
+    unsafe { NSLocale::currentLocale().localeIdentifier().to_string() }
+}
+
 fn run_app(
     frame_buffer: *mut u8,
     width: usize,
     height: usize,
+    view: ViewConfig,
+    audio_routes: &[AudioRoute],
+    config: Config,
     update: impl FnMut(PlatformRequest) + 'static,
-) {
-    let app = init_app(update, frame_buffer, width, height);
-    init_audio();
-    unsafe { app.finishLaunching() };
+) -> Result<(f64, f64), Error> {
+    let audio = loop {
+        match AudioOutput::new(&config, audio_routes) {
+            Ok(audio) => break Some(&*Box::leak(Box::new(audio))),
+            Err(err) => {
+                let message = format!("failed to initialize audio: {err}");
+                match report_error(&config, &message) {
+                    ErrorAction::Retry => {}
+                    ErrorAction::Continue => break None,
+                    ErrorAction::Abort => return Err(Error::Aborted(message)),
+                }
+            }
+        }
+    };
+    let (app, delegate) = init_app(update, frame_buffer, width, height, view, config, audio);
+    // Returns after `stop_app`, launching the app first.
     app.run();
+
+    // The game is not called into again after this, so its memory can be returned.
+    unsafe { delegate.ivars().timer.invalidate() };
+    if let Some(audio) = audio {
+        audio.stop();
+    }
+    // Finishes the WAV header of a capture left running.
+    #[cfg(feature = "std")]
+    stop_audio_capture();
+    let origin = delegate.ivars().window.frame().origin;
+    for window in app.windows().to_vec() {
+        window.orderOut(None);
+    }
+    Ok((origin.x, origin.y))
+}
+
+/// Returns from `NSApplication::run` in `run_app` on the next pass of the run loop.
+fn stop_app(mtm: MainThreadMarker) {
+    // The game keeps running once `App::run` returns.
+    for_each_game_view(objc2::sel!(releaseCursor), |view| {
+        let _: () = unsafe { msg_send![view, releaseCursor] };
+    });
+    let app = NSApplication::sharedApplication(mtm);
+    app.stop(None);
+    // `stop:` is only checked after an event is handled, which may never come while the
+    // app sits in the background.
+    let event = unsafe {
+        NSEvent::otherEventWithType_location_modifierFlags_timestamp_windowNumber_context_subtype_data1_data2(
+            NSEventType::ApplicationDefined,
+            NSPoint::new(0.0, 0.0),
+            NSEventModifierFlags(0),
+            0.0,
+            0,
+            None,
+            0,
+            0,
+            0,
+        )
+    };
+    if let Some(event) = event {
+        app.postEvent_atStart(&event, true);
+    }
 }
 
+define_class!(
+    // Media keys are sent to the application as system defined events rather than to the
+    // key window, so they are intercepted here and forwarded to the game view.
+    #[unsafe(super(NSApplication, NSResponder, NSObject))]
+    #[thread_kind = MainThreadOnly]
+    struct GlazerApplication;
+
+    unsafe impl NSObjectProtocol for GlazerApplication {}
+
+    impl GlazerApplication {
+        #[unsafe(method(sendEvent:))]
+        fn send_event(&self, event: &NSEvent) {
+            let mut consumed = false;
+            if unsafe { event.r#type() } == NSEventType::SystemDefined
+                && unsafe { event.subtype() }.0 == MEDIA_KEY_SUBTYPE
+            {
+                for_each_game_view(objc2::sel!(handleMediaKey:), |view| {
+                    let handled: bool = unsafe { msg_send![view, handleMediaKey: event] };
+                    consumed |= handled;
+                });
+            }
+            if !consumed {
+                let _: () = unsafe { msg_send![super(self), sendEvent: event] };
+            }
+            // `NSApplication` drops key releases while Command is held, so the game would
+            // never see the key come up.
+            if unsafe { event.r#type() } == NSEventType::KeyUp
+                && unsafe { event.modifierFlags() }.contains(NSEventModifierFlags::Command)
+                && let Some(window) = self.keyWindow()
+            {
+                window.sendEvent(event);
+            }
+        }
+
+        // The Quit menu item, which returns from `App::run` rather than exiting like
+        // `terminate:`.
+        #[unsafe(method(quit:))]
+        fn quit(&self, _sender: Option<&AnyObject>) {
+            stop_app(self.mtm());
+        }
+    }
+);
+
 #[derive(Debug, Clone)]
 struct AppDelegateIvars {
-    #[expect(unused)]
     window: Retained<NSWindow>,
-    _timer: Retained<NSTimer>,
+    timer: Retained<NSTimer>,
+    // keeps App Nap disabled in `LatencyMode::LowLatency`
+    _activity: Option<Retained<ProtocolObject<dyn NSObjectProtocol>>>,
+    audio: Option<&'static AudioOutput>,
+}
+
+// Timer tolerance in `LatencyMode::PowerSaving`, a quarter of a 60Hz frame.
+const POWER_SAVING_TIMER_TOLERANCE: f64 = 1.0 / 240.0;
+
+/// Applies `mode` to the frame timer and main thread, returning the activity which must
+/// be kept alive for it to stay in effect.
+fn apply_latency_mode(
+    mode: LatencyMode,
+    timer: &NSTimer,
+) -> Option<Retained<ProtocolObject<dyn NSObjectProtocol>>> {
+    match mode {
+        LatencyMode::PowerSaving => {
+            unsafe { timer.setTolerance(POWER_SAVING_TIMER_TOLERANCE) };
+            crate::log!(
+                "latency mode: power saving, timer tolerance {:.2}ms",
+                POWER_SAVING_TIMER_TOLERANCE * 1000.0
+            );
+            None
+        }
+        LatencyMode::LowLatency => {
+            unsafe { timer.setTolerance(0.0) };
+            let result = unsafe {
+                libc::pthread_set_qos_class_self_np(
+                    libc::qos_class_t::QOS_CLASS_USER_INTERACTIVE,
+                    0,
+                )
+            };
+            if result != 0 {
+                crate::log!("ERROR: failed to set main thread QoS class: {result}");
+            }
+            let activity = unsafe {
+                NSProcessInfo::processInfo().beginActivityWithOptions_reason(
+                    NSActivityOptions::UserInitiatedAllowingIdleSystemSleep
+                        | NSActivityOptions::LatencyCritical,
+                    ns_string!("latency critical rendering"),
+                )
+            };
+            crate::log!("latency mode: low latency, timer tolerance 0ms, user interactive QoS");
+            Some(activity)
+        }
+    }
 }
 
 define_class!(
@@ -281,8 +1067,9 @@ define_class!(
         fn did_finish_launching(&self, notification: &NSNotification) {
             dbg!(notification);
             dbg!(self.ivars());
-            start_audio();
-            NSApplication::main(MainThreadMarker::from(self));
+            if let Some(audio) = self.ivars().audio {
+                audio.start();
+            }
         }
 
         #[unsafe(method(applicationShouldTerminate:))]
@@ -290,7 +1077,9 @@ define_class!(
             &self,
             _sender: &NSApplication,
         ) -> NSApplicationTerminateReply {
-            stop_audio();
+            if let Some(audio) = self.ivars().audio {
+                audio.stop();
+            }
             NSApplicationTerminateReply::TerminateNow
         }
 
@@ -299,15 +1088,16 @@ define_class!(
             &self,
             _sender: &NSApplication,
         ) -> bool {
-            true
+            // Closing the window stops the run loop instead, see `windowWillClose:`.
+            false
         }
     }
 
     unsafe impl NSWindowDelegate for Delegate {
         #[unsafe(method(windowWillClose:))]
         fn window_will_close(&self, _notification: &NSNotification) {
-            // Quit the application when the window is closed.
-            unsafe { NSApplication::sharedApplication(self.mtm()).terminate(None) };
+            // Return from `App::run` when the window is closed.
+            stop_app(self.mtm());
         }
     }
 );
@@ -317,8 +1107,10 @@ impl Delegate {
         mtm: MainThreadMarker,
         window: Retained<NSWindow>,
         view: &Retained<GameView>,
+        audio: Option<&'static AudioOutput>,
+        latency_mode: Option<LatencyMode>,
     ) -> Retained<Self> {
-        let _timer = unsafe {
+        let timer = unsafe {
             NSTimer::scheduledTimerWithTimeInterval_target_selector_userInfo_repeats(
                 0.0,
                 view,
@@ -327,16 +1119,112 @@ impl Delegate {
                 true,
             )
         };
-        let this = Self::alloc(mtm).set_ivars(AppDelegateIvars { window, _timer });
+        let _activity = latency_mode.and_then(|mode| apply_latency_mode(mode, &timer));
+        let this = Self::alloc(mtm).set_ivars(AppDelegateIvars {
+            window,
+            timer,
+            _activity,
+            audio,
+        });
         unsafe { msg_send![super(this), init] }
     }
 }
 
+/// A step applied to the presented frame, see `App::add_post_stage`.
+enum PostStage {
+    ColorFilter,
+    PresentFilter,
+    PerformanceOverlay,
+    Game(PostStageFn),
+}
+
 struct GameViewIvars {
     fb: *mut u8,
     update: RefCell<Box<dyn FnMut(PlatformRequest)>>,
-    last_time: RefCell<Instant>,
+    clock: RefCell<FrameClock>,
     window: Retained<NSWindow>,
+    audio: Option<&'static AudioOutput>,
+    in_transition: Cell<bool>,
+    suppress_mouse_delta: Cell<bool>,
+    clamped_transition_deltas: Cell<u64>,
+    // focus was lost with `Config::pause_on_focus_loss`
+    paused: Cell<bool>,
+    last_input_time: Cell<f64>,
+    #[cfg(feature = "std")]
+    audio_capture: RefCell<Option<AudioCapture>>,
+    // `glazer::set_cursor_confined`
+    cursor_confined: Cell<bool>,
+    cursor_edges: Cell<CursorEdges>,
+    // updates so far, labels profiling intervals
+    frame_index: Cell<u64>,
+    // seconds between presents with `Config::preferred_fps`
+    present_interval: Cell<f64>,
+    // `AUDIO_UNDERRUNS` when it was last recorded in the event log
+    audio_underruns: Cell<u64>,
+    // mouse motion since the last input, until it leaves `Config::idle_mouse_dead_zone`
+    idle_mouse_travel: Cell<f32>,
+    config: Config,
+    // timestamp of the latency probe key press, waiting for an update
+    latency_probe_event: Cell<Option<f64>>,
+    // timestamp of the latency probe key press, waiting for the frame to be drawn
+    latency_probe_frame: Cell<Option<f64>>,
+    latency: Cell<LatencyStats>,
+    // changed with `Command::SetTitle`
+    title: RefCell<String>,
+    commands: RefCell<CommandQueue>,
+    // requested with `Command::SetCursorVisible`
+    cursor_hidden: Cell<bool>,
+    // set with `Command::SetCursor`, applied through the view's cursor rect
+    cursor: Cell<Cursor>,
+    // set with `glazer::set_custom_cursor`, used over `cursor` until the next
+    // `Command::SetCursor`
+    custom_cursor: RefCell<Option<Retained<NSCursor>>>,
+    // whether the cursor is over the view, a hidden cursor is shown outside it
+    cursor_inside: Cell<bool>,
+    // screen position to restore, `Some` while grabbed with `Command::SetCursorGrab`
+    cursor_grab: Cell<Option<NSPoint>>,
+    // applied by `sync_cursor`, hides are counted so only one is outstanding
+    cursor_hide_applied: Cell<bool>,
+    cursor_detached: Cell<bool>,
+    // copy of `fb`, or the output of `render_pipeline`, taken once the game finishes a
+    // frame so `drawRect:` never reads a partially written one, see `pixel_bytes`
+    front_buffer: RefCell<Vec<u32>>,
+    // larger than the frame once a present filter scales it up
+    front_buffer_size: Cell<(usize, usize)>,
+    // straight alpha RGBA drawn over the frame with `Config::ui_layer`, see `pixel_bytes`
+    ui_layer: RefCell<Vec<u32>>,
+    ui_layer_size: Cell<(usize, usize)>,
+    render_pipeline: RefCell<Option<Box<dyn RenderPipeline>>>,
+    // input method composition, empty when not composing
+    marked_text: RefCell<String>,
+    // the `NSOpenGLContext` and the handle given to the game
+    gl_context: Option<(Retained<AnyObject>, OpenGLContext)>,
+    gl_context_attached: Cell<bool>,
+    // top left origin, see `glazer::set_ime_area`
+    ime_area: Cell<NSRect>,
+    drag_areas: Vec<Rect>,
+    color_filters: Vec<(ColorFilter, ColorMatrixPipeline)>,
+    // index into `color_filters`, unfiltered when `None`
+    color_filter: Cell<Option<usize>>,
+    // window changes requested by the game, applied at the start of the next update
+    pending_opacity: Cell<Option<f64>>,
+    pending_content_protection: Cell<Option<bool>>,
+    // accumulated pinch in the current gesture, `None` once it has toggled full screen
+    magnification: Cell<Option<f64>>,
+    performance_overlay: RefCell<Option<PerformanceOverlay>>,
+    // run on `front_buffer` after `render_pipeline`, in order
+    post_stages: RefCell<Vec<(i32, PostStage)>>,
+    present_filter: Cell<PresentFilter>,
+    // the frame scaled up for the present filter to read from, empty without one
+    present_filter_input: RefCell<Vec<u8>>,
+    first_present_pending: Cell<bool>,
+    // see `glazer::hold_first_present`
+    first_present_held: Cell<bool>,
+    pending_input: InputQueue,
+    // as of the last `flagsChanged:`, cleared when focus is lost
+    modifier_flags: Cell<NSEventModifierFlags>,
+    // `GCController`s by slot in `Gamepads`
+    gamepads: RefCell<[Option<Retained<AnyObject>>; MAX_GAMEPADS]>,
 }
 
 define_class!(
@@ -347,130 +1235,644 @@ define_class!(
 
     unsafe impl NSObjectProtocol for GameView {}
 
-    impl GameView {
-        #[unsafe(method(drawRect:))]
-        fn draw_rect(&self, rect: NSRect) {
-            let fb = self.ivars().fb;
-            let image_rep = unsafe {
-
-                let planes: [*const u8; 1] = [fb];
-                NSBitmapImageRep::initWithBitmapDataPlanes_pixelsWide_pixelsHigh_bitsPerSample_samplesPerPixel_hasAlpha_isPlanar_colorSpaceName_bytesPerRow_bitsPerPixel(
-                    NSBitmapImageRep::alloc(),
-                    planes.as_ptr() as *mut _,
-                    WIDTH as isize,
-                    HEIGHT as isize,
-                    8,
-                    4,
-                    true,
-                    false,
-                    &*NSColorSpaceName::from_str("NSCalibratedRGBColorSpace"),
-                    WIDTH as isize * 4,
-                    32,
-                )
-            };
-
-            if let Some(image_rep) = image_rep {
-                unsafe {
-                    let size = NSSize::new(WIDTH as f64, HEIGHT as f64);
-                    let image = NSImage::initWithSize(NSImage::alloc(), size);
-                    image.addRepresentation(&image_rep);
-                    image.drawInRect(rect);
-                }
+    unsafe impl NSDraggingDestination for GameView {
+        #[unsafe(method(draggingEntered:))]
+        fn dragging_entered(&self, sender: &ProtocolObject<dyn NSDraggingInfo>) -> NSDragOperation {
+            if dropped_paths(sender).is_empty() {
+                NSDragOperation::None
+            } else {
+                NSDragOperation::Copy
             }
         }
 
-        #[unsafe(method(update:))]
-        fn update(&self, _timer: &NSTimer) {
-            update(self, self.ivars());
+        #[unsafe(method(performDragOperation:))]
+        fn perform_drag_operation(&self, sender: &ProtocolObject<dyn NSDraggingInfo>) -> bool {
+            let paths = dropped_paths(sender);
+            let accepted = !paths.is_empty();
+            if accepted {
+                self.queue_input(Input::FileDrop { paths });
+            }
+            accepted
         }
+    }
 
-        #[unsafe(method(acceptsFirstResponder))]
-        fn accepts_first_responder(&self) -> bool {
-            true
+    unsafe impl NSTextInputClient for GameView {
+        #[unsafe(method(insertText:replacementRange:))]
+        fn insert_text(&self, string: &AnyObject, _replacement_range: NSRange) {
+            self.ivars().marked_text.borrow_mut().clear();
+            self.queue_input(Input::ImeCommit {
+                text: input_text(string),
+            });
         }
 
-        #[unsafe(method(keyDown:))]
-        fn key_down(&self, event: &NSEvent) {
-            let mut update = self.ivars().update.borrow_mut();
-            unsafe {
-                update(PlatformRequest::Input(Input::Key {
-                    code: KEY_CODE_LUT[event.keyCode() as usize],
-                    modifiers: KeyModifiers::from(event.modifierFlags()),
-                    pressed: true,
-                    repeat: event.isARepeat(),
-                }));
+        // Commands such as `insertNewline:` are already reported as key presses.
+        #[unsafe(method(doCommandBySelector:))]
+        fn do_command_by_selector(&self, _selector: Sel) {}
+
+        #[unsafe(method(setMarkedText:selectedRange:replacementRange:))]
+        fn set_marked_text(
+            &self,
+            string: &AnyObject,
+            selected_range: NSRange,
+            _replacement_range: NSRange,
+        ) {
+            let text = input_text(string);
+            let started = self.ivars().marked_text.borrow().is_empty();
+            *self.ivars().marked_text.borrow_mut() = text.clone();
+
+            if started {
+                self.queue_input(Input::ImeStartComposition);
             }
+            let cursor = utf16_to_byte_offset(&text, selected_range.location);
+            self.queue_input(Input::ImeUpdateComposition { text, cursor });
         }
 
-        #[unsafe(method(keyUp:))]
-        fn key_up(&self, event: &NSEvent) {
-            let mut update = self.ivars().update.borrow_mut();
-            unsafe {
-                update(PlatformRequest::Input(Input::Key {
-                    code: KEY_CODE_LUT[event.keyCode() as usize],
-                    modifiers: KeyModifiers::from(event.modifierFlags()),
-                    pressed: false,
-                    repeat: event.isARepeat(),
-                }));
+        #[unsafe(method(unmarkText))]
+        fn unmark_text(&self) {
+            let text = core::mem::take(&mut *self.ivars().marked_text.borrow_mut());
+            if !text.is_empty() {
+                self.queue_input(Input::ImeCommit { text });
             }
         }
 
-        #[unsafe(method(mouseMoved:))]
-        fn mouse_moved(&self, event: &NSEvent) {
-            let mut update = self.ivars().update.borrow_mut();
-            unsafe {
-                update(PlatformRequest::Input(Input::MouseMoved {
-                    dx: event.deltaX() as f32,
-                    dy: event.deltaY() as f32,
-                }));
+        #[unsafe(method(selectedRange))]
+        fn selected_range(&self) -> NSRange {
+            NSRange::new(NSNotFound as usize, 0)
+        }
+
+        #[unsafe(method(markedRange))]
+        fn marked_range(&self) -> NSRange {
+            let marked_text = self.ivars().marked_text.borrow();
+            if marked_text.is_empty() {
+                NSRange::new(NSNotFound as usize, 0)
+            } else {
+                NSRange::new(0, marked_text.encode_utf16().count())
             }
         }
 
-        #[unsafe(method(flagsChanged:))]
-        fn flags_changed(&self, event: &NSEvent) {
-            static mut PREVIOUS_MODIFIER_FLAGS: NSEventModifierFlags = NSEventModifierFlags(0);
+        #[unsafe(method(hasMarkedText))]
+        fn has_marked_text(&self) -> bool {
+            !self.ivars().marked_text.borrow().is_empty()
+        }
 
-            unsafe {
-                let current_flags = event.modifierFlags();
-                #[allow(static_mut_refs)]
-                let changed = current_flags.bits() ^ PREVIOUS_MODIFIER_FLAGS.bits();
-                PREVIOUS_MODIFIER_FLAGS = current_flags;
-                let pressed = (current_flags.bits() & changed) != 0;
-                let mut update = self.ivars().update.borrow_mut();
-
-                if changed & NSEventModifierFlags::Shift.bits() != 0 {
-                    update(PlatformRequest::Input(Input::Key {
-                        // TODO: LeftShift vs RightShift
-                        code: KeyCode::LeftShift,
-                        modifiers: KeyModifiers::from(current_flags),
-                        pressed,
-                        repeat: false,
-                    }));
-                }
+        #[unsafe(method_id(attributedSubstringForProposedRange:actualRange:))]
+        fn attributed_substring_for_proposed_range(
+            &self,
+            _range: NSRange,
+            _actual_range: NSRangePointer,
+        ) -> Option<Retained<NSAttributedString>> {
+            None
+        }
+
+        #[unsafe(method_id(validAttributesForMarkedText))]
+        fn valid_attributes_for_marked_text(&self) -> Retained<NSArray<NSAttributedStringKey>> {
+            NSArray::new()
+        }
+
+        #[unsafe(method(firstRectForCharacterRange:actualRange:))]
+        fn first_rect_for_character_range(
+            &self,
+            _range: NSRange,
+            _actual_range: NSRangePointer,
+        ) -> NSRect {
+            let area = self.ivars().ime_area.get();
+            // `ime_area` has a top left origin, views have a bottom left origin.
+            let flipped = NSRect::new(
+                NSPoint::new(
+                    area.origin.x,
+                    self.bounds().size.height - area.origin.y - area.size.height,
+                ),
+                area.size,
+            );
+            let in_window = self.convertRect_toView(flipped, None);
+            self.ivars().window.convertRectToScreen(in_window)
+        }
+
+        #[unsafe(method(characterIndexForPoint:))]
+        fn character_index_for_point(&self, _point: NSPoint) -> usize {
+            NSNotFound as usize
+        }
+    }
+
+    impl GameView {
+        #[unsafe(method(resetCursorRects))]
+        fn reset_cursor_rects(&self) {
+            if let Some(cursor) = &*self.ivars().custom_cursor.borrow() {
+                self.addCursorRect_cursor(self.bounds(), cursor);
+                return;
+            }
+            let cursor = match self.ivars().cursor.get() {
+                // Hidden by `sync_cursor`.
+                Cursor::Arrow | Cursor::Hidden => NSCursor::arrowCursor(),
+                Cursor::Hand => NSCursor::pointingHandCursor(),
+                Cursor::IBeam => NSCursor::IBeamCursor(),
+                Cursor::Crosshair => NSCursor::crosshairCursor(),
+                // Their replacements need macOS 15.
+                #[expect(deprecated)]
+                Cursor::ResizeEW => NSCursor::resizeLeftRightCursor(),
+                #[expect(deprecated)]
+                Cursor::ResizeNS => NSCursor::resizeUpDownCursor(),
+            };
+            self.addCursorRect_cursor(self.bounds(), &cursor);
+        }
+
+        #[unsafe(method(drawRect:))]
+        fn draw_rect(&self, rect: NSRect) {
+            // With OpenGL the game presents with `OpenGLContext::swap_buffers` instead.
+            if self.ivars().gl_context.is_some() || unsafe { WIDTH == 0 || HEIGHT == 0 } {
+                return;
+            }
+
+            // Stretched over the whole view, which is only the frame's size until the window
+            // is resized.
+            let bounds = self.bounds();
+            let size = unsafe { NSSize::new(WIDTH as f64, HEIGHT as f64) };
+            let (width, height) = self.ivars().front_buffer_size.get();
+            let fb = self.ivars().front_buffer.borrow_mut().as_mut_ptr().cast();
+            unsafe { draw_rgba(fb, width, height, NSBitmapFormat(0), size, bounds) };
+
+            let (ui_width, ui_height) = self.ivars().ui_layer_size.get();
+            if ui_width > 0 && ui_height > 0 {
+                let ui_layer = self.ivars().ui_layer.borrow_mut().as_mut_ptr().cast();
+                let format = NSBitmapFormat::AlphaNonpremultiplied;
+                unsafe { draw_rgba(ui_layer, ui_width, ui_height, format, size, bounds) };
+            }
+
+            if let Some(timestamp) = self.ivars().latency_probe_frame.take() {
+                let latency = unsafe { NSProcessInfo::processInfo().systemUptime() } - timestamp;
+                let mut stats = self.ivars().latency.get();
+                stats.record(latency);
+                self.ivars().latency.set(stats);
+                crate::log!(
+                    "latency: {:.2}ms (min {:.2}ms, avg {:.2}ms, max {:.2}ms)",
+                    latency * 1000.0,
+                    stats.min * 1000.0,
+                    stats.avg() * 1000.0,
+                    stats.max * 1000.0,
+                );
+
+                let marker = NSRect::new(
+                    NSPoint::new(0.0, rect.size.height - LATENCY_MARKER_SIZE),
+                    NSSize::new(LATENCY_MARKER_SIZE, LATENCY_MARKER_SIZE),
+                );
+                unsafe {
+                    NSColor::whiteColor().set();
+                    NSRectFill(marker);
+                }
+            }
+        }
+
+        #[unsafe(method(update:))]
+        fn update(&self, _timer: &NSTimer) {
+            update(self, self.ivars());
+        }
+
+        // Sent by `glazer::set_ime_area`.
+        #[unsafe(method(setImeArea:))]
+        fn set_ime_area(&self, area: NSRect) {
+            self.ivars().ime_area.set(area);
+            if let Some(context) = self.inputContext() {
+                context.invalidateCharacterCoordinates();
+            }
+        }
+
+        // Sent by `glazer::hold_first_present` and `glazer::release_first_present`.
+        #[unsafe(method(setFirstPresentHeld:))]
+        fn set_first_present_held(&self, held: bool) {
+            self.ivars().first_present_held.set(held);
+        }
+
+        // Sent by `GlazerApplication`, returns whether the event was a captured media key.
+        #[unsafe(method(handleMediaKey:))]
+        fn handle_media_key(&self, event: &NSEvent) -> bool {
+            self.ivars().config.media_keys && self.queue_media_key(event)
+        }
+
+        // Sent by `glazer::set_window_opacity`.
+        #[unsafe(method(setWindowOpacity:))]
+        fn set_window_opacity(&self, opacity: f64) {
+            self.ivars().pending_opacity.set(Some(opacity));
+        }
+
+        // Sent by `glazer::audio_capture::start`.
+        #[cfg(feature = "std")]
+        #[unsafe(method(startAudioCapture:))]
+        fn start_audio_capture_message(&self, path: &NSString) -> bool {
+            self.start_audio_capture(&path.to_string())
+        }
+
+        // Sent by `glazer::audio_capture::stop`.
+        #[cfg(feature = "std")]
+        #[unsafe(method(stopAudioCapture))]
+        fn stop_audio_capture(&self) {
+            if let Some(capture) = self.ivars().audio_capture.take()
+                && let Err(err) = capture.stop()
+            {
+                crate::log!("ERROR: failed to finish audio capture: {err}");
+            }
+        }
+
+        // Sent by `glazer::audio_capture::mark`.
+        #[cfg(feature = "std")]
+        #[unsafe(method(markAudioCapture:))]
+        fn mark_audio_capture(&self, name: &NSString) {
+            if let Some(capture) = self.ivars().audio_capture.borrow_mut().as_mut() {
+                capture.mark(&name.to_string());
+            }
+        }
+
+        // Sent by `glazer::dump_event_log`.
+        #[unsafe(method(dumpEventLog:))]
+        fn dump_event_log(&self, path: &NSString) -> bool {
+            write_event_log(&path.to_string(), None)
+        }
+
+        // Sent by `glazer::set_present_filter`, with a pointer to the `PresentFilter`.
+        #[unsafe(method(setPresentFilter:))]
+        fn set_present_filter(&self, filter: *const c_void) {
+            let filter = unsafe { *filter.cast::<PresentFilter>() };
+            self.ivars().present_filter.set(filter);
+        }
+
+        // Sent by `glazer::screenshot`, with a pointer to the stage and the result, which
+        // the first window fills.
+        #[unsafe(method(captureScreenshot:))]
+        fn capture_screenshot(&self, request: *mut c_void) {
+            let (stage, screenshot) =
+                unsafe { &mut *request.cast::<(ScreenshotStage, Option<Screenshot>)>() };
+            if screenshot.is_none() {
+                *screenshot = self.screenshot(*stage);
+            }
+        }
+
+        // Sent by `glazer::set_cursor_confined`.
+        #[unsafe(method(setCursorConfined:))]
+        fn set_cursor_confined(&self, confined: bool) {
+            self.ivars().cursor_confined.set(confined);
+            if !confined && self.ivars().cursor_edges.replace(CursorEdges::NONE) != CursorEdges::NONE {
+                self.queue_input(Input::CursorAtEdge {
+                    edges: CursorEdges::NONE,
+                });
+            }
+        }
+
+        // Sent by `glazer::set_custom_cursor`.
+        #[unsafe(method(setCustomCursor:))]
+        fn set_custom_cursor(&self, cursor: &NSCursor) {
+            *self.ivars().custom_cursor.borrow_mut() = Some(cursor.retain());
+            self.ivars().window.invalidateCursorRectsForView(self);
+        }
+
+        // Sent by `glazer::set_cursor_visible`.
+        #[unsafe(method(setCursorVisible:))]
+        fn set_cursor_visible(&self, visible: bool) {
+            self.ivars().cursor_hidden.set(!visible);
+            self.sync_cursor();
+        }
+
+        // Sent by `glazer::set_cursor_locked`.
+        #[unsafe(method(setCursorLocked:))]
+        fn set_cursor_locked(&self, locked: bool) {
+            self.set_cursor_grab(locked);
+        }
+
+        // Sent by `stop_app`.
+        #[unsafe(method(releaseCursor))]
+        fn release_cursor(&self) {
+            self.set_cursor_grab(false);
+            self.ivars().cursor_hidden.set(false);
+            self.ivars().cursor.set(Cursor::Arrow);
+            self.sync_cursor();
+        }
+
+        // Sent by `glazer::set_content_protection`.
+        #[unsafe(method(setContentProtection:))]
+        fn set_content_protection(&self, enabled: bool) {
+            self.ivars().pending_content_protection.set(Some(enabled));
+        }
+
+        #[unsafe(method(printFramebuffer:))]
+        fn print_framebuffer(&self, _sender: Option<&AnyObject>) {
+            print_framebuffer(self, self.ivars());
+        }
+
+        // Services provider message for the `processText` service, see
+        // `App::with_services_menu`.
+        #[unsafe(method(processText:userData:error:))]
+        fn process_text(
+            &self,
+            pasteboard: &NSPasteboard,
+            _user_data: Option<&NSString>,
+            _error: *mut *mut NSString,
+        ) {
+            let Some(text) = (unsafe { pasteboard.stringForType(NSPasteboardTypeString) }) else {
+                return;
+            };
+            self.queue_input(Input::ServiceAction {
+                action_id: "com.glazer.processText".to_string(),
+                data: text.to_string().into_bytes(),
+            });
+        }
+
+        #[unsafe(method(acceptsFirstResponder))]
+        fn accepts_first_responder(&self) -> bool {
+            true
+        }
+
+        #[unsafe(method(keyDown:))]
+        fn key_down(&self, event: &NSEvent) {
+            let modifiers = unsafe { event.modifierFlags() };
+            if uses_input_method(&self.ivars().config, modifiers) {
+                let was_composing = !self.ivars().marked_text.borrow().is_empty();
+                let handled = self
+                    .inputContext()
+                    .is_some_and(|context| unsafe { context.handleEvent(event) });
+                let composing = !self.ivars().marked_text.borrow().is_empty();
+                if handled && (was_composing || composing) {
+                    return;
+                }
+            }
+
+            unsafe {
+                let code = KEY_CODE_LUT[event.keyCode() as usize];
+                if self.ivars().config.latency_probe == Some(code) && !event.isARepeat() {
+                    self.ivars().latency_probe_event.set(Some(event.timestamp()));
+                }
+                if self.ivars().config.color_filter_key == Some(code) && !event.isARepeat() {
+                    self.cycle_color_filter();
+                }
+                if is_consumed_shortcut(&self.ivars().config, code) {
+                    return;
+                }
+                self.queue_input(Input::Key {
+                    code,
+                    modifiers: KeyModifiers::from(event.modifierFlags()),
+                    pressed: true,
+                    repeat: event.isARepeat(),
+                });
+            }
+        }
+
+        #[unsafe(method(keyUp:))]
+        fn key_up(&self, event: &NSEvent) {
+            unsafe {
+                let code = KEY_CODE_LUT[event.keyCode() as usize];
+                if is_consumed_shortcut(&self.ivars().config, code) {
+                    return;
+                }
+                self.queue_input(Input::Key {
+                    code,
+                    modifiers: KeyModifiers::from(event.modifierFlags()),
+                    pressed: false,
+                    repeat: event.isARepeat(),
+                });
+            }
+        }
+
+        #[unsafe(method(mouseDown:))]
+        fn mouse_down(&self, event: &NSEvent) {
+            let point = self.convertPoint_fromView(unsafe { event.locationInWindow() }, None);
+            // `drag_areas` have a top left origin, views have a bottom left origin.
+            let (x, y) = (point.x as f32, (self.bounds().size.height - point.y) as f32);
+            if self.ivars().drag_areas.iter().any(|area| area.contains(x, y)) {
+                self.ivars().window.performWindowDragWithEvent(event);
+            } else {
+                self.mouse_button(event, true);
+            }
+        }
+
+        #[unsafe(method(mouseUp:))]
+        fn mouse_up(&self, event: &NSEvent) {
+            self.mouse_button(event, false);
+        }
+
+        #[unsafe(method(rightMouseDown:))]
+        fn right_mouse_down(&self, event: &NSEvent) {
+            self.mouse_button(event, true);
+        }
+
+        #[unsafe(method(rightMouseUp:))]
+        fn right_mouse_up(&self, event: &NSEvent) {
+            self.mouse_button(event, false);
+        }
+
+        #[unsafe(method(otherMouseDown:))]
+        fn other_mouse_down(&self, event: &NSEvent) {
+            self.mouse_button(event, true);
+        }
+
+        #[unsafe(method(otherMouseUp:))]
+        fn other_mouse_up(&self, event: &NSEvent) {
+            self.mouse_button(event, false);
+        }
+
+        #[unsafe(method(scrollWheel:))]
+        fn scroll_wheel(&self, event: &NSEvent) {
+            let (phase, momentum) = unsafe { (event.phase(), event.momentumPhase()) };
+            let phase = if !momentum.is_empty() {
+                ScrollPhase::Momentum
+            } else if phase.contains(NSEventPhase::MayBegin) {
+                ScrollPhase::MayBegin
+            } else if phase.contains(NSEventPhase::Began) {
+                ScrollPhase::Began
+            } else if phase.contains(NSEventPhase::Changed) {
+                ScrollPhase::Changed
+            } else if phase.intersects(NSEventPhase::Ended | NSEventPhase::Cancelled) {
+                ScrollPhase::Ended
+            } else {
+                ScrollPhase::Wheel
+            };
+            let scale = if unsafe { event.hasPreciseScrollingDeltas() } {
+                1.0
+            } else {
+                SCROLL_LINE_POINTS
+            };
+            unsafe {
+                self.queue_input(Input::MouseScroll {
+                    dx: (event.scrollingDeltaX() * scale) as f32,
+                    dy: (event.scrollingDeltaY() * scale) as f32,
+                    phase,
+                });
+            }
+        }
 
-                if changed & NSEventModifierFlags::Control.bits() != 0 {
-                    update(PlatformRequest::Input(Input::Key {
-                        // TODO: LeftAlt vs RightAlt,
-                        code: KeyCode::LeftControl,
-                        modifiers: KeyModifiers::from(current_flags),
-                        pressed,
-                        repeat: false,
-                    }));
+        #[unsafe(method(magnifyWithEvent:))]
+        fn magnify_with_event(&self, event: &NSEvent) {
+            let ivars = self.ivars();
+            if !ivars.config.touchpad_zoom_to_fullscreen {
+                return;
+            }
+            unsafe {
+                if event.phase().contains(NSEventPhase::Began) {
+                    ivars.magnification.set(Some(0.0));
                 }
+                let Some(magnification) = ivars.magnification.get() else {
+                    return;
+                };
+                let magnification = magnification + event.magnification();
+                ivars.magnification.set(Some(magnification));
 
-                if changed & NSEventModifierFlags::Option.bits() != 0 {
-                    update(PlatformRequest::Input(Input::Key {
-                        // TODO: LeftAlt vs RightAlt,
-                        code: KeyCode::LeftAlt,
-                        modifiers: KeyModifiers::from(current_flags),
-                        pressed,
-                        repeat: false,
-                    }));
+                let fullscreen = ivars.window.styleMask().contains(NSWindowStyleMask::FullScreen);
+                if (!fullscreen && magnification > FULLSCREEN_MAGNIFICATION)
+                    || (fullscreen && magnification < -FULLSCREEN_MAGNIFICATION)
+                {
+                    ivars.magnification.set(None);
+                    ivars.window.toggleFullScreen(None);
                 }
+            }
+        }
+
+        #[unsafe(method(smartMagnifyWithEvent:))]
+        fn smart_magnify_with_event(&self, _event: &NSEvent) {
+            if self.ivars().config.touchpad_zoom_to_fullscreen {
+                self.ivars().window.toggleFullScreen(None);
+            }
+        }
+
+        #[unsafe(method(mouseMoved:))]
+        fn mouse_moved(&self, event: &NSEvent) {
+            self.mouse_motion(event);
+        }
+
+        #[unsafe(method(mouseEntered:))]
+        fn mouse_entered(&self, _event: &NSEvent) {
+            self.ivars().cursor_inside.set(true);
+            self.sync_cursor();
+        }
+
+        #[unsafe(method(mouseExited:))]
+        fn mouse_exited(&self, _event: &NSEvent) {
+            self.ivars().cursor_inside.set(false);
+            self.sync_cursor();
+        }
+
+        // Sent instead of `mouseMoved:` while a button is held.
+        #[unsafe(method(mouseDragged:))]
+        fn mouse_dragged(&self, event: &NSEvent) {
+            self.mouse_motion(event);
+        }
+
+        #[unsafe(method(rightMouseDragged:))]
+        fn right_mouse_dragged(&self, event: &NSEvent) {
+            self.mouse_motion(event);
+        }
+
+        #[unsafe(method(otherMouseDragged:))]
+        fn other_mouse_dragged(&self, event: &NSEvent) {
+            self.mouse_motion(event);
+        }
+
+        #[unsafe(method(windowDidChangeScreen:))]
+        fn window_did_change_screen(&self, _notification: &NSNotification) {
+            self.begin_transition();
+            self.queue_input(Input::DisplayChanged);
+        }
+
+        #[unsafe(method(windowDidResize:))]
+        fn window_did_resize(&self, _notification: &NSNotification) {
+            let size = unsafe { self.convertSizeToBacking(self.bounds().size) };
+            self.queue_input(Input::WindowResized {
+                width: size.width.round() as usize,
+                height: size.height.round() as usize,
+            });
+        }
+
+        #[unsafe(method(windowDidBecomeKey:))]
+        fn window_did_become_key(&self, _notification: &NSNotification) {
+            // The cursor may have entered while another window was key.
+            let inside = unsafe {
+                let point = self.convertPoint_fromView(
+                    self.ivars().window.mouseLocationOutsideOfEventStream(),
+                    None,
+                );
+                self.mouse_inRect(point, self.bounds())
+            };
+            self.ivars().cursor_inside.set(inside);
+            self.sync_cursor();
+            self.queue_input(Input::WindowFocused { focused: true });
+        }
+
+        #[unsafe(method(windowDidResignKey:))]
+        fn window_did_resign_key(&self, _notification: &NSNotification) {
+            // Modifiers released in another app never reach `flagsChanged:`.
+            self.ivars().modifier_flags.set(NSEventModifierFlags(0));
+            self.sync_cursor();
+            self.queue_input(Input::WindowFocused { focused: false });
+        }
+
+        #[unsafe(method(controllerDidConnect:))]
+        fn controller_did_connect(&self, notification: &NSNotification) {
+            let Some(controller) = (unsafe { notification.object() }) else {
+                return;
+            };
+            // Controllers without the extended profile, such as remotes, are ignored.
+            if gc_element(&controller, objc2::sel!(extendedGamepad)).is_none() {
+                return;
+            }
+            let mut gamepads = self.ivars().gamepads.borrow_mut();
+            let Some(slot) = gamepads.iter().position(Option::is_none) else {
+                crate::log!("ERROR: ignoring a controller, {MAX_GAMEPADS} are connected");
+                return;
+            };
+            gamepads[slot] = Some(controller);
+            drop(gamepads);
+            self.queue_input(Input::GamepadConnected { slot });
+        }
+
+        #[unsafe(method(controllerDidDisconnect:))]
+        fn controller_did_disconnect(&self, notification: &NSNotification) {
+            let Some(controller) = (unsafe { notification.object() }) else {
+                return;
+            };
+            let mut gamepads = self.ivars().gamepads.borrow_mut();
+            let Some(slot) = gamepads
+                .iter()
+                .position(|pad| pad.as_deref().is_some_and(|pad| core::ptr::eq(pad, &*controller)))
+            else {
+                return;
+            };
+            gamepads[slot] = None;
+            drop(gamepads);
+            self.queue_input(Input::GamepadDisconnected { slot });
+        }
 
-                if changed & NSEventModifierFlags::Command.bits() != 0 {
-                    // TODO: need command
+        #[unsafe(method(activeSpaceDidChange:))]
+        fn active_space_did_change(&self, _notification: &NSNotification) {
+            self.begin_transition();
+            self.queue_input(Input::WindowMoved);
+        }
+
+        #[unsafe(method(flagsChanged:))]
+        fn flags_changed(&self, event: &NSEvent) {
+            unsafe {
+                let current_flags = event.modifierFlags();
+                let changed =
+                    current_flags.bits() ^ self.ivars().modifier_flags.replace(current_flags).bits();
+
+                // The device flags keep a key held on the other side from hiding this
+                // one's release.
+                let code = KEY_CODE_LUT[event.keyCode() as usize];
+                let device_mask = match code {
+                    KeyCode::LeftShift => NX_DEVICELSHIFTKEYMASK,
+                    KeyCode::RightShift => NX_DEVICERSHIFTKEYMASK,
+                    KeyCode::LeftControl => NX_DEVICELCTLKEYMASK,
+                    KeyCode::RightControl => NX_DEVICERCTLKEYMASK,
+                    KeyCode::LeftAlt => NX_DEVICELALTKEYMASK,
+                    KeyCode::RightAlt => NX_DEVICERALTKEYMASK,
+                    KeyCode::LeftCommand => NX_DEVICELCMDKEYMASK,
+                    KeyCode::RightCommand => NX_DEVICERCMDKEYMASK,
+                    // Caps lock and fn.
+                    _ => return,
+                };
+                if changed & device_mask == 0 {
+                    return;
                 }
+                self.queue_input(Input::Key {
+                    code,
+                    modifiers: KeyModifiers::from(current_flags),
+                    pressed: current_flags.bits() & device_mask != 0,
+                    repeat: false,
+                });
             }
         }
     }
@@ -482,211 +1884,1882 @@ impl GameView {
         window: Retained<NSWindow>,
         update: impl FnMut(PlatformRequest) + 'static,
         frame_buffer: *mut u8,
+        audio: Option<&'static AudioOutput>,
+        view: ViewConfig,
+        config: Config,
     ) -> Retained<Self> {
+        let mut post_stages = vec![
+            (post_stage::COLOR_FILTER, PostStage::ColorFilter),
+            (post_stage::PRESENT_FILTER, PostStage::PresentFilter),
+            (
+                post_stage::PERFORMANCE_OVERLAY,
+                PostStage::PerformanceOverlay,
+            ),
+        ];
+        post_stages.extend(
+            view.post_stages
+                .into_iter()
+                .map(|(priority, stage)| (priority, PostStage::Game(stage))),
+        );
+        // Stable, so equal priorities keep the built-in stages first.
+        post_stages.sort_by_key(|(priority, _)| *priority);
+
         let ivars = GameViewIvars {
             fb: frame_buffer,
             update: RefCell::new(Box::new(update)),
-            last_time: RefCell::new(Instant::now()),
-            window,
+            clock: RefCell::new(FrameClock::new(Instant::now())),
+            window: window.clone(),
+            audio,
+            in_transition: Cell::new(false),
+            suppress_mouse_delta: Cell::new(false),
+            clamped_transition_deltas: Cell::new(0),
+            paused: Cell::new(false),
+            last_input_time: Cell::new(0.0),
+            #[cfg(feature = "std")]
+            audio_capture: RefCell::new(None),
+            cursor_confined: Cell::new(false),
+            cursor_edges: Cell::new(CursorEdges::NONE),
+            frame_index: Cell::new(0),
+            present_interval: Cell::new(0.0),
+            audio_underruns: Cell::new(0),
+            idle_mouse_travel: Cell::new(0.0),
+            config,
+            latency_probe_event: Cell::new(None),
+            latency_probe_frame: Cell::new(None),
+            latency: Cell::new(LatencyStats::default()),
+            title: RefCell::new(view.title),
+            commands: RefCell::new(CommandQueue::new()),
+            cursor_hidden: Cell::new(false),
+            cursor_inside: Cell::new(false),
+            cursor: Cell::new(Cursor::Arrow),
+            custom_cursor: RefCell::new(None),
+            cursor_grab: Cell::new(None),
+            cursor_hide_applied: Cell::new(false),
+            cursor_detached: Cell::new(false),
+            ui_layer: RefCell::new(Vec::new()),
+            ui_layer_size: Cell::new((0, 0)),
+            front_buffer: RefCell::new(match config.first_present {
+                FirstPresent::Splash(color) => {
+                    vec![u32::from_ne_bytes(color); unsafe { WIDTH * HEIGHT }]
+                }
+                _ => vec![0; unsafe { WIDTH * HEIGHT }],
+            }),
+            front_buffer_size: Cell::new(unsafe { (WIDTH, HEIGHT) }),
+            render_pipeline: RefCell::new(view.render_pipeline),
+            marked_text: RefCell::new(String::new()),
+            gl_context: config.opengl.then(|| init_opengl(&config)).flatten(),
+            gl_context_attached: Cell::new(false),
+            ime_area: Cell::new(NSRect::ZERO),
+            drag_areas: view.drag_areas,
+            color_filters: view.color_filters,
+            color_filter: Cell::new(None),
+            pending_opacity: Cell::new(None),
+            pending_content_protection: Cell::new(None),
+            magnification: Cell::new(None),
+            performance_overlay: RefCell::new(
+                config.performance_overlay.map(PerformanceOverlay::new),
+            ),
+            post_stages: RefCell::new(post_stages),
+            present_filter: Cell::new(config.present_filter),
+            present_filter_input: RefCell::new(Vec::new()),
+            first_present_pending: Cell::new(true),
+            first_present_held: Cell::new(false),
+            pending_input: InputQueue::default(),
+            modifier_flags: Cell::new(NSEventModifierFlags(0)),
+            gamepads: RefCell::new([const { None }; MAX_GAMEPADS]),
         };
         let this = Self::alloc(mtm).set_ivars(ivars);
-        unsafe { msg_send![super(this), init] }
+        let this: Retained<Self> = unsafe { msg_send![super(this), init] };
+
+        unsafe {
+            this.registerForDraggedTypes(&NSArray::from_slice(&[NSFilenamesPboardType]));
+
+            // Follows the view as it resizes.
+            let tracking_area = NSTrackingArea::initWithRect_options_owner_userInfo(
+                NSTrackingArea::alloc(),
+                NSRect::ZERO,
+                NSTrackingAreaOptions::MouseEnteredAndExited
+                    | NSTrackingAreaOptions::ActiveAlways
+                    | NSTrackingAreaOptions::InVisibleRect,
+                Some(&this),
+                None,
+            );
+            this.addTrackingArea(&tracking_area);
+
+            NSNotificationCenter::defaultCenter().addObserver_selector_name_object(
+                &this,
+                objc2::sel!(windowDidChangeScreen:),
+                Some(NSWindowDidChangeScreenNotification),
+                Some(&window),
+            );
+            for (selector, name) in [
+                (objc2::sel!(windowDidResize:), NSWindowDidResizeNotification),
+                (
+                    objc2::sel!(windowDidBecomeKey:),
+                    NSWindowDidBecomeKeyNotification,
+                ),
+                (
+                    objc2::sel!(windowDidResignKey:),
+                    NSWindowDidResignKeyNotification,
+                ),
+            ] {
+                NSNotificationCenter::defaultCenter().addObserver_selector_name_object(
+                    &this,
+                    selector,
+                    Some(name),
+                    Some(&window),
+                );
+            }
+            // Controllers connected at launch are also announced.
+            for (selector, name) in [
+                (
+                    objc2::sel!(controllerDidConnect:),
+                    GCControllerDidConnectNotification,
+                ),
+                (
+                    objc2::sel!(controllerDidDisconnect:),
+                    GCControllerDidDisconnectNotification,
+                ),
+            ] {
+                NSNotificationCenter::defaultCenter().addObserver_selector_name_object(
+                    &this,
+                    selector,
+                    Some(name),
+                    None,
+                );
+            }
+            NSWorkspace::sharedWorkspace()
+                .notificationCenter()
+                .addObserver_selector_name_object(
+                    &this,
+                    objc2::sel!(activeSpaceDidChange:),
+                    Some(NSWorkspaceActiveSpaceDidChangeNotification),
+                    None,
+                );
+        }
+
+        this
+    }
+
+    /// Queues the media key press or release in `event`, returning false for other system
+    /// defined events.
+    fn queue_media_key(&self, event: &NSEvent) -> bool {
+        let data = unsafe { event.data1() };
+        let code = match (data >> 16) & 0xffff {
+            NX_KEYTYPE_PLAY => KeyCode::MediaPlayPause,
+            NX_KEYTYPE_NEXT | NX_KEYTYPE_FAST => KeyCode::MediaNext,
+            NX_KEYTYPE_PREVIOUS | NX_KEYTYPE_REWIND => KeyCode::MediaPrevious,
+            NX_KEYTYPE_SOUND_UP => KeyCode::VolumeUp,
+            NX_KEYTYPE_SOUND_DOWN => KeyCode::VolumeDown,
+            NX_KEYTYPE_MUTE => KeyCode::Mute,
+            _ => return false,
+        };
+        // The low 16 bits hold the key state in the high byte and a repeat flag.
+        let flags = data & 0xffff;
+        self.queue_input(Input::Key {
+            code,
+            modifiers: KeyModifiers::from(unsafe { event.modifierFlags() }),
+            pressed: (flags >> 8) & 0xff == NX_KEYDOWN,
+            repeat: flags & 1 != 0,
+        });
+        true
+    }
+
+    /// Queues `input` for delivery at the start of the next update.
+    ///
+    /// Inputs received before an update are always delivered before that update, in the
+    /// order they arrived, regardless of where in the run loop the event fired.
+    fn queue_input(&self, input: Input) {
+        self.ivars().pending_input.push(input);
+    }
+
+    #[cfg(feature = "std")]
+    fn start_audio_capture(&self, path: &str) -> bool {
+        let Some(audio) = self.ivars().audio else {
+            crate::log!("ERROR: can not capture audio without an audio device");
+            return false;
+        };
+        let _: () = unsafe { msg_send![self, stopAudioCapture] };
+        let config = audio.config();
+        match AudioCapture::start(path, config.channels, config.format.sample_rate as u32) {
+            Ok(capture) => {
+                crate::log!("capturing audio to `{path}`");
+                *self.ivars().audio_capture.borrow_mut() = Some(capture);
+                true
+            }
+            Err(err) => {
+                crate::log!("ERROR: failed to start audio capture `{path}`: {err}");
+                false
+            }
+        }
+    }
+
+    fn mouse_button(&self, event: &NSEvent, pressed: bool) {
+        let button = match unsafe { event.buttonNumber() } {
+            0 => MouseButton::Left,
+            1 => MouseButton::Right,
+            2 => MouseButton::Middle,
+            3 => MouseButton::Back,
+            4 => MouseButton::Forward,
+            number => MouseButton::Other(number.clamp(0, u8::MAX as isize) as u8),
+        };
+        let (x, y) = self.frame_position(unsafe { event.locationInWindow() });
+        self.queue_input(Input::MouseButton {
+            button,
+            modifiers: KeyModifiers::from(unsafe { event.modifierFlags() }),
+            pressed,
+            x,
+            y,
+            clicks: unsafe { event.clickCount() }.max(0) as u32,
+        });
+    }
+
+    /// A point in window coordinates, in frame buffer pixels from the top left.
+    fn frame_position(&self, location_in_window: NSPoint) -> (f32, f32) {
+        let point = self.convertPoint_fromView(location_in_window, None);
+        let bounds = self.bounds().size;
+        if bounds.width <= 0.0 || bounds.height <= 0.0 {
+            return (0.0, 0.0);
+        }
+        let (width, height) = unsafe { (WIDTH, HEIGHT) };
+        (
+            (point.x * width as f64 / bounds.width) as f32,
+            ((bounds.height - point.y) * height as f64 / bounds.height) as f32,
+        )
+    }
+
+    fn mouse_motion(&self, event: &NSEvent) {
+        // The first delta after a Space transition spans the whole transition.
+        if self.ivars().suppress_mouse_delta.take() {
+            return;
+        }
+        let (dx, dy) = match self.ivars().config.mouse_input_mode {
+            MouseInputMode::Accelerated => unsafe { (event.deltaX(), event.deltaY()) },
+            MouseInputMode::Raw => unsafe {
+                let cg_event: *const c_void = msg_send![event, CGEvent];
+                (
+                    CGEventGetDoubleValueField(cg_event, CG_EVENT_UNACCELERATED_POINTER_MOVEMENT_X),
+                    CGEventGetDoubleValueField(cg_event, CG_EVENT_UNACCELERATED_POINTER_MOVEMENT_Y),
+                )
+            },
+        };
+        self.queue_input(Input::MouseMoved {
+            dx: dx as f32,
+            dy: dy as f32,
+        });
+        // A grabbed cursor does not move.
+        if self.ivars().cursor_confined.get()
+            && self.ivars().cursor_grab.get().is_none()
+            && self.ivars().window.isKeyWindow()
+        {
+            self.confine_cursor(event);
+        }
+    }
+
+    /// Warps the cursor back inside the view if `event` moved it out, and reports the
+    /// edges it is pressed against.
+    fn confine_cursor(&self, event: &NSEvent) {
+        let point = self.convertPoint_fromView(unsafe { event.locationInWindow() }, None);
+        let size = self.bounds().size;
+        let (max_x, max_y) = ((size.width - 1.0).max(0.0), (size.height - 1.0).max(0.0));
+        let clamped = NSPoint::new(point.x.clamp(0.0, max_x), point.y.clamp(0.0, max_y));
+
+        let mut edges = CursorEdges::NONE;
+        if clamped.x <= 0.0 {
+            edges = edges | CursorEdges::LEFT;
+        }
+        if clamped.x >= max_x {
+            edges = edges | CursorEdges::RIGHT;
+        }
+        // Views have a bottom left origin.
+        if clamped.y >= max_y {
+            edges = edges | CursorEdges::TOP;
+        }
+        if clamped.y <= 0.0 {
+            edges = edges | CursorEdges::BOTTOM;
+        }
+        if self.ivars().cursor_edges.replace(edges) != edges {
+            self.queue_input(Input::CursorAtEdge { edges });
+        }
+
+        if clamped.x != point.x || clamped.y != point.y {
+            warp_cursor(self.mtm(), self.view_to_screen(clamped));
+        }
+    }
+
+    fn view_to_screen(&self, point: NSPoint) -> NSPoint {
+        unsafe {
+            let window_point = self.convertPoint_toView(point, None);
+            self.ivars().window.convertPointToScreen(window_point)
+        }
+    }
+
+    fn set_cursor_grab(&self, grab: bool) {
+        let ivars = self.ivars();
+        match (grab, ivars.cursor_grab.get()) {
+            (true, None) => {
+                ivars
+                    .cursor_grab
+                    .set(Some(unsafe { NSEvent::mouseLocation() }));
+                // Clicks while grabbed land in the window.
+                let bounds = self.bounds();
+                let center = NSPoint::new(bounds.size.width / 2.0, bounds.size.height / 2.0);
+                warp_cursor(self.mtm(), self.view_to_screen(center));
+                self.sync_cursor();
+            }
+            (false, Some(position)) => {
+                ivars.cursor_grab.set(None);
+                self.sync_cursor();
+                warp_cursor(self.mtm(), position);
+            }
+            _ => {}
+        }
+    }
+
+    /// Applies the requested cursor visibility and grab while the window is focused, and
+    /// lifts both while it is not, so the cursor is never left hidden or stuck for other
+    /// apps.
+    fn sync_cursor(&self) {
+        let ivars = self.ivars();
+        let focused = ivars.window.isKeyWindow();
+        let grabbed = ivars.cursor_grab.get().is_some();
+        let hidden = ivars.cursor_hidden.get() || ivars.cursor.get() == Cursor::Hidden;
+        let hide = focused && (grabbed || (hidden && ivars.cursor_inside.get()));
+        if hide != ivars.cursor_hide_applied.replace(hide) {
+            unsafe {
+                if hide {
+                    NSCursor::hide();
+                } else {
+                    NSCursor::unhide();
+                }
+            }
+        }
+        let detach = focused && grabbed;
+        if detach != ivars.cursor_detached.replace(detach) {
+            unsafe { CGAssociateMouseAndMouseCursorPosition(u32::from(!detach)) };
+        }
+    }
+
+    fn screenshot(&self, stage: ScreenshotStage) -> Option<Screenshot> {
+        let ivars = self.ivars();
+        if ivars.first_present_pending.get() || ivars.gl_context.is_some() {
+            return None;
+        }
+        let (width, height) = ivars.front_buffer_size.get();
+        if width == 0 || height == 0 {
+            return None;
+        }
+        let unfiltered = ivars.present_filter_input.borrow();
+        let pixels = match stage {
+            ScreenshotStage::BeforePresentFilter if !unfiltered.is_empty() => unfiltered.clone(),
+            _ => pixel_bytes(&ivars.front_buffer.borrow()).to_vec(),
+        };
+        Some(Screenshot {
+            pixels,
+            width,
+            height,
+        })
+    }
+
+    fn cycle_color_filter(&self) {
+        let ivars = self.ivars();
+        let next = match ivars.color_filter.get() {
+            None => Some(0),
+            Some(index) => Some(index + 1).filter(|next| *next < ivars.color_filters.len()),
+        };
+        ivars.color_filter.set(next);
+        let name = next.map_or("none", |index| ivars.color_filters[index].0.name());
+        crate::log!("color filter: {name}");
+    }
+
+    /// Space and display transitions stall the update timer and warp the cursor, so the
+    /// next delta is clamped and the next mouse delta dropped.
+    fn begin_transition(&self) {
+        self.ivars().in_transition.set(true);
+        self.ivars().suppress_mouse_delta.set(true);
+    }
+}
+
+const CHANNELS: usize = 2;
+const AUDIO_SAMPLES_LEN: usize = 1024 * 4;
+
+// Seconds for the output to fade between silent and full volume.
+const AUDIO_FADE_SECONDS: f32 = 0.1;
+
+#[derive(Debug, Clone, Copy)]
+struct AudioConfig {
+    format: AudioFormat,
+    channels: usize,
+    // output device, the default output when `None`
+    device: Option<u32>,
+    thread_priority: AudioThreadPriority,
+}
+
+impl Default for AudioConfig {
+    fn default() -> Self {
+        Self {
+            format: AudioFormat::default(),
+            channels: CHANNELS,
+            device: None,
+            thread_priority: AudioThreadPriority::Default,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AudioError {
+    ComponentNotFound,
+    InstanceNew(i32),
+    SetProperty { property: u32, status: i32 },
+    Initialize(i32),
+}
+
+impl core::fmt::Display for AudioError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::ComponentNotFound => write!(f, "no default audio output component"),
+            Self::InstanceNew(status) => {
+                write!(f, "failed to create audio unit (OSStatus {status})")
+            }
+            Self::SetProperty { property, status } => {
+                write!(
+                    f,
+                    "failed to set audio unit property {property} (OSStatus {status})"
+                )
+            }
+            Self::Initialize(status) => {
+                write!(f, "failed to initialize audio unit (OSStatus {status})")
+            }
+        }
+    }
+}
+
+/// Owns the output audio unit and the ring buffer shared with its render callback.
+///
+/// The game writes into `game_samples` on the main thread, which are then copied into
+/// `samples` for the render callback to drain on the audio thread.
+#[derive(Debug)]
+struct AudioStream {
+    unit: AudioComponentInstance,
+    config: AudioConfig,
+    samples: UnsafeCell<[i16; AUDIO_SAMPLES_LEN]>,
+    // secondary buffer for the game to write to
+    game_samples: UnsafeCell<[i16; AUDIO_SAMPLES_LEN]>,
+    // write index is packed into top 32 bits, read index in bottom 32 bits
+    indices: AtomicU64,
+    thread_priority_set: AtomicBool,
+    // the output fades towards silence while set
+    faded_out: AtomicBool,
+    // current output volume, from 0 to 1
+    gain: UnsafeCell<f32>,
+}
+
+// `samples` is only written by the main thread in the region between the write and read
+// indices, and only read by the audio thread in the region between the read and write
+// indices. `game_samples` is only ever touched by the main thread, and `gain` only by the
+// audio thread.
+unsafe impl Sync for AudioStream {}
+
+impl AudioStream {
+    fn new(config: AudioConfig) -> Result<Box<Self>, AudioError> {
+        let mut stream = Box::new(Self {
+            unit: null_mut(),
+            config,
+            samples: UnsafeCell::new([0; AUDIO_SAMPLES_LEN]),
+            game_samples: UnsafeCell::new([0; AUDIO_SAMPLES_LEN]),
+            indices: AtomicU64::new((config.channels as u64) << 32),
+            thread_priority_set: AtomicBool::new(false),
+            faded_out: AtomicBool::new(false),
+            gain: UnsafeCell::new(1.0),
+        });
+
+        let desc = AudioComponentDescription {
+            componentType: kAudioUnitType_Output,
+            componentSubType: match config.device {
+                Some(_) => kAudioUnitSubType_HALOutput,
+                None => kAudioUnitSubType_DefaultOutput,
+            },
+            componentManufacturer: kAudioUnitManufacturer_Apple,
+            componentFlags: 0,
+            componentFlagsMask: 0,
+        };
+
+        let (format_flags, bytes_per_sample) = match config.format.sample_format {
+            SampleFormat::I16 => (
+                kLinearPCMFormatFlagIsSignedInteger | kLinearPCMFormatFlagIsPacked,
+                core::mem::size_of::<i16>(),
+            ),
+            SampleFormat::F32 => (
+                kLinearPCMFormatFlagIsFloat | kLinearPCMFormatFlagIsPacked,
+                core::mem::size_of::<f32>(),
+            ),
+        };
+        let bytes_per_frame = (config.channels * bytes_per_sample) as u32;
+        let stream_desc = AudioStreamBasicDescription {
+            mSampleRate: config.format.sample_rate as f64,
+            mFormatID: kAudioFormatLinearPCM,
+            mFormatFlags: format_flags,
+            mBytesPerPacket: bytes_per_frame,
+            mFramesPerPacket: 1,
+            mBytesPerFrame: bytes_per_frame,
+            mChannelsPerFrame: config.channels as u32,
+            mBitsPerChannel: bytes_per_sample as u32 * 8,
+            mReserved: 0,
+        };
+        // The stream is boxed, so this pointer stays valid for as long as the unit lives.
+        let callback = AURenderCallbackStruct {
+            inputProc: Some(audio_callback),
+            inputProcRefCon: &*stream as *const Self as *mut c_void,
+        };
+
+        unsafe {
+            let component = AudioComponentFindNext(null_mut(), NonNull::from(&desc));
+            if component.is_null() {
+                return Err(AudioError::ComponentNotFound);
+            }
+            let result = AudioComponentInstanceNew(component, NonNull::from(&mut stream.unit));
+            if result != 0 {
+                return Err(AudioError::InstanceNew(result));
+            }
+            if let Some(device) = config.device {
+                stream.set_property(
+                    kAudioOutputUnitProperty_CurrentDevice,
+                    kAudioUnitScope_Global,
+                    &device,
+                )?;
+            }
+            stream.set_property(
+                kAudioUnitProperty_StreamFormat,
+                kAudioUnitScope_Input,
+                &stream_desc,
+            )?;
+            stream.set_property(
+                kAudioUnitProperty_SetRenderCallback,
+                kAudioUnitScope_Input,
+                &callback,
+            )?;
+            let result = AudioUnitInitialize(stream.unit);
+            if result != 0 {
+                return Err(AudioError::Initialize(result));
+            }
+        }
+
+        Ok(stream)
+    }
+
+    fn set_property<T>(&self, property: u32, scope: u32, value: &T) -> Result<(), AudioError> {
+        let status = unsafe {
+            AudioUnitSetProperty(
+                self.unit,
+                property,
+                scope,
+                // element 0
+                0,
+                value as *const _ as *const c_void,
+                std::mem::size_of::<T>() as u32,
+            )
+        };
+        if status != 0 {
+            return Err(AudioError::SetProperty { property, status });
+        }
+        Ok(())
+    }
+
+    fn start(&self) {
+        unsafe {
+            let result = AudioOutputUnitStart(self.unit);
+            debug_assert_eq!(result, 0);
+        }
+    }
+
+    fn stop(&self) {
+        unsafe {
+            let result = AudioOutputUnitStop(self.unit);
+            debug_assert_eq!(result, 0);
+        }
+    }
+
+    /// Returns the ring buffer write index and how many samples can be written at it.
+    fn writable_samples(&self) -> (usize, usize) {
+        let channels = self.config.channels;
+        let indices = self.indices.load(Ordering::Acquire);
+        let write_index = (indices >> 32) as usize;
+        debug_assert_eq!(write_index % channels, 0);
+        let wrapped_read_index = (indices & u32::MAX as u64) as usize;
+        debug_assert_eq!(wrapped_read_index % channels, 0);
+
+        let samples_to_write = if write_index >= wrapped_read_index {
+            (wrapped_read_index + AUDIO_SAMPLES_LEN - write_index - channels) % AUDIO_SAMPLES_LEN
+        } else {
+            wrapped_read_index - write_index - channels
+        };
+        (write_index, samples_to_write)
+    }
+
+    /// The buffer handed to the game for this frame's samples.
+    ///
+    /// # Safety
+    ///
+    /// Must only be called from the main thread, and the returned slice must be dropped
+    /// before calling [`AudioStream::commit`] or this function again.
+    #[allow(clippy::mut_from_ref)]
+    unsafe fn game_samples(&self, len: usize) -> &mut [i16] {
+        unsafe { &mut (&mut *self.game_samples.get())[..len] }
+    }
+
+    /// Copies `len` samples written by the game into the ring buffer at `write_index`.
+    fn commit(&self, write_index: usize, len: usize) {
+        unsafe {
+            let game_samples = &*self.game_samples.get();
+            // Write through a raw pointer, the audio thread may be reading the other half.
+            let samples = self.samples.get().cast::<i16>();
+            let mut index = write_index;
+            for sample in game_samples[..len].iter() {
+                samples.add(index).write(*sample);
+                index = (index + 1) % AUDIO_SAMPLES_LEN;
+            }
+        }
+
+        self.indices
+            .fetch_update(Ordering::Release, Ordering::Acquire, |current_indices| {
+                let current_read_index = current_indices & u32::MAX as u64;
+                let new_write_index = ((write_index + len) % AUDIO_SAMPLES_LEN) as u64;
+                Some((new_write_index << 32) | current_read_index)
+            })
+            .unwrap();
+    }
+
+    /// Drains up to `frames` frames from the ring buffer into the device buffer `data`,
+    /// converting each sample with `convert`.
+    ///
+    /// # Safety
+    ///
+    /// Must only be called from the render callback, `data` must point to `byte_size`
+    /// bytes of `T`.
+    unsafe fn render<T: Copy + Default>(
+        &self,
+        data: *mut T,
+        byte_size: u32,
+        frames: usize,
+        convert: impl Fn(i16) -> T,
+    ) {
+        let channels = self.config.channels;
+        unsafe {
+            let len = byte_size as usize / core::mem::size_of::<T>();
+            let data = core::slice::from_raw_parts_mut(data, len);
+            debug_assert!(len > 0);
+
+            let indices = self.indices.load(Ordering::Acquire);
+            let wrapped_write_index = (indices >> 32) as usize;
+            debug_assert_eq!(wrapped_write_index % channels, 0);
+            let read_index = (indices & u32::MAX as u64) as usize;
+            debug_assert_eq!(read_index % channels, 0);
+
+            let available_samples = if wrapped_write_index >= read_index {
+                wrapped_write_index - read_index
+            } else {
+                wrapped_write_index + AUDIO_SAMPLES_LEN - read_index
+            };
+
+            let samples_needed = frames * channels;
+            let samples_to_read = available_samples.min(samples_needed);
+
+            let frames_to_read = samples_to_read / channels;
+            let ring = self.samples.get().cast::<i16>();
+            let gain = &mut *self.gain.get();
+            let target_gain = if self.faded_out.load(Ordering::Relaxed) {
+                0.0
+            } else {
+                1.0
+            };
+            let fade_step = 1.0 / (AUDIO_FADE_SECONDS * self.config.format.sample_rate);
+            let mut index = read_index;
+            for frame in data.chunks_mut(channels).take(frames_to_read) {
+                if *gain < target_gain {
+                    *gain = (*gain + fade_step).min(target_gain);
+                } else if *gain > target_gain {
+                    *gain = (*gain - fade_step).max(target_gain);
+                }
+                for (channel, sample) in frame.iter_mut().enumerate() {
+                    let value = ring.add(index + channel).read();
+                    *sample = convert((value as f32 * *gain) as i16);
+                }
+                index = (index + channels) % AUDIO_SAMPLES_LEN;
+            }
+
+            if frames_to_read < frames {
+                // crate::log!("ERROR: audio underrun {} samples", frames - frames_to_read);
+                AUDIO_UNDERRUNS.fetch_add(1, Ordering::Relaxed);
+                data[frames_to_read * channels..frames * channels].fill(T::default());
+            }
+
+            self.indices
+                .fetch_update(Ordering::Release, Ordering::Acquire, |current_indices| {
+                    let current_write_index = current_indices >> 32;
+                    let new_read_index = (read_index + samples_to_read) % AUDIO_SAMPLES_LEN;
+                    Some((current_write_index << 32) | new_read_index as u64)
+                })
+                .unwrap();
+        }
+    }
+}
+
+/// Applies `priority` to the calling thread.
+fn set_thread_priority(priority: AudioThreadPriority) {
+    let AudioThreadPriority::RealTime {
+        period_ns,
+        computation_ns,
+        constraint_ns,
+    } = priority
+    else {
+        return;
+    };
+
+    unsafe {
+        // Time constraints are expressed in mach absolute time units.
+        let timebase = mach_timebase();
+        let to_abs = |ns: u64| (ns * timebase.denom as u64 / timebase.numer as u64) as u32;
+
+        let mut policy = libc::thread_time_constraint_policy {
+            period: to_abs(period_ns),
+            computation: to_abs(computation_ns),
+            constraint: to_abs(constraint_ns),
+            preemptible: 1,
+        };
+        let result = libc::thread_policy_set(
+            libc::pthread_mach_thread_np(libc::pthread_self()),
+            libc::THREAD_TIME_CONSTRAINT_POLICY as u32,
+            &mut policy as *mut _ as libc::thread_policy_t,
+            libc::THREAD_TIME_CONSTRAINT_POLICY_COUNT,
+        );
+        if result != 0 {
+            crate::log!("ERROR: failed to set audio thread time constraint policy: {result}");
+        }
+    }
+}
+
+impl Drop for AudioStream {
+    fn drop(&mut self) {
+        // `new` may fail before the unit is created.
+        if self.unit.is_null() {
+            return;
+        }
+        unsafe {
+            AudioOutputUnitStop(self.unit);
+            AudioComponentInstanceDispose(self.unit);
+        }
+    }
+}
+
+/// The game facing audio output, backed by one [`AudioStream`] per output device.
+///
+/// Without routes the game writes straight into the default output's stream. Otherwise
+/// the game writes into `routed_samples`, which are split across the device streams.
+#[derive(Debug)]
+struct AudioOutput {
+    streams: Vec<Box<AudioStream>>,
+    // index into `streams` of each route's device
+    routes: Vec<(usize, AudioRoute)>,
+    channels: usize,
+    routed_samples: UnsafeCell<Vec<i16>>,
+}
+
+// `routed_samples` is only ever touched by the main thread.
+unsafe impl Sync for AudioOutput {}
+
+impl AudioOutput {
+    fn new(config: &Config, routes: &[AudioRoute]) -> Result<Self, AudioError> {
+        let new_stream = |device, channels| {
+            let audio_config = |format| AudioConfig {
+                format,
+                channels,
+                device,
+                thread_priority: config.audio_thread_priority,
+            };
+            let stream = AudioStream::new(audio_config(config.audio_format));
+            match (stream, config.audio_fallback_format) {
+                (Err(err), Some(fallback)) => {
+                    crate::log!(
+                        "failed to initialize audio with {:?}: {err}, falling back to {:?}",
+                        config.audio_format,
+                        fallback
+                    );
+                    AudioStream::new(audio_config(fallback))
+                }
+                (stream, _) => stream,
+            }
+        };
+
+        if routes.is_empty() {
+            return Ok(Self {
+                streams: vec![new_stream(None, CHANNELS)?],
+                routes: Vec::new(),
+                channels: CHANNELS,
+                routed_samples: UnsafeCell::new(Vec::new()),
+            });
+        }
+
+        let mut devices = Vec::new();
+        let routes: Vec<_> = routes
+            .iter()
+            .map(|route| {
+                let index = match devices.iter().position(|d| *d == route.output_device_id) {
+                    Some(index) => index,
+                    None => {
+                        devices.push(route.output_device_id);
+                        devices.len() - 1
+                    }
+                };
+                (index, route.clone())
+            })
+            .collect();
+
+        let mut streams = Vec::with_capacity(devices.len());
+        for (index, device) in devices.iter().enumerate() {
+            let channels = routes
+                .iter()
+                .filter(|(i, _)| *i == index)
+                .map(|(_, route)| route.output_channels.end)
+                .max()
+                .unwrap_or(1);
+            // Frames must evenly divide the ring buffer.
+            streams.push(new_stream(Some(*device), channels.next_power_of_two())?);
+        }
+
+        let channels = routes
+            .iter()
+            .map(|(_, route)| route.source_channels.end)
+            .max()
+            .unwrap_or(1)
+            .max(1);
+        Ok(Self {
+            streams,
+            routes,
+            channels,
+            routed_samples: UnsafeCell::new(vec![0; AUDIO_SAMPLES_LEN * channels]),
+        })
+    }
+
+    fn start(&self) {
+        for stream in self.streams.iter() {
+            stream.start();
+        }
+    }
+
+    fn stop(&self) {
+        for stream in self.streams.iter() {
+            stream.stop();
+        }
+    }
+
+    /// Fades the queued samples out, or back in, over `AUDIO_FADE_SECONDS`.
+    fn set_faded_out(&self, faded_out: bool) {
+        for stream in self.streams.iter() {
+            stream.faded_out.store(faded_out, Ordering::Relaxed);
+        }
+    }
+
+    /// The format and channels of the samples written by the game.
+    fn config(&self) -> AudioConfig {
+        AudioConfig {
+            channels: self.channels,
+            ..self.streams[0].config
+        }
+    }
+
+    /// How many frames can be written to every stream.
+    fn writable_frames(&self) -> usize {
+        self.streams
+            .iter()
+            .map(|stream| stream.writable_samples().1 / stream.config.channels)
+            .min()
+            .unwrap_or(0)
+    }
+
+    /// How full the fullest ring buffer is, from `0.0` to `1.0`.
+    fn fill_level(&self) -> f32 {
+        self.streams
+            .iter()
+            .map(|stream| {
+                let (_, writable) = stream.writable_samples();
+                let capacity = AUDIO_SAMPLES_LEN - stream.config.channels;
+                1.0 - writable as f32 / capacity as f32
+            })
+            .fold(0.0, f32::max)
+    }
+
+    /// The buffer handed to the game for this frame's samples.
+    ///
+    /// # Safety
+    ///
+    /// Must only be called from the main thread, and the returned slice must be dropped
+    /// before calling [`AudioOutput::commit`] or this function again.
+    #[allow(clippy::mut_from_ref)]
+    unsafe fn game_samples(&self, frames: usize) -> &mut [i16] {
+        let len = frames * self.channels;
+        if self.routes.is_empty() {
+            unsafe { self.streams[0].game_samples(len) }
+        } else {
+            unsafe { &mut (&mut *self.routed_samples.get())[..len] }
+        }
+    }
+
+    /// Copies `frames` frames written by the game into each stream's ring buffer.
+    fn commit(&self, frames: usize) {
+        if self.routes.is_empty() {
+            let (write_index, _) = self.streams[0].writable_samples();
+            self.streams[0].commit(write_index, frames * self.channels);
+            return;
+        }
+
+        let routed_samples = unsafe { &(&*self.routed_samples.get())[..frames * self.channels] };
+        for (index, stream) in self.streams.iter().enumerate() {
+            let channels = stream.config.channels;
+            let len = frames * channels;
+            let (write_index, _) = stream.writable_samples();
+            let samples = unsafe { stream.game_samples(len) };
+            samples.fill(0);
+            for (_, route) in self.routes.iter().filter(|(i, _)| *i == index) {
+                let source_frames = routed_samples.chunks_exact(self.channels);
+                for (source, frame) in source_frames.zip(samples.chunks_exact_mut(channels)) {
+                    let pairs = route
+                        .source_channels
+                        .clone()
+                        .zip(route.output_channels.clone());
+                    for (source_channel, output_channel) in pairs {
+                        frame[output_channel] = source[source_channel];
+                    }
+                }
+            }
+            stream.commit(write_index, len);
+        }
+    }
+}
+
+// Whether `code` triggers a platform shortcut and is kept from the game.
+fn is_consumed_shortcut(config: &Config, code: KeyCode) -> bool {
+    !config.deliver_shortcut_keys && config.color_filter_key == Some(code)
+}
+
+// Whether a key down goes to the input method first. Command shortcuts are never text.
+fn uses_input_method(config: &Config, modifiers: NSEventModifierFlags) -> bool {
+    config.input_method_editor && !modifiers.contains(NSEventModifierFlags::Command)
+}
+
+// Whether `window` overlaps any of `screens`, so a window restored from the state file is
+// not placed on a display which has since been disconnected.
+fn is_on_screen(window: NSRect, screens: &[NSRect]) -> bool {
+    let (origin, size) = (window.origin, window.size);
+    if !(origin.x.is_finite() && origin.y.is_finite()) {
+        return false;
+    }
+    screens.iter().any(|screen| {
+        origin.x < screen.origin.x + screen.size.width
+            && screen.origin.x < origin.x + size.width
+            && origin.y < screen.origin.y + screen.size.height
+            && screen.origin.y < origin.y + size.height
+    })
+}
+
+fn init_app(
+    update: impl FnMut(PlatformRequest) + 'static,
+    frame_buffer: *mut u8,
+    width: usize,
+    height: usize,
+    view: ViewConfig,
+    config: Config,
+    audio: Option<&'static AudioOutput>,
+) -> (Retained<NSApplication>, Retained<Delegate>) {
+    unsafe {
+        WIDTH = width;
+        HEIGHT = height;
+    }
+
+    let mtm = MainThreadMarker::new().unwrap();
+    let app = NSApplication::sharedApplication(mtm);
+
+    let mut style_mask =
+        NSWindowStyleMask::Titled | NSWindowStyleMask::Closable | NSWindowStyleMask::Miniaturizable;
+    if config.resizable_window {
+        style_mask |= NSWindowStyleMask::Resizable;
+    }
+    if config.transparent_titlebar {
+        style_mask |= NSWindowStyleMask::FullSizeContentView;
+    }
+    let window = unsafe {
+        NSWindow::initWithContentRect_styleMask_backing_defer(
+            NSWindow::alloc(mtm),
+            NSRect::new(
+                NSPoint::new(0.0, 0.0),
+                NSSize::new(width as f64, height as f64),
+            ),
+            style_mask,
+            NSBackingStoreType::Buffered,
+            false,
+        )
+    };
+    if config.transparent_titlebar {
+        window.setTitlebarAppearsTransparent(true);
+    }
+    if config.hide_title {
+        window.setTitleVisibility(NSWindowTitleVisibility::Hidden);
+    }
+    window.setHasShadow(config.window_shadow);
+    unsafe {
+        window.setReleasedWhenClosed(false);
+        if !config.window_animations {
+            window.setAnimationBehavior(NSWindowAnimationBehavior::None);
+        }
+        if config.touchpad_zoom_to_fullscreen {
+            window.setCollectionBehavior(NSWindowCollectionBehavior::FullScreenPrimary);
+        }
+    }
+
+    window.setTitle(&NSString::from_str(&view.title));
+    let screens: Vec<NSRect> = NSScreen::screens(mtm)
+        .to_vec()
+        .into_iter()
+        .map(|screen| screen.frame())
+        .collect();
+    let window_size = window.frame().size;
+    match config.window_origin {
+        Some((x, y)) if is_on_screen(NSRect::new(NSPoint::new(x, y), window_size), &screens) => unsafe {
+            window.setFrameOrigin(NSPoint::new(x, y))
+        },
+        Some((x, y)) => {
+            crate::log!("ERROR: restored window origin ({x}, {y}) is off screen, centering");
+            window.center();
+        }
+        None => window.center(),
+    }
+    if config.first_present != FirstPresent::Hidden {
+        window.makeKeyAndOrderFront(None);
+    }
+    window.setAcceptsMouseMovedEvents(true);
+
+    let custom_view = GameView::new(
+        mtm,
+        window.clone(),
+        update,
+        frame_buffer,
+        audio,
+        view,
+        config,
+    );
+    window.makeFirstResponder(Some(&custom_view));
+    let delegate = Delegate::new(
+        mtm,
+        window.clone(),
+        &custom_view,
+        audio,
+        config.latency_mode,
+    );
+    if config.services_menu {
+        unsafe { app.setServicesProvider(Some(&custom_view)) };
+    }
+    window.setContentView(Some(&*custom_view.into_super()));
+    window.setDelegate(Some(ProtocolObject::from_ref(&*delegate)));
+    app.setDelegate(Some(ProtocolObject::from_ref(&*delegate)));
+    if config.print_support || config.services_menu {
+        init_menu(mtm, &app, config);
+    }
+    app.setActivationPolicy(NSApplicationActivationPolicy::Regular);
+    // Activate the application.
+    // Required when launching unbundled (as is done with Cargo).
+    #[expect(deprecated)]
+    app.activateIgnoringOtherApps(true);
+    (app, delegate)
+}
+
+static mut WIDTH: usize = 0;
+static mut HEIGHT: usize = 0;
+
+fn init_menu(mtm: MainThreadMarker, app: &NSApplication, config: Config) {
+    let main_menu = NSMenu::new(mtm);
+
+    // The first item's submenu always becomes the application menu.
+    let app_menu = NSMenu::new(mtm);
+    if config.services_menu {
+        let services_menu =
+            unsafe { NSMenu::initWithTitle(NSMenu::alloc(mtm), ns_string!("Services")) };
+        let services_menu_item = NSMenuItem::new(mtm);
+        unsafe { services_menu_item.setTitle(ns_string!("Services")) };
+        services_menu_item.setSubmenu(Some(&services_menu));
+        app_menu.addItem(&services_menu_item);
+        app_menu.addItem(&NSMenuItem::separatorItem(mtm));
+        unsafe { app.setServicesMenu(Some(&services_menu)) };
+    }
+    unsafe {
+        app_menu.addItemWithTitle_action_keyEquivalent(
+            ns_string!("Quit"),
+            Some(objc2::sel!(quit:)),
+            ns_string!("q"),
+        );
+    }
+    let app_menu_item = NSMenuItem::new(mtm);
+    app_menu_item.setSubmenu(Some(&app_menu));
+    main_menu.addItem(&app_menu_item);
+
+    if config.print_support {
+        let file_menu = unsafe { NSMenu::initWithTitle(NSMenu::alloc(mtm), ns_string!("File")) };
+        // No target, the action travels up the responder chain to `GameView`.
+        unsafe {
+            file_menu.addItemWithTitle_action_keyEquivalent(
+                ns_string!("Print..."),
+                Some(objc2::sel!(printFramebuffer:)),
+                ns_string!("p"),
+            );
+        }
+        let file_menu_item = NSMenuItem::new(mtm);
+        file_menu_item.setSubmenu(Some(&file_menu));
+        main_menu.addItem(&file_menu_item);
+    }
+
+    app.setMainMenu(Some(&main_menu));
+}
+
+// Scale applied to the window size when asking the print handler for pixels.
+const PRINT_SCALE: usize = 4;
+
+fn print_framebuffer(view: &GameView, ivars: &GameViewIvars) {
+    let (width, height) = unsafe { (WIDTH, HEIGHT) };
+    if width == 0 || height == 0 {
+        crate::log!("ERROR: nothing to print in a {width}x{height} frame");
+        return;
+    }
+
+    let mut pixels = None;
+    {
+        let mut update = ivars.update.borrow_mut();
+        update(PlatformRequest::Print(PrintRequest {
+            width: width * PRINT_SCALE,
+            height: height * PRINT_SCALE,
+            pixels: &mut pixels,
+        }));
+    }
+
+    let (pixels, pixels_width, pixels_height) = match pixels {
+        Some(pixels) => (pixels, width * PRINT_SCALE, height * PRINT_SCALE),
+        None => {
+            let fb = unsafe { core::slice::from_raw_parts(ivars.fb, width * height * 4) };
+            (fb.to_vec(), width, height)
+        }
+    };
+    if pixels.len() != pixels_width * pixels_height * 4 {
+        crate::log!(
+            "ERROR: print handler returned {} bytes, expected {}",
+            pixels.len(),
+            pixels_width * pixels_height * 4
+        );
+        return;
+    }
+
+    let size = NSSize::new(width as f64, height as f64);
+    let Some(image) = image_from_rgba(&pixels, pixels_width, pixels_height, size) else {
+        crate::log!("ERROR: failed to create print image");
+        return;
+    };
+
+    unsafe {
+        let image_view = NSImageView::imageViewWithImage(&image, view.mtm());
+        image_view.setFrame(NSRect::new(NSPoint::new(0.0, 0.0), size));
+        let operation = NSPrintOperation::printOperationWithView(&image_view);
+        operation.setShowsPrintPanel(true);
+        operation.runOperation();
+    }
+}
+
+/// Draws `width * height` RGBA `pixels`, an image of `size` points, into `rect` of the
+/// current graphics context without copying them.
+///
+/// # Safety
+///
+/// `pixels` must point to `width * height * 4` bytes.
+unsafe fn draw_rgba(
+    pixels: *mut u8,
+    width: usize,
+    height: usize,
+    format: NSBitmapFormat,
+    size: NSSize,
+    rect: NSRect,
+) {
+    unsafe {
+        let planes: [*const u8; 1] = [pixels];
+        let image_rep = NSBitmapImageRep::initWithBitmapDataPlanes_pixelsWide_pixelsHigh_bitsPerSample_samplesPerPixel_hasAlpha_isPlanar_colorSpaceName_bitmapFormat_bytesPerRow_bitsPerPixel(
+            NSBitmapImageRep::alloc(),
+            planes.as_ptr() as *mut _,
+            width as isize,
+            height as isize,
+            8,
+            4,
+            true,
+            false,
+            &NSColorSpaceName::from_str("NSCalibratedRGBColorSpace"),
+            format,
+            width as isize * 4,
+            32,
+        );
+        if let Some(image_rep) = image_rep {
+            let image = NSImage::initWithSize(NSImage::alloc(), size);
+            image.addRepresentation(&image_rep);
+            image.drawInRect(rect);
+        }
+    }
+}
+
+#[link(name = "CoreGraphics", kind = "framework")]
+unsafe extern "C" {
+    fn CGWarpMouseCursorPosition(position: NSPoint) -> i32;
+    fn CGAssociateMouseAndMouseCursorPosition(connected: u32) -> i32;
+    fn CGEventGetDoubleValueField(event: *const c_void, field: u32) -> f64;
+}
+
+// `kCGEventUnacceleratedPointerMovementX` and `Y`, the `CGEventField`s holding a mouse
+// event's motion before pointer acceleration.
+const CG_EVENT_UNACCELERATED_POINTER_MOVEMENT_X: u32 = 170;
+const CG_EVENT_UNACCELERATED_POINTER_MOVEMENT_Y: u32 = 171;
+
+#[link(name = "GameController", kind = "framework")]
+unsafe extern "C" {
+    static GCControllerDidConnectNotification: &'static NSString;
+    static GCControllerDidDisconnectNotification: &'static NSString;
+}
+
+/// Reads an element of a `GameController` profile, `None` if the controller lacks it.
+///
+/// The framework is only messaged dynamically, `objc2` has no bindings for it.
+fn gc_element(object: &AnyObject, element: Sel) -> Option<Retained<AnyObject>> {
+    unsafe { msg_send![object, performSelector: element] }
+}
+
+fn read_gamepads(ivars: &GameViewIvars) -> Gamepads {
+    let mut gamepads = Gamepads::default();
+    for (slot, controller) in ivars.gamepads.borrow().iter().enumerate() {
+        gamepads.slots[slot] = controller.as_deref().and_then(read_gamepad);
+    }
+    gamepads
+}
+
+fn read_gamepad(controller: &AnyObject) -> Option<Gamepad> {
+    use objc2::sel;
+
+    let profile = gc_element(controller, sel!(extendedGamepad))?;
+    let value = |element: Option<Retained<AnyObject>>| -> f32 {
+        element.map_or(0.0, |element| unsafe { msg_send![&*element, value] })
+    };
+    let stick = |stick: Sel| {
+        let stick = gc_element(&profile, stick);
+        let axis = |axis: Sel| value(stick.as_deref().and_then(|stick| gc_element(stick, axis)));
+        (axis(sel!(xAxis)), axis(sel!(yAxis)))
+    };
+    let mut gamepad = Gamepad::default();
+    gamepad.left_stick = stick(sel!(leftThumbstick));
+    gamepad.right_stick = stick(sel!(rightThumbstick));
+    gamepad.left_trigger = value(gc_element(&profile, sel!(leftTrigger)));
+    gamepad.right_trigger = value(gc_element(&profile, sel!(rightTrigger)));
+    let dpad = gc_element(&profile, sel!(dpad));
+    for button in GamepadButton::ALL {
+        let (parent, element) = match button {
+            GamepadButton::South => (&profile, sel!(buttonA)),
+            GamepadButton::East => (&profile, sel!(buttonB)),
+            GamepadButton::West => (&profile, sel!(buttonX)),
+            GamepadButton::North => (&profile, sel!(buttonY)),
+            GamepadButton::LeftShoulder => (&profile, sel!(leftShoulder)),
+            GamepadButton::RightShoulder => (&profile, sel!(rightShoulder)),
+            GamepadButton::LeftStick => (&profile, sel!(leftThumbstickButton)),
+            GamepadButton::RightStick => (&profile, sel!(rightThumbstickButton)),
+            GamepadButton::Start => (&profile, sel!(buttonMenu)),
+            GamepadButton::Select => (&profile, sel!(buttonOptions)),
+            GamepadButton::Home => (&profile, sel!(buttonHome)),
+            _ => {
+                let Some(dpad) = &dpad else {
+                    continue;
+                };
+                match button {
+                    GamepadButton::DPadUp => (dpad, sel!(up)),
+                    GamepadButton::DPadDown => (dpad, sel!(down)),
+                    GamepadButton::DPadLeft => (dpad, sel!(left)),
+                    _ => (dpad, sel!(right)),
+                }
+            }
+        };
+        if let Some(element) = gc_element(parent, element) {
+            let pressed: bool = unsafe { msg_send![&*element, isPressed] };
+            gamepad.set_pressed(button, pressed);
+        }
+    }
+    Some(gamepad)
+}
+
+/// Moves the cursor to `point` in screen coordinates, without the move showing up as a
+/// mouse delta.
+fn warp_cursor(mtm: MainThreadMarker, point: NSPoint) {
+    // Screen coordinates have a bottom left origin on the primary display, CoreGraphics
+    // has a top left origin.
+    let Some(primary) = NSScreen::screens(mtm).to_vec().into_iter().next() else {
+        return;
+    };
+    let position = NSPoint::new(point.x, primary.frame().size.height - point.y);
+    unsafe {
+        CGAssociateMouseAndMouseCursorPosition(0);
+        CGWarpMouseCursorPosition(position);
+        // Reconnecting also ends the pause in mouse events which follows a warp.
+        CGAssociateMouseAndMouseCursorPosition(1);
+    }
+}
+
+/// Copies `pixels` into a new image which is drawn at `size` points.
+fn image_from_rgba(
+    pixels: &[u8],
+    width: usize,
+    height: usize,
+    size: NSSize,
+) -> Option<Retained<NSImage>> {
+    unsafe {
+        // Null planes make the image rep allocate and own its pixel storage.
+        let image_rep = NSBitmapImageRep::initWithBitmapDataPlanes_pixelsWide_pixelsHigh_bitsPerSample_samplesPerPixel_hasAlpha_isPlanar_colorSpaceName_bytesPerRow_bitsPerPixel(
+            NSBitmapImageRep::alloc(),
+            null_mut(),
+            width as isize,
+            height as isize,
+            8,
+            4,
+            true,
+            false,
+            &NSColorSpaceName::from_str("NSCalibratedRGBColorSpace"),
+            width as isize * 4,
+            32,
+        )?;
+        core::ptr::copy_nonoverlapping(pixels.as_ptr(), image_rep.bitmapData(), pixels.len());
+        let image = NSImage::initWithSize(NSImage::alloc(), size);
+        image.addRepresentation(&image_rep);
+        Some(image)
+    }
+}
+
+/// The file paths on a drag's pasteboard, empty if it is not dragging files.
+fn dropped_paths(sender: &ProtocolObject<dyn NSDraggingInfo>) -> Vec<String> {
+    let pasteboard = unsafe { sender.draggingPasteboard() };
+    let Some(paths) = pasteboard
+        .propertyListForType(unsafe { NSFilenamesPboardType })
+        .and_then(|list| list.downcast::<NSArray>().ok())
+    else {
+        return Vec::new();
+    };
+    (0..paths.count())
+        .filter_map(|index| {
+            let path = paths.objectAtIndex(index);
+            path.downcast_ref::<NSString>().map(NSString::to_string)
+        })
+        .collect()
+}
+
+/// The text of an `NSString` or `NSAttributedString` from the input method.
+fn input_text(string: &AnyObject) -> String {
+    if let Some(string) = string.downcast_ref::<NSAttributedString>() {
+        string.string().to_string()
+    } else if let Some(string) = string.downcast_ref::<NSString>() {
+        string.to_string()
+    } else {
+        String::new()
+    }
+}
+
+fn utf16_to_byte_offset(text: &str, utf16_offset: usize) -> usize {
+    let mut utf16_len = 0;
+    for (byte_offset, c) in text.char_indices() {
+        if utf16_len >= utf16_offset {
+            return byte_offset;
+        }
+        utf16_len += c.len_utf16();
+    }
+    text.len()
+}
+
+// Side length in points of the marker drawn on frames measured by the latency probe.
+const LATENCY_MARKER_SIZE: f64 = 16.0;
+
+// Largest delta reported for the frame following a Space or display transition.
+const MAX_TRANSITION_DELTA: f64 = 1.0 / 30.0;
+
+// Frame deltas, and the time accumulated from them, see `PlatformUpdate::time`.
+struct FrameClock {
+    last_tick: Instant,
+    time: f64,
+}
+
+impl FrameClock {
+    fn new(now: Instant) -> Self {
+        Self {
+            last_tick: now,
+            time: 0.0,
+        }
+    }
+
+    fn since_last_tick(&self, now: Instant) -> f64 {
+        now.duration_since(self.last_tick).as_secs_f64()
+    }
+
+    // Seconds since the last tick, which is moved to `now`. Each delta starts where the
+    // last ended, so none of the elapsed time is lost between frames.
+    fn tick(&mut self, now: Instant) -> f64 {
+        let delta = self.since_last_tick(now);
+        self.last_tick = now;
+        delta
+    }
+
+    // Adds a frame's `delta`, which may have been clamped or skipped while paused,
+    // returning the new time.
+    fn accumulate(&mut self, delta: f64) -> f64 {
+        self.time += delta;
+        self.time
+    }
+}
+
+// Seconds the first present can be held before the window is shown anyway.
+const FIRST_PRESENT_TIMEOUT: f64 = 5.0;
+
+// Points per line of a scroll wheel without precise deltas.
+const SCROLL_LINE_POINTS: f64 = 16.0;
+
+// Pinch needed in one gesture to toggle full screen, smaller pinches are ignored.
+const FULLSCREEN_MAGNIFICATION: f64 = 0.5;
+
+/// Whether this frame should be presented, showing the window on the first present if it
+/// was hidden.
+fn present_frame(ivars: &GameViewIvars, time: f64) -> bool {
+    if !ivars.first_present_pending.get() {
+        return true;
+    }
+    if ivars.first_present_held.get() {
+        if time < FIRST_PRESENT_TIMEOUT {
+            return false;
+        }
+        crate::log!("ERROR: first present held for over {FIRST_PRESENT_TIMEOUT}s, presenting");
+    }
+    ivars.first_present_pending.set(false);
+    if ivars.config.first_present == FirstPresent::Hidden {
+        ivars.window.makeKeyAndOrderFront(None);
     }
+    true
 }
 
-static mut AUDIO_UNIT: AudioComponentInstance = null_mut();
-const SAMPLE_RATE: f32 = 44_100.0;
-const CHANNELS: usize = 2;
+// Inputs received since the last update, in arrival order, see `GameView::queue_input`.
+#[derive(Default)]
+struct InputQueue {
+    inputs: RefCell<VecDeque<Input>>,
+}
 
-fn start_audio() {
-    unsafe {
-        let result = AudioOutputUnitStart(AUDIO_UNIT);
-        debug_assert_eq!(result, 0);
+impl InputQueue {
+    fn push(&self, input: Input) {
+        self.inputs.borrow_mut().push_back(input);
     }
-}
 
-fn stop_audio() {
-    unsafe {
-        let result = AudioOutputUnitStop(AUDIO_UNIT);
-        debug_assert_eq!(result, 0);
+    // The queue is not left borrowed, so handling the input may queue more behind it.
+    fn pop(&self) -> Option<Input> {
+        self.inputs.borrow_mut().pop_front()
     }
 }
 
-fn init_audio() {
-    use core::ptr::{NonNull, null_mut};
+/// Shortens a mouse delta to the distance `left` this frame, returning false if nothing
+/// is left of it.
+fn clamp_mouse_delta(dx: &mut f32, dy: &mut f32, left: &mut f32) -> bool {
+    let length = dx.hypot(*dy);
+    if length > *left {
+        if *left <= 0.0 {
+            return false;
+        }
+        let scale = *left / length;
+        *dx *= scale;
+        *dy *= scale;
+        *left = 0.0;
+    } else {
+        *left -= length;
+    }
+    true
+}
 
-    let mut unit = core::ptr::null_mut();
-    let desc = AudioComponentDescription {
-        componentType: kAudioUnitType_Output,
-        componentSubType: kAudioUnitSubType_DefaultOutput,
-        componentManufacturer: kAudioUnitManufacturer_Apple,
-        componentFlags: 0,
-        componentFlagsMask: 0,
+/// Whether `input` counts as activity, mouse motion only does once it leaves the dead zone.
+fn resets_idle(ivars: &GameViewIvars, input: &Input) -> bool {
+    let Input::MouseMoved { dx, dy } = input else {
+        return true;
     };
+    let travel = ivars.idle_mouse_travel.get() + dx.hypot(*dy);
+    if travel > ivars.config.idle_mouse_dead_zone {
+        ivars.idle_mouse_travel.set(0.0);
+        true
+    } else {
+        ivars.idle_mouse_travel.set(travel);
+        false
+    }
+}
 
-    let stream_desc = AudioStreamBasicDescription {
-        mSampleRate: SAMPLE_RATE as f64,
-        mFormatID: kAudioFormatLinearPCM,
-        mFormatFlags: kLinearPCMFormatFlagIsSignedInteger,
-        mBytesPerPacket: 4,
-        mFramesPerPacket: 1,
-        mBytesPerFrame: 4,
-        mChannelsPerFrame: 2,
-        mBitsPerChannel: 16,
-        mReserved: 0,
+/// Interval between presents closest to `fps` that the window's display can keep, and the
+/// display's shortest refresh interval.
+fn present_interval(window: &NSWindow, fps: u32) -> (f64, f64) {
+    let preferred = 1.0 / fps as f64;
+    let Some(screen) = window.screen() else {
+        return (preferred, 0.0);
     };
-    let callback = AURenderCallbackStruct {
-        inputProc: Some(audio_callback),
-        inputProcRefCon: null_mut(),
-    };
-
-    unsafe {
-        let component = AudioComponentFindNext(null_mut(), NonNull::from(&desc));
-        debug_assert!(!component.is_null());
-        let result = AudioComponentInstanceNew(component, NonNull::from(&mut unit));
-        debug_assert_eq!(result, 0);
-        set_property(unit, kAudioUnitProperty_StreamFormat, &stream_desc);
-        set_property(unit, kAudioUnitProperty_SetRenderCallback, &callback);
-        let result = AudioUnitInitialize(unit);
-        debug_assert_eq!(result, 0);
-        AUDIO_UNIT = unit;
-
-        fn set_property<T>(unit: AudioComponentInstance, prop: u32, value: &T) {
-            unsafe {
-                let result = AudioUnitSetProperty(
-                    unit,
-                    prop,
-                    kAudioUnitScope_Input,
-                    kAudioUnitScope_Global,
-                    value as *const _ as *const c_void,
-                    std::mem::size_of::<T>() as u32,
-                );
-                debug_assert_eq!(result, 0);
-            }
-        }
+    let refresh = unsafe { screen.minimumRefreshInterval() };
+    if refresh <= 0.0 {
+        return (preferred, 0.0);
     }
+    let refreshes = (preferred / refresh).round().max(1.0);
+    (refresh * refreshes, refresh)
 }
 
-fn init_app(
-    update: impl FnMut(PlatformRequest) + 'static,
-    frame_buffer: *mut u8,
-    width: usize,
-    height: usize,
-) -> Retained<NSApplication> {
-    unsafe {
-        WIDTH = width;
-        HEIGHT = height;
+/// Sizes the UI layer to the window's backing pixels and clears it, returning its pixels
+/// and size.
+fn prepare_ui_layer(view: &GameView, ivars: &GameViewIvars) -> (*mut u8, usize, usize) {
+    let size = unsafe { view.convertSizeToBacking(view.bounds().size) };
+    let (width, height) = (size.width.round() as usize, size.height.round() as usize);
+    let mut ui_layer = ivars.ui_layer.borrow_mut();
+    if ivars.ui_layer_size.replace((width, height)) != (width, height) {
+        ui_layer.clear();
+        ui_layer.resize(width * height, 0);
+    } else if !ivars.config.preserve_ui_layer {
+        ui_layer.fill(0);
     }
+    if ui_layer.is_empty() {
+        return (null_mut(), 0, 0);
+    }
+    (ui_layer.as_mut_ptr().cast(), width, height)
+}
 
-    let mtm = MainThreadMarker::new().unwrap();
-    let app = NSApplication::sharedApplication(mtm);
+// The front buffer and UI layer are stored as `u32`s, so their bytes are aligned for
+// any 4 byte `Pixels` the game casts them to.
+fn pixel_bytes(pixels: &[u32]) -> &[u8] {
+    unsafe { core::slice::from_raw_parts(pixels.as_ptr().cast(), pixels.len() * 4) }
+}
 
-    let window = unsafe {
-        NSWindow::initWithContentRect_styleMask_backing_defer(
-            NSWindow::alloc(mtm),
-            NSRect::new(
-                NSPoint::new(0.0, 0.0),
-                NSSize::new(width as f64, height as f64),
-            ),
-            NSWindowStyleMask::Titled
-                | NSWindowStyleMask::Closable
-                | NSWindowStyleMask::Miniaturizable,
-            // | NSWindowStyleMask::Resizable,
-            NSBackingStoreType::Buffered,
-            false,
-        )
-    };
-    unsafe {
-        window.setReleasedWhenClosed(false);
-    }
+fn pixel_bytes_mut(pixels: &mut [u32]) -> &mut [u8] {
+    unsafe { core::slice::from_raw_parts_mut(pixels.as_mut_ptr().cast(), pixels.len() * 4) }
+}
 
-    window.setTitle(ns_string!("glazer app"));
-    window.center();
-    window.makeKeyAndOrderFront(None);
-    window.setAcceptsMouseMovedEvents(true);
+// The largest whole number the `width * height` frame can be scaled up by and still fit
+// `view` in pixels, at least 1.
+fn output_scale(view: &GameView, width: usize, height: usize) -> usize {
+    let size = unsafe { view.convertSizeToBacking(view.bounds().size) };
+    let scale = (size.width / width as f64).min(size.height / height as f64);
+    (scale.floor() as usize).max(1)
+}
 
-    let custom_view = GameView::new(mtm, window.clone(), update, frame_buffer);
-    window.makeFirstResponder(Some(&custom_view));
-    let delegate = Delegate::new(mtm, window.clone(), &custom_view);
-    window.setContentView(Some(&*custom_view.into_super()));
-    app.setDelegate(Some(ProtocolObject::from_ref(&*delegate)));
-    app.setActivationPolicy(NSApplicationActivationPolicy::Regular);
-    // Activate the application.
-    // Required when launching unbundled (as is done with Cargo).
-    #[expect(deprecated)]
-    app.activateIgnoringOtherApps(true);
-    app
+/// The parts of `view` covered by window chrome, in pixels of the `width * height` frame
+/// drawn into it.
+fn safe_area_insets(view: &GameView, window: &NSWindow, width: usize, height: usize) -> Insets {
+    let bounds = view.bounds().size;
+    if bounds.width <= 0.0 || bounds.height <= 0.0 {
+        return Insets::default();
+    }
+    // The content view fills the window, so window coordinates are view coordinates.
+    let layout = unsafe { window.contentLayoutRect() };
+    let (scale_x, scale_y) = (width as f64 / bounds.width, height as f64 / bounds.height);
+    Insets {
+        top: ((bounds.height - (layout.origin.y + layout.size.height)) * scale_y) as f32,
+        left: (layout.origin.x * scale_x) as f32,
+        bottom: (layout.origin.y * scale_y) as f32,
+        right: ((bounds.width - (layout.origin.x + layout.size.width)) * scale_x) as f32,
+    }
 }
 
-static mut WIDTH: usize = 0;
-static mut HEIGHT: usize = 0;
+/// Applies the commands issued by the last update, in order.
+fn apply_commands(view: &GameView, ivars: &GameViewIvars) {
+    let mut commands = ivars.commands.borrow_mut();
+    let (commands, dropped) = commands.drain();
+    for command in commands {
+        record_event(format_args!("command {command:?}"));
+        match command {
+            Command::SetTitle(title) => *ivars.title.borrow_mut() = String::from(title.as_str()),
+            Command::Quit => stop_app(view.mtm()),
+            Command::SetCursorVisible(visible) => {
+                ivars.cursor_hidden.set(!visible);
+                view.sync_cursor();
+            }
+            Command::SetCursorGrab(grab) => view.set_cursor_grab(grab),
+            Command::SetCursor(cursor) => {
+                let had_custom = ivars.custom_cursor.take().is_some();
+                if cursor != ivars.cursor.replace(cursor) || had_custom {
+                    ivars.window.invalidateCursorRectsForView(view);
+                    view.sync_cursor();
+                }
+            }
+        }
+    }
+    if dropped > 0 {
+        crate::log!("ERROR: dropped {dropped} commands, more than {CAPACITY} were issued");
+    }
+}
 
 fn update(view: &GameView, ivars: &GameViewIvars) {
     let now = Instant::now();
-    let delta = {
-        let mut last_time = ivars.last_time.borrow_mut();
-        let delta = now.duration_since(*last_time).as_secs_f32();
-        *last_time = now;
+    if let Some(fps) = ivars.config.preferred_fps {
+        let (interval, refresh) = present_interval(&ivars.window, fps);
+        if interval != ivars.present_interval.replace(interval) {
+            crate::log!("presenting at {:.1}Hz", 1.0 / interval);
+        }
+        // Half a refresh of slack, so a tick landing just early is not pushed a whole
+        // refresh later.
+        let elapsed = ivars.clock.borrow().since_last_tick(now);
+        if elapsed < interval - refresh * 0.5 {
+            return;
+        }
+    }
+    let delta = ivars.clock.borrow_mut().tick(now);
+    let delta = if ivars.in_transition.take() && delta > MAX_TRANSITION_DELTA {
+        let clamped = ivars.clamped_transition_deltas.get() + 1;
+        ivars.clamped_transition_deltas.set(clamped);
+        crate::log!("clamped transition delta of {delta:.3}s ({clamped} total)");
+        MAX_TRANSITION_DELTA
+    } else {
         delta
     };
 
+    if ivars.config.pause_on_focus_loss {
+        let paused = !ivars.window.isKeyWindow();
+        if paused != ivars.paused.replace(paused) {
+            crate::log!("{}", if paused { "paused" } else { "resumed" });
+            if let Some(audio) = ivars.audio {
+                audio.set_faded_out(paused);
+            }
+        }
+        // Input stays queued, and time stands still, until the window is focused.
+        if paused {
+            return;
+        }
+    }
+
+    let time = ivars.clock.borrow_mut().accumulate(delta);
+
+    record_event(format_args!("frame, delta {delta:.4}s"));
+    let underruns = AUDIO_UNDERRUNS.load(Ordering::Relaxed);
+    if underruns != ivars.audio_underruns.replace(underruns) {
+        record_event(format_args!("audio underrun, {underruns} total"));
+    }
+
     let fps = if delta > 0.0 { 1.0 / delta } else { 0.0 };
-    let title = format!("glazer app - {:.2}", fps);
+    let title = format!("{} - {:.2}", ivars.title.borrow(), fps);
     ivars.window.setTitle(&*NSString::from_str(&title));
 
     let fb = ivars.fb;
-    let indices = AUDIO_SAMPLES_INDICES.load(Ordering::Acquire);
-    let write_index = (indices >> 32) as usize;
-    debug_assert_eq!(write_index % CHANNELS, 0);
-    let wrapped_read_index = (indices & u32::MAX as u64) as usize;
-    debug_assert_eq!(wrapped_read_index % CHANNELS, 0);
-
-    let samples_to_write = if write_index >= wrapped_read_index {
-        (wrapped_read_index + AUDIO_SAMPLES_LEN - write_index - CHANNELS) % AUDIO_SAMPLES_LEN
+    let audio = ivars.audio;
+    let frames_to_write = audio.map_or(0, |audio| audio.writable_frames());
+    let audio_config = audio.map_or(AudioConfig::default(), |audio| audio.config());
+
+    // The context can only be attached once the view is in a window.
+    if let Some((context, _)) = &ivars.gl_context
+        && !ivars.gl_context_attached.replace(true)
+    {
+        let _: () = unsafe { msg_send![context, setView: view] };
+    }
+
+    let (ui_frame_buffer, ui_width, ui_height) = if ivars.config.ui_layer {
+        prepare_ui_layer(view, ivars)
     } else {
-        wrapped_read_index - write_index - CHANNELS
+        (null_mut(), 0, 0)
     };
 
+    if let Some(opacity) = ivars.pending_opacity.take() {
+        unsafe { ivars.window.setAlphaValue(opacity) };
+    }
+    if let Some(enabled) = ivars.pending_content_protection.take() {
+        ivars.window.setSharingType(if enabled {
+            NSWindowSharingType::None
+        } else {
+            NSWindowSharingType::ReadOnly
+        });
+    }
+
     let mut update = ivars.update.borrow_mut();
+    let mut mouse_delta_left = ivars.config.max_mouse_delta.unwrap_or(f32::INFINITY);
+    while let Some(mut input) = ivars.pending_input.pop() {
+        if let Input::MouseMoved { dx, dy } = &mut input
+            && !clamp_mouse_delta(dx, dy, &mut mouse_delta_left)
+        {
+            continue;
+        }
+        record_event(format_args!("input {input:?}"));
+        if resets_idle(ivars, &input) {
+            ivars.last_input_time.set(time);
+        }
+        update(PlatformRequest::Input(input));
+    }
+    let seconds_since_last_input = (time - ivars.last_input_time.get()) as f32;
     unsafe {
+        let frame = ivars.frame_index.get();
+        ivars.frame_index.set(frame + 1);
+        let update_scope = crate::profiling::frame_scope(c"update_and_render", frame);
         update(PlatformRequest::Update(PlatformState {
             delta,
+            time,
+            seconds_since_last_input,
+            idle: ivars
+                .config
+                .idle_threshold
+                .is_some_and(|threshold| seconds_since_last_input >= threshold),
+            commands: &mut ivars.commands.borrow_mut(),
+            gamepads: &read_gamepads(ivars),
+            mouse_position: view.frame_position(ivars.window.mouseLocationOutsideOfEventStream()),
+            #[cfg(feature = "std")]
+            window_origin: {
+                let origin = ivars.window.frame().origin;
+                (origin.x, origin.y)
+            },
             //
             frame_buffer: fb,
             width: WIDTH,
             height: HEIGHT,
+            gl_context: ivars.gl_context.as_ref().map(|(_, context)| context),
+            safe_area_insets: safe_area_insets(view, &ivars.window, WIDTH, HEIGHT),
+            ui_frame_buffer,
+            ui_width,
+            ui_height,
             //
-            samples: &mut GAME_SAMPLES[..samples_to_write],
-            channels: CHANNELS,
-            sample_rate: SAMPLE_RATE,
+            samples: match audio {
+                Some(audio) => audio.game_samples(frames_to_write),
+                None => &mut [],
+            },
+            channels: audio_config.channels,
+            sample_rate: audio_config.format.sample_rate,
+            audio_format: audio_config.format,
+            //
+            latency: ivars.config.latency_probe.map(|_| ivars.latency.get()),
         }));
-        view.setNeedsDisplay(true);
+        drop(update_scope);
+        apply_commands(view, ivars);
+        // A zero sized frame has nothing to present, and can not be made into an image.
+        if WIDTH > 0 && HEIGHT > 0 && present_frame(ivars, time) {
+            let _present_scope = crate::profiling::frame_scope(c"present", frame);
+            let back_buffer = core::slice::from_raw_parts(fb, WIDTH * HEIGHT * 4);
+            let mut performance_overlay = ivars.performance_overlay.borrow_mut();
+            let audio_fill = audio
+                .filter(|_| performance_overlay.is_some())
+                .map(|audio| audio.fill_level());
+            let front_buffer_size = compose_frame(
+                back_buffer,
+                &mut ivars.front_buffer.borrow_mut(),
+                WIDTH,
+                HEIGHT,
+                output_scale(view, WIDTH, HEIGHT),
+                ivars.render_pipeline.borrow_mut().as_mut(),
+                &mut ivars.post_stages.borrow_mut(),
+                PostStageInputs {
+                    color_filter: ivars
+                        .color_filter
+                        .get()
+                        .map(|index| &ivars.color_filters[index].1),
+                    present_filter: ivars.present_filter.get(),
+                    present_filter_input: &mut ivars.present_filter_input.borrow_mut(),
+                    performance_overlay: performance_overlay.as_mut(),
+                    delta: delta as f32,
+                    audio_fill,
+                },
+            );
+            ivars.front_buffer_size.set(front_buffer_size);
+            view.setNeedsDisplay(true);
+        }
+    }
+    // The probe key has been handled, so the next drawn frame is its response.
+    if let Some(timestamp) = ivars.latency_probe_event.take() {
+        ivars.latency_probe_frame.set(Some(timestamp));
+    }
 
-        let mut index = write_index;
-        for sample in GAME_SAMPLES[..samples_to_write].iter() {
-            AUDIO_SAMPLES[index] = *sample;
-            index = (index + 1) % AUDIO_SAMPLES_LEN;
+    if let Some(audio) = audio {
+        #[cfg(feature = "std")]
+        if let Some(capture) = ivars.audio_capture.borrow_mut().as_mut() {
+            capture.write(unsafe { audio.game_samples(frames_to_write) });
         }
+        audio.commit(frames_to_write);
     }
+}
 
-    AUDIO_SAMPLES_INDICES
-        .fetch_update(Ordering::Release, Ordering::Acquire, |current_indices| {
-            let current_read_index = current_indices & u32::MAX as u64;
-            let new_write_index = ((write_index + samples_to_write) % AUDIO_SAMPLES_LEN) as u64;
-            Some((new_write_index << 32) | current_read_index)
-        })
-        .unwrap();
+// What the built in post stages draw with, see `compose_frame`.
+struct PostStageInputs<'a> {
+    color_filter: Option<&'a ColorMatrixPipeline>,
+    present_filter: PresentFilter,
+    // scratch copy of the frame for the present filter
+    present_filter_input: &'a mut Vec<u8>,
+    performance_overlay: Option<&'a mut PerformanceOverlay>,
+    delta: f32,
+    audio_fill: Option<f32>,
 }
 
-const AUDIO_SAMPLES_LEN: usize = 1024 * 4;
-static mut AUDIO_SAMPLES: [i16; AUDIO_SAMPLES_LEN] = [0; AUDIO_SAMPLES_LEN];
-// secondary buffer for the game to write to
-static mut GAME_SAMPLES: [i16; AUDIO_SAMPLES_LEN] = [0; AUDIO_SAMPLES_LEN];
-// write index is packed into top 32 bits, read index in bottom 32 bits
-static AUDIO_SAMPLES_INDICES: AtomicU64 = AtomicU64::new((2 << 32) | 0);
+// Fills `front_buffer` from the game's `width * height` RGBA `back_buffer` through the
+// render pipeline and the post stages, returning its size. A present filter scales the
+// frame up by `output_scale` first, and later stages run at that size. Touches no AppKit
+// state, so it runs without a window.
+#[allow(clippy::too_many_arguments)]
+fn compose_frame(
+    back_buffer: &[u8],
+    front_buffer: &mut Vec<u32>,
+    width: usize,
+    height: usize,
+    output_scale: usize,
+    render_pipeline: Option<&mut Box<dyn RenderPipeline>>,
+    post_stages: &mut [(i32, PostStage)],
+    inputs: PostStageInputs,
+) -> (usize, usize) {
+    // A zero sized frame has nothing to present.
+    if width == 0 || height == 0 {
+        return (width, height);
+    }
+    // Only reallocates when the size grows, a present filter keeps the capacity.
+    front_buffer.resize(width * height, 0);
+    match render_pipeline {
+        Some(pipeline) => {
+            pipeline.process(back_buffer, pixel_bytes_mut(front_buffer), width, height)
+        }
+        None => pixel_bytes_mut(front_buffer).copy_from_slice(back_buffer),
+    }
+    let PostStageInputs {
+        color_filter,
+        present_filter,
+        present_filter_input,
+        mut performance_overlay,
+        delta,
+        audio_fill,
+    } = inputs;
+    present_filter_input.clear();
+    let (mut width, mut height) = (width, height);
+    for (_, stage) in post_stages.iter_mut() {
+        match stage {
+            PostStage::ColorFilter => {
+                if let Some(filter) = color_filter {
+                    filter.apply(pixel_bytes_mut(front_buffer));
+                }
+            }
+            PostStage::PresentFilter => match present_filter {
+                PresentFilter::None => {}
+                PresentFilter::Crt(mut crt) => {
+                    let scale = output_scale.max(1);
+                    let (scaled_width, scaled_height) = (width * scale, height * scale);
+                    present_filter_input.resize(scaled_width * scaled_height * 4, 0);
+                    let front = pixel_bytes(front_buffer);
+                    upscale_nearest(front, present_filter_input, width, height, scale);
+                    (width, height) = (scaled_width, scaled_height);
+                    front_buffer.resize(width * height, 0);
+                    crt.process(
+                        present_filter_input,
+                        pixel_bytes_mut(front_buffer),
+                        width,
+                        height,
+                    );
+                }
+            },
+            PostStage::PerformanceOverlay => {
+                if let Some(overlay) = performance_overlay.as_mut() {
+                    overlay.record(delta);
+                    overlay.draw(pixel_bytes_mut(front_buffer), width, height, audio_fill);
+                }
+            }
+            PostStage::Game(stage) => stage(pixel_bytes_mut(front_buffer), width, height),
+        }
+    }
+    (width, height)
+}
 
 unsafe extern "C-unwind" fn audio_callback(
-    _ref_con: NonNull<c_void>,
+    ref_con: NonNull<c_void>,
     _action_flags: NonNull<AudioUnitRenderActionFlags>,
     _time_stamp: NonNull<AudioTimeStamp>,
     _bus: u32,
@@ -694,55 +3767,30 @@ unsafe extern "C-unwind" fn audio_callback(
     data: *mut AudioBufferList,
 ) -> i32 {
     let frames = frames as usize;
+    let _scope = crate::profiling::scope(c"audio_callback");
     unsafe {
-        let len = (*data).mNumberBuffers as usize;
-        debug_assert_eq!(len, 1);
-
-        let len = (*data).mBuffers[0].mDataByteSize as usize / 2;
-        let samples = (*data).mBuffers[0].mData as *mut i16;
-        let data = core::slice::from_raw_parts_mut(samples, len);
-        debug_assert!(len > 0);
-
-        let indices = AUDIO_SAMPLES_INDICES.load(Ordering::Acquire);
-        let wrapped_write_index = (indices >> 32) as usize;
-        debug_assert_eq!(wrapped_write_index % CHANNELS, 0);
-        let read_index = (indices & u32::MAX as u64) as usize;
-        debug_assert_eq!(read_index % CHANNELS, 0);
-
-        let available_samples = if wrapped_write_index >= read_index {
-            wrapped_write_index - read_index
-        } else {
-            wrapped_write_index + AUDIO_SAMPLES_LEN - read_index
-        };
+        let stream = ref_con.cast::<AudioStream>().as_ref();
 
-        let samples_needed = frames * CHANNELS;
-        let samples_to_read = available_samples.min(samples_needed);
-
-        let frames_to_read = samples_to_read / CHANNELS;
-        let mut index = read_index;
-        debug_assert_eq!(CHANNELS, 2);
-        for frame in data.chunks_mut(CHANNELS).take(frames_to_read) {
-            frame[0] = AUDIO_SAMPLES[index];
-            frame[1] = AUDIO_SAMPLES[index + 1];
-            index = (index + CHANNELS) % AUDIO_SAMPLES_LEN;
+        // The render thread is owned by CoreAudio, so the policy can only be applied once
+        // we are running on it.
+        if !stream.thread_priority_set.swap(true, Ordering::Relaxed) {
+            set_thread_priority(stream.config.thread_priority);
         }
 
-        if frames_to_read < frames {
-            // crate::log!("ERROR: audio underrun {} samples", frames - frames_to_read);
-            debug_assert_eq!(CHANNELS, 2);
-            for i in frames_to_read..frames {
-                data[i * CHANNELS] = 0;
-                data[i * CHANNELS + 1] = 0;
+        let len = (*data).mNumberBuffers as usize;
+        debug_assert_eq!(len, 1);
+
+        let buffer = (*data).mBuffers[0];
+        match stream.config.format.sample_format {
+            SampleFormat::I16 => {
+                stream.render(buffer.mData.cast(), buffer.mDataByteSize, frames, |s| s)
+            }
+            SampleFormat::F32 => {
+                stream.render(buffer.mData.cast(), buffer.mDataByteSize, frames, |s| {
+                    s as f32 / -(i16::MIN as f32)
+                })
             }
         }
-
-        AUDIO_SAMPLES_INDICES
-            .fetch_update(Ordering::Release, Ordering::Acquire, |current_indices| {
-                let current_write_index = current_indices >> 32;
-                let new_read_index = (read_index + samples_to_read) % AUDIO_SAMPLES_LEN;
-                Some((current_write_index << 32) | new_read_index as u64)
-            })
-            .unwrap();
     }
     0
 }
@@ -770,6 +3818,30 @@ impl From<NSEventModifierFlags> for KeyModifiers {
 }
 
 // https://gist.github.com/eegrok/949034
+// `NSEventSubtype` of the system defined events sent for media keys.
+const MEDIA_KEY_SUBTYPE: i16 = 8;
+// Media key types and the key down state from IOKit's `ev_keymap.h`.
+const NX_KEYTYPE_SOUND_UP: isize = 0;
+const NX_KEYTYPE_SOUND_DOWN: isize = 1;
+const NX_KEYTYPE_MUTE: isize = 7;
+const NX_KEYTYPE_PLAY: isize = 16;
+const NX_KEYTYPE_NEXT: isize = 17;
+const NX_KEYTYPE_PREVIOUS: isize = 18;
+const NX_KEYTYPE_FAST: isize = 19;
+const NX_KEYTYPE_REWIND: isize = 20;
+const NX_KEYDOWN: isize = 0xa;
+
+// Device dependent modifier flags from IOKit's `IOLLEvent.h`, the low bits of
+// `NSEvent::modifierFlags` which tell the left and right keys apart.
+const NX_DEVICELCTLKEYMASK: usize = 0x1;
+const NX_DEVICELSHIFTKEYMASK: usize = 0x2;
+const NX_DEVICERSHIFTKEYMASK: usize = 0x4;
+const NX_DEVICELCMDKEYMASK: usize = 0x8;
+const NX_DEVICERCMDKEYMASK: usize = 0x10;
+const NX_DEVICELALTKEYMASK: usize = 0x20;
+const NX_DEVICERALTKEYMASK: usize = 0x40;
+const NX_DEVICERCTLKEYMASK: usize = 0x2000;
+
 const KEY_CODE_LUT: [KeyCode; 128] = {
     let mut lut = [KeyCode::Unknown; 128];
     lut[0x00] = KeyCode::KeyA;
@@ -826,13 +3898,58 @@ const KEY_CODE_LUT: [KeyCode; 128] = {
     lut[0x33] = KeyCode::DeleteOrBackspace;
     lut[0x34] = KeyCode::Return;
     lut[0x35] = KeyCode::Escape;
+    lut[0x36] = KeyCode::RightCommand;
+    lut[0x37] = KeyCode::LeftCommand;
+    lut[0x38] = KeyCode::LeftShift;
+    lut[0x3A] = KeyCode::LeftAlt;
+    lut[0x3B] = KeyCode::LeftControl;
+    lut[0x3C] = KeyCode::RightShift;
+    lut[0x3D] = KeyCode::RightAlt;
+    lut[0x3E] = KeyCode::RightControl;
+    lut[0x40] = KeyCode::F17;
+    lut[0x41] = KeyCode::NumpadDecimal;
+    lut[0x43] = KeyCode::NumpadMultiply;
+    lut[0x45] = KeyCode::NumpadAdd;
+    lut[0x47] = KeyCode::NumpadClear;
+    lut[0x4B] = KeyCode::NumpadDivide;
+    lut[0x4C] = KeyCode::NumpadEnter;
+    lut[0x4E] = KeyCode::NumpadSubtract;
+    lut[0x4F] = KeyCode::F18;
+    lut[0x50] = KeyCode::F19;
+    lut[0x51] = KeyCode::NumpadEquals;
+    lut[0x52] = KeyCode::Numpad0;
+    lut[0x53] = KeyCode::Numpad1;
+    lut[0x54] = KeyCode::Numpad2;
+    lut[0x55] = KeyCode::Numpad3;
+    lut[0x56] = KeyCode::Numpad4;
+    lut[0x57] = KeyCode::Numpad5;
+    lut[0x58] = KeyCode::Numpad6;
+    lut[0x59] = KeyCode::Numpad7;
+    lut[0x5B] = KeyCode::Numpad8;
+    lut[0x5C] = KeyCode::Numpad9;
     lut[0x5F] = KeyCode::Separator;
+    lut[0x60] = KeyCode::F5;
+    lut[0x61] = KeyCode::F6;
+    lut[0x62] = KeyCode::F7;
+    lut[0x63] = KeyCode::F3;
+    lut[0x64] = KeyCode::F8;
+    lut[0x65] = KeyCode::F9;
+    lut[0x67] = KeyCode::F11;
+    lut[0x69] = KeyCode::F13;
+    lut[0x6A] = KeyCode::F16;
+    lut[0x6B] = KeyCode::F14;
+    lut[0x6D] = KeyCode::F10;
+    lut[0x6F] = KeyCode::F12;
+    lut[0x71] = KeyCode::F15;
     lut[0x72] = KeyCode::Insert;
     lut[0x73] = KeyCode::Home;
     lut[0x74] = KeyCode::PageUp;
     lut[0x75] = KeyCode::DeleteForward;
+    lut[0x76] = KeyCode::F4;
     lut[0x77] = KeyCode::End;
+    lut[0x78] = KeyCode::F2;
     lut[0x79] = KeyCode::PageDown;
+    lut[0x7A] = KeyCode::F1;
     lut[0x7B] = KeyCode::LeftArrow;
     lut[0x7C] = KeyCode::RightArrow;
     lut[0x7D] = KeyCode::DownArrow;
@@ -842,33 +3959,498 @@ const KEY_CODE_LUT: [KeyCode; 128] = {
 
 // Debug utilities
 
+// Recent events, see `glazer::dump_event_log`.
+static EVENT_LOG: Mutex<EventLog> = Mutex::new(EventLog::new());
+// Event times are seconds since the first event.
+static EVENT_LOG_START: OnceLock<Instant> = OnceLock::new();
+// Seconds of events written by a dump.
+const EVENT_LOG_SECONDS: f64 = 5.0;
+// Render callbacks which ran out of samples, counted on the audio thread and recorded in
+// the event log by the next update.
+static AUDIO_UNDERRUNS: AtomicU64 = AtomicU64::new(0);
+
+fn record_event(args: core::fmt::Arguments) {
+    let time = EVENT_LOG_START
+        .get_or_init(Instant::now)
+        .elapsed()
+        .as_secs_f64();
+    if let Ok(mut event_log) = EVENT_LOG.lock() {
+        event_log.record(time, args);
+    }
+}
+
+/// Writes the recent events to `path`, after `header` if there is one.
+type PanicHook = Box<dyn Fn(&std::panic::PanicHookInfo<'_>) + Sync + Send>;
+
+// Set while a `CrashLogHook` is installed, so a nested `run` does not chain another.
+static CRASH_LOG_HOOK_INSTALLED: AtomicBool = AtomicBool::new(false);
+
+// Writes the event log to `crash-<timestamp>.log` for panics on the thread running the
+// app, not on task threads, and puts the previous panic hook back when dropped.
+struct CrashLogHook {
+    // `None` if another hook was already installed
+    previous: Option<Arc<PanicHook>>,
+}
+
+impl CrashLogHook {
+    fn install() -> Self {
+        if CRASH_LOG_HOOK_INSTALLED.swap(true, Ordering::AcqRel) {
+            return Self { previous: None };
+        }
+        let previous = Arc::new(std::panic::take_hook());
+        let chained = previous.clone();
+        let app_thread = std::thread::current().id();
+        std::panic::set_hook(Box::new(move |info| {
+            chained(info);
+            if std::thread::current().id() != app_thread {
+                return;
+            }
+            let timestamp = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map_or(0, |since| since.as_secs());
+            let path = format!("crash-{timestamp}.log");
+            if write_event_log(&path, Some(info)) {
+                crate::log!("wrote the event log to `{path}`");
+            }
+        }));
+        Self {
+            previous: Some(previous),
+        }
+    }
+}
+
+impl Drop for CrashLogHook {
+    fn drop(&mut self) {
+        // The hooks can not be changed while panicking, ours then stays installed.
+        if std::thread::panicking() {
+            return;
+        }
+        let Some(previous) = self.previous.take() else {
+            return;
+        };
+        // Dropping our hook releases its share of the previous one.
+        drop(std::panic::take_hook());
+        match Arc::try_unwrap(previous) {
+            Ok(previous) => std::panic::set_hook(previous),
+            Err(previous) => std::panic::set_hook(Box::new(move |info| previous(info))),
+        }
+        CRASH_LOG_HOOK_INSTALLED.store(false, Ordering::Release);
+    }
+}
+
+fn write_event_log(path: &str, header: Option<&dyn core::fmt::Display>) -> bool {
+    use core::fmt::Write;
+
+    let mut text = String::new();
+    if let Some(header) = header {
+        let _ = writeln!(text, "{header}\n");
+    }
+    {
+        // Called from the panic hook, which may have interrupted a recording.
+        let event_log = match EVENT_LOG.try_lock() {
+            Ok(event_log) => event_log,
+            Err(TryLockError::Poisoned(err)) => err.into_inner(),
+            Err(TryLockError::WouldBlock) => return false,
+        };
+        let _ = event_log.write_recent(&mut text, EVENT_LOG_SECONDS);
+    }
+    match std::fs::write(path, text) {
+        Ok(()) => true,
+        Err(err) => {
+            crate::log!("ERROR: failed to write the event log to `{path}`: {err}");
+            false
+        }
+    }
+}
+
 #[inline]
 pub fn log(str: &str) {
+    if str.starts_with("ERROR") {
+        record_event(format_args!("{str}"));
+    }
     std::print!("{str}");
 }
 
-pub fn debug_time_secs<R>(mut f: impl FnMut() -> R) -> (f32, R) {
-    extern crate std;
-    let start = std::time::Instant::now();
-    let result = f();
-    let duration = std::time::Instant::now()
-        .duration_since(start)
-        .as_secs_f32();
-    (duration, result)
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-pub fn debug_time_millis<R>(mut f: impl FnMut() -> R) -> (u128, R) {
-    extern crate std;
-    let start = std::time::Instant::now();
-    let result = f();
-    let duration = std::time::Instant::now().duration_since(start).as_millis();
-    (duration, result)
-}
+    fn rect(x: f64, y: f64, width: f64, height: f64) -> NSRect {
+        NSRect::new(NSPoint::new(x, y), NSSize::new(width, height))
+    }
+
+    #[test]
+    fn restored_window_must_overlap_a_screen() {
+        let screens = [
+            rect(0.0, 0.0, 1920.0, 1080.0),
+            rect(1920.0, 0.0, 1280.0, 800.0),
+        ];
+        assert!(is_on_screen(rect(100.0, 100.0, 640.0, 480.0), &screens));
+        assert!(is_on_screen(rect(3000.0, 700.0, 640.0, 480.0), &screens));
+        assert!(is_on_screen(rect(-600.0, -400.0, 640.0, 480.0), &screens));
+
+        assert!(!is_on_screen(rect(3200.0, 0.0, 640.0, 480.0), &screens));
+        assert!(!is_on_screen(rect(0.0, -480.0, 640.0, 480.0), &screens));
+        assert!(!is_on_screen(rect(f64::NAN, 100.0, 640.0, 480.0), &screens));
+        assert!(!is_on_screen(
+            rect(100.0, f64::INFINITY, 640.0, 480.0),
+            &screens
+        ));
+        assert!(!is_on_screen(rect(100.0, 100.0, 640.0, 480.0), &[]));
+    }
+
+    fn compose(width: usize, height: usize, every_stage: bool) -> Vec<u8> {
+        let back_buffer: Vec<u8> = (0..width * height * 4).map(|i| i as u8).collect();
+        let mut front_buffer = Vec::new();
+        let mut pipeline: Box<dyn RenderPipeline> = Box::new(crate::CrtPipeline::default());
+        let mut post_stages = vec![
+            (post_stage::COLOR_FILTER, PostStage::ColorFilter),
+            (post_stage::PRESENT_FILTER, PostStage::PresentFilter),
+            (
+                post_stage::PERFORMANCE_OVERLAY,
+                PostStage::PerformanceOverlay,
+            ),
+            (
+                0,
+                PostStage::Game(Box::new(|pixels, width, height| {
+                    assert_eq!(pixels.len(), width * height * 4);
+                    assert!(pixels.as_ptr().cast::<u32>().is_aligned());
+                })),
+            ),
+        ];
+        #[cfg(feature = "std")]
+        let color_filter = Some(ColorMatrixPipeline::from_filter(ColorFilter::Deuteranopia));
+        #[cfg(not(feature = "std"))]
+        let color_filter = None;
+        let mut overlay = PerformanceOverlay::new(crate::PerformanceOverlayConfig::default());
+        let mut present_filter_input = Vec::new();
+        let output_scale = if every_stage { 2 } else { 1 };
+        let size = compose_frame(
+            &back_buffer,
+            &mut front_buffer,
+            width,
+            height,
+            output_scale,
+            every_stage.then_some(&mut pipeline),
+            if every_stage {
+                &mut post_stages
+            } else {
+                &mut []
+            },
+            PostStageInputs {
+                color_filter: color_filter.as_ref(),
+                present_filter: PresentFilter::Crt(crate::CrtPipeline::default()),
+                present_filter_input: &mut present_filter_input,
+                performance_overlay: Some(&mut overlay),
+                delta: 1.0 / 60.0,
+                audio_fill: Some(0.5),
+            },
+        );
+        let front_buffer = pixel_bytes(&front_buffer).to_vec();
+        if !every_stage {
+            assert_eq!(front_buffer, back_buffer);
+        }
+        if width > 0 && height > 0 {
+            assert_eq!(size, (width * output_scale, height * output_scale));
+            assert_eq!(front_buffer.len(), size.0 * size.1 * 4);
+        }
+        front_buffer
+    }
+
+    fn key(code: KeyCode, pressed: bool) -> Input {
+        Input::Key {
+            code,
+            modifiers: KeyModifiers::CLEAR,
+            pressed,
+            repeat: false,
+        }
+    }
+
+    #[test]
+    fn input_queue_is_fifo() {
+        let queue = InputQueue::default();
+        assert_eq!(queue.pop(), None);
+
+        let inputs = [
+            key(KeyCode::KeyW, true),
+            Input::MouseMoved { dx: 1.0, dy: -2.0 },
+            key(KeyCode::KeyW, false),
+            key(KeyCode::Spacebar, true),
+        ];
+        for input in inputs.clone() {
+            queue.push(input);
+        }
+
+        // Handling an input may queue another, which is delivered after the rest.
+        let mut delivered = Vec::new();
+        while let Some(input) = queue.pop() {
+            if delivered.is_empty() {
+                queue.push(Input::MouseMoved { dx: 0.0, dy: 0.0 });
+            }
+            delivered.push(input);
+        }
+        assert_eq!(delivered[..4], inputs);
+        assert_eq!(delivered[4], Input::MouseMoved { dx: 0.0, dy: 0.0 });
+        assert_eq!(queue.pop(), None);
+    }
+
+    #[test]
+    fn mouse_deltas_are_clamped_per_frame() {
+        let mut left = 5.0;
+        let (mut dx, mut dy) = (3.0, 4.0);
+        assert!(clamp_mouse_delta(&mut dx, &mut dy, &mut left));
+        assert_eq!((dx, dy, left), (3.0, 4.0, 0.0));
+
+        let mut left = 5.0;
+        let (mut dx, mut dy) = (6.0, 8.0);
+        assert!(clamp_mouse_delta(&mut dx, &mut dy, &mut left));
+        assert_eq!((dx, dy, left), (3.0, 4.0, 0.0));
+        assert!(!clamp_mouse_delta(&mut dx, &mut dy, &mut left));
+    }
+
+    #[test]
+    fn shortcut_keys_are_consumed_unless_delivered() {
+        let mut config = Config::default();
+        assert!(
+            KeyCode::ALL
+                .iter()
+                .all(|&code| !is_consumed_shortcut(&config, code))
+        );
+
+        config.color_filter_key = Some(KeyCode::F2);
+        config.latency_probe = Some(KeyCode::F3);
+        assert!(is_consumed_shortcut(&config, KeyCode::F2));
+        assert!(!is_consumed_shortcut(&config, KeyCode::F3));
+        assert!(!is_consumed_shortcut(&config, KeyCode::KeyW));
+
+        config.deliver_shortcut_keys = true;
+        assert!(
+            KeyCode::ALL
+                .iter()
+                .all(|&code| !is_consumed_shortcut(&config, code))
+        );
+    }
+
+    #[test]
+    fn command_keys_skip_the_input_method() {
+        let mut config = Config::default();
+        assert!(!uses_input_method(&config, NSEventModifierFlags(0)));
+
+        config.input_method_editor = true;
+        assert!(uses_input_method(&config, NSEventModifierFlags(0)));
+        assert!(uses_input_method(&config, NSEventModifierFlags::Shift));
+        assert!(uses_input_method(&config, NSEventModifierFlags::Option));
+        assert!(!uses_input_method(&config, NSEventModifierFlags::Command));
+        assert!(!uses_input_method(
+            &config,
+            NSEventModifierFlags::Command | NSEventModifierFlags::Shift
+        ));
+    }
+
+    #[cfg(debug_assertions)]
+    fn game_functions(
+        memory_layout: Option<MemoryLayout>,
+        migrate_memory: Option<extern "C" fn(*mut u8, u32)>,
+    ) -> LoadedGameFunctions<u64, u32> {
+        extern "C" fn allocation_stats() -> AllocationStats {
+            AllocationStats::default()
+        }
+        LoadedGameFunctions {
+            dylib: null_mut(),
+            handle_input: |_| {},
+            update_and_render: |_| {},
+            allocation_stats,
+            run_tasks: crate::task::__run_cooperative,
+            memory_layout,
+            migrate_memory,
+        }
+    }
+
+    // Stores the old version in the memory, so a test can see it ran.
+    #[cfg(debug_assertions)]
+    extern "C" fn migrate(memory: *mut u8, old_version: u32) {
+        unsafe { *memory.cast::<u64>() = 1000 + old_version as u64 };
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    fn memory_layout_changes_are_refused() {
+        let v1 = game_functions(Some(MemoryLayout::of::<u64>(1)), None);
+        let mut memory = 7u64;
+
+        for layout in [
+            MemoryLayout::of::<u32>(1),
+            MemoryLayout::of::<[u32; 2]>(1),
+            MemoryLayout::of::<u128>(1),
+        ] {
+            let new = game_functions(Some(layout), Some(migrate));
+            assert!(!accept_memory_layout(&mut memory, &v1, &new), "{layout:?}");
+        }
+
+        let v2 = game_functions(Some(MemoryLayout::of::<u64>(2)), None);
+        assert!(!accept_memory_layout(&mut memory, &v1, &v2));
+        assert_eq!(memory, 7);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    fn memory_layout_matches_or_migrates() {
+        let unversioned = game_functions(None, None);
+        let v1 = game_functions(Some(MemoryLayout::of::<u64>(1)), None);
+        let mut memory = 7u64;
+
+        // Without an exported layout there is nothing to compare.
+        assert!(accept_memory_layout(&mut memory, &v1, &unversioned));
+        assert!(accept_memory_layout(&mut memory, &unversioned, &v1));
+        assert!(accept_memory_layout(&mut memory, &v1, &v1));
+        assert_eq!(memory, 7);
+
+        let v2 = game_functions(Some(MemoryLayout::of::<u64>(2)), Some(migrate));
+        assert!(accept_memory_layout(&mut memory, &v1, &v2));
+        assert_eq!(memory, 1001);
+    }
 
-pub fn debug_time_nanos<R>(mut f: impl FnMut() -> R) -> (u128, R) {
-    extern crate std;
-    let start = std::time::Instant::now();
-    let result = f();
-    let duration = std::time::Instant::now().duration_since(start).as_nanos();
-    (duration, result)
+    #[test]
+    fn frame_clock_soak() {
+        use std::time::Duration;
+
+        // A day at 60Hz, with ticks jittering by up to a millisecond either way.
+        const FRAMES: u32 = 60 * 60 * 60 * 24;
+        let start = Instant::now();
+        let mut clock = FrameClock::new(start);
+        let mut now = start;
+        let mut noise = crate::dsp::Noise::new(1);
+        let mut time = 0.0;
+        for _ in 0..FRAMES {
+            let jitter = (noise.sample() * 1_000_000.0) as i64;
+            now += Duration::from_nanos((16_666_667 + jitter) as u64);
+            let delta = clock.tick(now);
+            assert!(delta > 0.0 && delta < 0.02);
+            time = clock.accumulate(delta);
+        }
+        let elapsed = (now - start).as_secs_f64();
+        assert!((elapsed - 86_400.0).abs() < 1.0, "{elapsed}");
+        assert!((time - elapsed).abs() < 1e-6, "{time} {elapsed}");
+        assert_eq!(clock.since_last_tick(now), 0.0);
+
+        // Deltas skipped while paused are not accumulated, but are not delivered later.
+        now += Duration::from_secs(10);
+        clock.tick(now);
+        now += Duration::from_millis(16);
+        assert!((clock.tick(now) - 0.016).abs() < 1e-9);
+        assert_eq!(clock.accumulate(0.016), time + 0.016);
+    }
+
+    #[test]
+    fn crash_log_hook_is_installed_once_and_restored() {
+        use std::sync::atomic::AtomicUsize;
+
+        const MESSAGE: &str = "crash log hook test";
+        static PREVIOUS_CALLS: AtomicUsize = AtomicUsize::new(0);
+        let panic_in_task = || {
+            let _ = std::thread::spawn(|| std::panic::panic_any(MESSAGE)).join();
+        };
+        let crash_logs = || {
+            std::fs::read_dir(".")
+                .unwrap()
+                .filter(|entry| {
+                    let name = entry.as_ref().unwrap().file_name();
+                    name.to_string_lossy().starts_with("crash-")
+                })
+                .count()
+        };
+
+        std::panic::set_hook(Box::new(|info| {
+            if info.payload().downcast_ref::<&str>() == Some(&MESSAGE) {
+                PREVIOUS_CALLS.fetch_add(1, Ordering::Relaxed);
+            }
+        }));
+        let logs_before = crash_logs();
+        {
+            let hook = CrashLogHook::install();
+            let nested = CrashLogHook::install();
+            assert!(hook.previous.is_some() && nested.previous.is_none());
+
+            // Chained once, and task panics are not crashes of the app.
+            panic_in_task();
+            assert_eq!(PREVIOUS_CALLS.load(Ordering::Relaxed), 1);
+            assert_eq!(crash_logs(), logs_before);
+        }
+        assert!(!CRASH_LOG_HOOK_INSTALLED.load(Ordering::Relaxed));
+        panic_in_task();
+        assert_eq!(PREVIOUS_CALLS.load(Ordering::Relaxed), 2);
+
+        // Installs again after being restored, without chaining the first.
+        drop(CrashLogHook::install());
+        panic_in_task();
+        assert_eq!(PREVIOUS_CALLS.load(Ordering::Relaxed), 3);
+        drop(std::panic::take_hook());
+    }
+
+    #[test]
+    fn composes_degenerate_frames() {
+        for (width, height) in [(0, 0), (0, 16), (16, 0), (1, 1), (1, 10_000), (10_000, 1)] {
+            for every_stage in [false, true] {
+                compose(width, height, every_stage);
+            }
+        }
+        assert!(compose(0, 0, true).is_empty());
+    }
+
+    #[test]
+    fn present_filter_runs_at_output_resolution() {
+        let back_buffer = [200, 100, 50, 255].repeat(2 * 2);
+        let mut front_buffer = Vec::new();
+        let mut present_filter_input = Vec::new();
+        let crt = crate::CrtPipeline {
+            scanline_darkness: 0.5,
+            horizontal_blur: 0.0,
+            barrel_distortion: 0.0,
+            aperture_grille: 0.0,
+        };
+        let mut post_stages = [(post_stage::PRESENT_FILTER, PostStage::PresentFilter)];
+        let mut compose = |present_filter, output_scale| {
+            let size = compose_frame(
+                &back_buffer,
+                &mut front_buffer,
+                2,
+                2,
+                output_scale,
+                None,
+                &mut post_stages,
+                PostStageInputs {
+                    color_filter: None,
+                    present_filter,
+                    present_filter_input: &mut present_filter_input,
+                    performance_overlay: None,
+                    delta: 1.0 / 60.0,
+                    audio_fill: None,
+                },
+            );
+            let presented = pixel_bytes(&front_buffer).to_vec();
+            (size, presented, present_filter_input.clone())
+        };
+
+        // Scanlines alternate on the rows of the 6x6 output, not the 2x2 frame.
+        let (size, presented, unfiltered) = compose(PresentFilter::Crt(crt), 3);
+        assert_eq!(size, (6, 6));
+        assert_eq!(unfiltered, [200, 100, 50, 255].repeat(6 * 6));
+        for (y, row) in presented.chunks_exact(6 * 4).enumerate() {
+            let expected = if y % 2 == 1 {
+                [100, 50, 25, 255]
+            } else {
+                [200, 100, 50, 255]
+            };
+            assert!(
+                row.chunks_exact(4).all(|pixel| pixel == expected),
+                "row {y}"
+            );
+        }
+
+        // Without a filter the frame is presented at its own size again.
+        let (size, presented, unfiltered) = compose(PresentFilter::None, 3);
+        assert_eq!(size, (2, 2));
+        assert_eq!(presented, back_buffer);
+        assert!(unfiltered.is_empty());
+    }
 }
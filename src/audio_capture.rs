@@ -0,0 +1,244 @@
+//! Records the samples written by the game to a WAV file, before they reach the device.
+//!
+//! Samples are handed to a writer thread in a fixed pool of blocks which it sends back
+//! once written, so a slow disk drops blocks of the capture rather than stalling
+//! playback, and a running capture does not allocate.
+
+extern crate std;
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::sync::mpsc::{Receiver, SyncSender, TryRecvError, sync_channel};
+use std::thread::JoinHandle;
+
+/// Starts recording the game's samples to a 16-bit PCM WAV file at `path`, returning
+/// whether the capture started.
+///
+/// A capture which is already running is stopped first.
+pub fn start(path: &str) -> bool {
+    crate::platform::start_audio_capture(path)
+}
+
+/// Stops the capture, finishing the WAV file and writing its markers.
+pub fn stop() {
+    crate::platform::stop_audio_capture();
+}
+
+/// Marks the next sample written with `name`.
+///
+/// Markers are written to `<path>.markers.txt` when the capture stops, one per line as
+/// the frame index and name separated by a tab.
+pub fn mark(name: &str) {
+    crate::platform::mark_audio_capture(name);
+}
+
+// Blocks, one per update, waiting for the writer before new ones are dropped.
+const QUEUE_LEN: usize = 64;
+
+/// A running capture, owned by the platform.
+pub(crate) struct AudioCapture {
+    path: String,
+    sender: SyncSender<Vec<i16>>,
+    // Blocks the writer is done with. Each grows to the longest update once.
+    free_blocks: Receiver<Vec<i16>>,
+    writer: JoinHandle<io::Result<()>>,
+    channels: usize,
+    // frames written so far, the position of the next marker
+    frames: u64,
+    dropped_blocks: u64,
+    markers: Vec<(u64, String)>,
+}
+
+impl AudioCapture {
+    pub fn start(path: &str, channels: usize, sample_rate: u32) -> io::Result<Self> {
+        let mut file = BufWriter::new(File::create(path)?);
+        // The sizes are filled in once the length is known.
+        write_wav_header(&mut file, channels as u16, sample_rate, 0)?;
+        let (sender, receiver) = sync_channel(QUEUE_LEN);
+        let (recycle, free_blocks) = sync_channel(QUEUE_LEN);
+        for _ in 0..QUEUE_LEN {
+            let _ = recycle.send(Vec::new());
+        }
+        let writer = std::thread::Builder::new()
+            .name(String::from("glazer audio capture"))
+            .spawn(move || write_samples(file, receiver, recycle, channels as u16, sample_rate))?;
+        Ok(Self {
+            path: String::from(path),
+            sender,
+            free_blocks,
+            writer,
+            channels,
+            frames: 0,
+            dropped_blocks: 0,
+            markers: Vec::new(),
+        })
+    }
+
+    /// Queues interleaved `samples` for the writer.
+    pub fn write(&mut self, samples: &[i16]) {
+        if samples.is_empty() {
+            return;
+        }
+        let mut block = match self.free_blocks.try_recv() {
+            Ok(block) => block,
+            // Every block is waiting for the writer.
+            Err(TryRecvError::Empty) => {
+                self.dropped_blocks += 1;
+                return;
+            }
+            // The writer failed, its error is reported by `stop`.
+            Err(TryRecvError::Disconnected) => return,
+        };
+        block.clear();
+        block.extend_from_slice(samples);
+        // Never full, the queue holds the whole pool.
+        if self.sender.try_send(block).is_ok() {
+            self.frames += (samples.len() / self.channels) as u64;
+        }
+    }
+
+    pub fn mark(&mut self, name: &str) {
+        self.markers.push((self.frames, String::from(name)));
+    }
+
+    pub fn stop(self) -> io::Result<()> {
+        drop(self.sender);
+        if self.dropped_blocks > 0 {
+            crate::log!(
+                "ERROR: audio capture `{}` dropped {} blocks",
+                self.path,
+                self.dropped_blocks
+            );
+        }
+        self.writer
+            .join()
+            .unwrap_or_else(|_| Err(io::Error::other("audio capture writer panicked")))?;
+        if !self.markers.is_empty() {
+            let mut markers = String::new();
+            for (frame, name) in &self.markers {
+                markers.push_str(&format!("{frame}\t{name}\n"));
+            }
+            std::fs::write(format!("{}.markers.txt", self.path), markers)?;
+        }
+        Ok(())
+    }
+}
+
+fn write_samples(
+    mut file: BufWriter<File>,
+    receiver: Receiver<Vec<i16>>,
+    recycle: SyncSender<Vec<i16>>,
+    channels: u16,
+    sample_rate: u32,
+) -> io::Result<()> {
+    for block in receiver {
+        for sample in &block {
+            file.write_all(&sample.to_le_bytes())?;
+        }
+        let _ = recycle.try_send(block);
+    }
+    let data_len = file.stream_position()? as u32 - WAV_HEADER_LEN;
+    file.seek(SeekFrom::Start(0))?;
+    write_wav_header(&mut file, channels, sample_rate, data_len)?;
+    file.flush()
+}
+
+const WAV_HEADER_LEN: u32 = 44;
+
+fn write_wav_header(
+    out: &mut impl Write,
+    channels: u16,
+    sample_rate: u32,
+    data_len: u32,
+) -> io::Result<()> {
+    let block_align = channels * 2;
+    out.write_all(b"RIFF")?;
+    out.write_all(&(WAV_HEADER_LEN - 8 + data_len).to_le_bytes())?;
+    out.write_all(b"WAVE")?;
+    out.write_all(b"fmt ")?;
+    out.write_all(&16u32.to_le_bytes())?;
+    // PCM
+    out.write_all(&1u16.to_le_bytes())?;
+    out.write_all(&channels.to_le_bytes())?;
+    out.write_all(&sample_rate.to_le_bytes())?;
+    out.write_all(&(sample_rate * block_align as u32).to_le_bytes())?;
+    out.write_all(&block_align.to_le_bytes())?;
+    out.write_all(&16u16.to_le_bytes())?;
+    out.write_all(b"data")?;
+    out.write_all(&data_len.to_le_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn u16_at(bytes: &[u8], at: usize) -> u16 {
+        u16::from_le_bytes(bytes[at..at + 2].try_into().unwrap())
+    }
+
+    fn u32_at(bytes: &[u8], at: usize) -> u32 {
+        u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn wav_header_layout() {
+        let mut header = Vec::new();
+        write_wav_header(&mut header, 2, 48_000, 1000).unwrap();
+        assert_eq!(header.len(), WAV_HEADER_LEN as usize);
+        assert_eq!(&header[0..4], b"RIFF");
+        // Everything after the RIFF size.
+        assert_eq!(u32_at(&header, 4), 36 + 1000);
+        assert_eq!(&header[8..16], b"WAVEfmt ");
+        assert_eq!(u32_at(&header, 16), 16);
+        assert_eq!(u16_at(&header, 20), 1);
+        assert_eq!(u16_at(&header, 22), 2);
+        assert_eq!(u32_at(&header, 24), 48_000);
+        // Bytes per second and per frame.
+        assert_eq!(u32_at(&header, 28), 48_000 * 4);
+        assert_eq!(u16_at(&header, 32), 4);
+        assert_eq!(u16_at(&header, 34), 16);
+        assert_eq!(&header[36..40], b"data");
+        assert_eq!(u32_at(&header, 40), 1000);
+    }
+
+    #[test]
+    fn capture_writes_samples_and_markers() {
+        let path = std::env::temp_dir().join(format!("glazer-capture-{}.wav", std::process::id()));
+        let path = path.to_str().unwrap();
+        let markers_path = format!("{path}.markers.txt");
+
+        let mut capture = AudioCapture::start(path, 2, 44_100).unwrap();
+        capture.mark("start");
+        capture.write(&[1, -1, 2, -2]);
+        capture.write(&[]);
+        capture.mark("hit");
+        // More blocks than the pool holds, any the writer has not returned yet are dropped.
+        for _ in 0..QUEUE_LEN * 2 {
+            capture.write(&[3, -3]);
+        }
+        let written = capture.frames;
+        capture.stop().unwrap();
+
+        let bytes = std::fs::read(path).unwrap();
+        let markers = std::fs::read_to_string(&markers_path).unwrap();
+        let _ = std::fs::remove_file(path);
+        let _ = std::fs::remove_file(&markers_path);
+
+        let data_len = written as u32 * 4;
+        assert_eq!(bytes.len(), (WAV_HEADER_LEN + data_len) as usize);
+        assert_eq!(u32_at(&bytes, 4), 36 + data_len);
+        assert_eq!(u32_at(&bytes, 40), data_len);
+        let samples: Vec<i16> = bytes[44..]
+            .chunks_exact(2)
+            .map(|sample| i16::from_le_bytes([sample[0], sample[1]]))
+            .collect();
+        assert_eq!(samples[..4], [1, -1, 2, -2]);
+        assert!(samples[4..].chunks_exact(2).all(|frame| frame == [3, -3]));
+
+        // Frame index and name, a tab apart.
+        assert_eq!(markers, "0\tstart\n2\thit\n");
+    }
+}
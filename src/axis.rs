@@ -0,0 +1,173 @@
+//! Dead zones and response curves for analog inputs, and digital keys as axes.
+//!
+//! Sticks are `(x, y)` pairs with each component in `-1.0..=1.0`.
+//!
+//! ```ignore
+//! let stick = axis::deadzone_radial(raw_stick, 0.15, 0.95);
+//! let keys = axis::clamp_unit((
+//!     axis::digital_to_axis(left_held, right_held),
+//!     axis::digital_to_axis(down_held, up_held),
+//! ));
+//! ```
+
+#[cfg(feature = "std")]
+extern crate std;
+
+/// Rescales `stick` so its length is `0.0` up to `inner` and reaches `1.0` at `outer`,
+/// keeping its direction.
+///
+/// Unlike a dead zone on each axis, small diagonal motion is not snapped to an axis.
+pub fn deadzone_radial(stick: (f32, f32), inner: f32, outer: f32) -> (f32, f32) {
+    let length = sqrt(stick.0 * stick.0 + stick.1 * stick.1);
+    if length <= inner || length == 0.0 {
+        return (0.0, 0.0);
+    }
+    let scaled = rescale(length, inner, outer);
+    (stick.0 / length * scaled, stick.1 / length * scaled)
+}
+
+/// Rescales a single axis so it is `0.0` up to `inner` and reaches `1.0` at `outer`,
+/// keeping its sign.
+pub fn deadzone_axial(value: f32, inner: f32, outer: f32) -> f32 {
+    let magnitude = value.abs();
+    if magnitude <= inner {
+        return 0.0;
+    }
+    rescale(magnitude, inner, outer).copysign(value)
+}
+
+// `magnitude` above `inner`, onto `0.0..=1.0`.
+fn rescale(magnitude: f32, inner: f32, outer: f32) -> f32 {
+    if outer <= inner {
+        return 1.0;
+    }
+    ((magnitude - inner) / (outer - inner)).min(1.0)
+}
+
+/// Raises the magnitude of `value` to `exponent`, keeping its sign.
+///
+/// `1.0` is linear, `2.0` squared gives finer control near the center.
+///
+/// Needs the `std` feature for `powf`, [`curve_int`] works without it.
+#[cfg(feature = "std")]
+pub fn curve(value: f32, exponent: f32) -> f32 {
+    value.abs().powf(exponent).copysign(value)
+}
+
+/// [`curve`] with a whole number `exponent`, which does not need the `std` feature.
+pub fn curve_int(value: f32, exponent: u32) -> f32 {
+    let magnitude = value.abs();
+    (0..exponent)
+        .fold(1.0, |result, _| result * magnitude)
+        .copysign(value)
+}
+
+/// `-1.0` while only the negative key is held, `1.0` while only the positive key is
+/// held, otherwise `0.0`.
+pub fn digital_to_axis(negative: bool, positive: bool) -> f32 {
+    positive as i8 as f32 - negative as i8 as f32
+}
+
+/// Shortens `stick` to a length of `1.0` if it is longer, keeping its direction.
+///
+/// Two [`digital_to_axis`] axes held together are `2.0_f32.sqrt()` long, so diagonal
+/// movement is otherwise faster.
+pub fn clamp_unit(stick: (f32, f32)) -> (f32, f32) {
+    let length_squared = stick.0 * stick.0 + stick.1 * stick.1;
+    if length_squared <= 1.0 {
+        return stick;
+    }
+    let length = sqrt(length_squared);
+    (stick.0 / length, stick.1 / length)
+}
+
+// `core` has no square root for floats.
+fn sqrt(x: f32) -> f32 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+    // Halving the exponent is within a few percent, Newton's method converges from there.
+    let mut root = f32::from_bits((x.to_bits() >> 1) + (127 << 22));
+    for _ in 0..3 {
+        root = 0.5 * (root + x / root);
+    }
+    root
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn length(stick: (f32, f32)) -> f32 {
+        sqrt(stick.0 * stick.0 + stick.1 * stick.1)
+    }
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-5
+    }
+
+    #[test]
+    fn radial_boundaries() {
+        assert_eq!(deadzone_radial((0.0, 0.0), 0.15, 0.95), (0.0, 0.0));
+        assert_eq!(deadzone_radial((0.0, 0.0), 0.0, 1.0), (0.0, 0.0));
+        assert_eq!(deadzone_radial((0.15, 0.0), 0.15, 0.95), (0.0, 0.0));
+        assert_eq!(deadzone_radial((0.0, -0.15), 0.15, 0.95), (0.0, 0.0));
+
+        let (x, y) = deadzone_radial((0.0, -0.95), 0.15, 0.95);
+        assert!(close(x, 0.0) && close(y, -1.0));
+        let (x, y) = deadzone_radial((0.55, 0.0), 0.15, 0.95);
+        assert!(close(x, 0.5) && close(y, 0.0));
+    }
+
+    #[test]
+    fn radial_diagonal_saturates_in_direction() {
+        let stick = deadzone_radial((1.0, 1.0), 0.15, 0.95);
+        assert!(close(length(stick), 1.0));
+        assert!(close(stick.0, stick.1));
+
+        let stick = deadzone_radial((-0.9, 0.9), 0.15, 0.95);
+        assert!(close(length(stick), 1.0));
+        assert!(close(stick.0, -stick.1));
+    }
+
+    #[test]
+    fn axial_boundaries() {
+        assert_eq!(deadzone_axial(0.0, 0.1, 0.9), 0.0);
+        assert_eq!(deadzone_axial(0.1, 0.1, 0.9), 0.0);
+        assert_eq!(deadzone_axial(-0.1, 0.1, 0.9), 0.0);
+        assert!(close(deadzone_axial(0.9, 0.1, 0.9), 1.0));
+        assert!(close(deadzone_axial(-0.9, 0.1, 0.9), -1.0));
+        assert!(close(deadzone_axial(-0.5, 0.1, 0.9), -0.5));
+        assert_eq!(deadzone_axial(-1.0, 0.1, 0.9), -1.0);
+        assert_eq!(deadzone_axial(0.5, 0.5, 0.5), 0.0);
+        assert_eq!(deadzone_axial(0.6, 0.5, 0.5), 1.0);
+    }
+
+    #[test]
+    fn curves_keep_sign() {
+        assert_eq!(curve_int(-0.5, 2), -0.25);
+        assert_eq!(curve_int(0.5, 3), 0.125);
+        assert_eq!(curve_int(-0.5, 1), -0.5);
+        assert_eq!(curve_int(0.5, 0), 1.0);
+        assert_eq!(curve_int(0.0, 2), 0.0);
+        #[cfg(feature = "std")]
+        {
+            assert!(close(curve(-0.5, 2.0), -0.25));
+            assert!(close(curve(0.25, 0.5), 0.5));
+        }
+    }
+
+    #[test]
+    fn digital_axes() {
+        assert_eq!(digital_to_axis(false, false), 0.0);
+        assert_eq!(digital_to_axis(true, true), 0.0);
+        assert_eq!(digital_to_axis(true, false), -1.0);
+        assert_eq!(digital_to_axis(false, true), 1.0);
+
+        let stick = clamp_unit((1.0, -1.0));
+        assert!(close(length(stick), 1.0));
+        assert!(close(stick.0, -stick.1));
+        assert_eq!(clamp_unit((0.5, 0.5)), (0.5, 0.5));
+        assert_eq!(clamp_unit((0.0, 0.0)), (0.0, 0.0));
+    }
+}
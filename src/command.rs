@@ -0,0 +1,173 @@
+//! Requests from `update_and_render` to the platform, applied in order once it returns.
+//!
+//! Commands are plain data in a fixed capacity queue owned by the platform, so they can
+//! be issued without allocating and cross the hot reload boundary unchanged.
+//!
+//! ```ignore
+//! if update.time > 5.0 {
+//!     update.commands.set_title("Loaded");
+//!     update.commands.set_cursor_visible(false);
+//! }
+//! ```
+
+/// Commands issued per update, later ones are dropped.
+pub const CAPACITY: usize = 32;
+/// Bytes of a [`Command::SetTitle`] title, longer titles are truncated.
+pub const TITLE_LEN: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Command {
+    /// Sets the window title, which the platform follows with the frame rate.
+    SetTitle(Title),
+    /// Stops the run loop after this frame, returning the game's memory from `run`.
+    Quit,
+    /// Shows or hides the cursor while it is over the window. It is always shown while
+    /// the window is in the background.
+    SetCursorVisible(bool),
+    /// Hides the cursor and holds it in place while the window is focused, for relative
+    /// mouse control. `Input::MouseMoved` is still delivered.
+    ///
+    /// Releasing the grab shows the cursor where it was when grabbed.
+    SetCursorGrab(bool),
+    /// Sets the cursor's shape while it is over the window, [`Cursor::Arrow`] until set.
+    /// Replaces a cursor set with [`set_custom_cursor`](crate::set_custom_cursor).
+    ///
+    /// Setting the current shape again does nothing, so it can be issued every update.
+    SetCursor(Cursor),
+}
+
+/// A system cursor shape, see [`Command::SetCursor`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Cursor {
+    #[default]
+    Arrow,
+    Hand,
+    IBeam,
+    Crosshair,
+    ResizeEW,
+    ResizeNS,
+    /// Hides the cursor, like [`Command::SetCursorVisible`].
+    Hidden,
+}
+
+/// A title stored inline, see [`TITLE_LEN`].
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Title {
+    len: u8,
+    bytes: [u8; TITLE_LEN],
+}
+
+impl Title {
+    pub fn new(title: &str) -> Self {
+        let mut len = title.len().min(TITLE_LEN);
+        while !title.is_char_boundary(len) {
+            len -= 1;
+        }
+        let mut bytes = [0; TITLE_LEN];
+        bytes[..len].copy_from_slice(&title.as_bytes()[..len]);
+        Self {
+            len: len as u8,
+            bytes,
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        // Only whole characters are ever copied in.
+        core::str::from_utf8(&self.bytes[..self.len as usize]).unwrap_or("")
+    }
+}
+
+impl core::fmt::Debug for Title {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        self.as_str().fmt(f)
+    }
+}
+
+/// The platform's queue, which [`Commands`] pushes to.
+#[derive(Debug)]
+pub(crate) struct CommandQueue {
+    commands: [Option<Command>; CAPACITY],
+    len: usize,
+    // pushed while full since the last drain
+    dropped: usize,
+}
+
+impl CommandQueue {
+    pub const fn new() -> Self {
+        Self {
+            commands: [None; CAPACITY],
+            len: 0,
+            dropped: 0,
+        }
+    }
+
+    /// Takes the queued commands in the order they were issued, and how many were
+    /// dropped.
+    pub fn drain(&mut self) -> (impl Iterator<Item = Command> + '_, usize) {
+        let len = core::mem::take(&mut self.len);
+        let dropped = core::mem::take(&mut self.dropped);
+        (
+            self.commands[..len].iter_mut().filter_map(Option::take),
+            dropped,
+        )
+    }
+}
+
+/// Issues [`Command`]s from `update_and_render`, see [`PlatformUpdate::commands`].
+///
+/// [`PlatformUpdate::commands`]: crate::PlatformUpdate::commands
+#[derive(Debug)]
+pub struct Commands<'a> {
+    queue: &'a mut CommandQueue,
+}
+
+impl<'a> Commands<'a> {
+    pub(crate) fn new(queue: &'a mut CommandQueue) -> Self {
+        Self { queue }
+    }
+
+    /// Queues `command`, returning `false` if [`CAPACITY`] commands were already
+    /// issued this update.
+    ///
+    /// Dropped commands are logged as an error by the platform.
+    pub fn push(&mut self, command: Command) -> bool {
+        let queue = &mut *self.queue;
+        if queue.len == CAPACITY {
+            queue.dropped += 1;
+            return false;
+        }
+        queue.commands[queue.len] = Some(command);
+        queue.len += 1;
+        true
+    }
+
+    pub fn set_title(&mut self, title: &str) -> bool {
+        self.push(Command::SetTitle(Title::new(title)))
+    }
+
+    pub fn quit(&mut self) -> bool {
+        self.push(Command::Quit)
+    }
+
+    pub fn set_cursor_visible(&mut self, visible: bool) -> bool {
+        self.push(Command::SetCursorVisible(visible))
+    }
+
+    pub fn set_cursor_grab(&mut self, grab: bool) -> bool {
+        self.push(Command::SetCursorGrab(grab))
+    }
+
+    pub fn set_cursor(&mut self, cursor: Cursor) -> bool {
+        self.push(Command::SetCursor(cursor))
+    }
+
+    /// Commands issued so far this update.
+    pub fn len(&self) -> usize {
+        self.queue.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.len == 0
+    }
+}
@@ -0,0 +1,266 @@
+//! Timing and allocation utilities for profiling game code.
+//!
+//! Time is read through a [`Clock`], so these work wherever the platform provides one
+//! and report zero durations elsewhere instead of requiring `std`.
+
+use core::alloc::{GlobalAlloc, Layout};
+use core::sync::atomic::{AtomicU64, Ordering};
+
+/// A monotonic time source.
+pub trait Clock {
+    /// Nanoseconds since an arbitrary, fixed point in time.
+    fn now_nanos() -> u64;
+}
+
+/// `mach_absolute_time`, which does not advance while the system is asleep.
+#[cfg(target_os = "macos")]
+pub struct MacOSClock;
+
+#[cfg(target_os = "macos")]
+impl Clock for MacOSClock {
+    fn now_nanos() -> u64 {
+        let timebase = mach_timebase();
+        let ticks = unsafe { mach_absolute_time() } as u128;
+        (ticks * timebase.numer as u128 / timebase.denom as u128) as u64
+    }
+}
+
+// `mach_timebase_info` packed as `numer << 32 | denom`, zero until first asked for.
+#[cfg(target_os = "macos")]
+static MACH_TIMEBASE: AtomicU64 = AtomicU64::new(0);
+
+/// The ratio of `mach_absolute_time` ticks to nanoseconds, which is fixed at boot.
+#[cfg(target_os = "macos")]
+pub(crate) fn mach_timebase() -> MachTimebaseInfo {
+    let mut packed = MACH_TIMEBASE.load(Ordering::Relaxed);
+    if packed == 0 {
+        let mut timebase = MachTimebaseInfo { numer: 0, denom: 0 };
+        unsafe { mach_timebase_info(&mut timebase) };
+        packed = (timebase.numer as u64) << 32 | timebase.denom as u64;
+        MACH_TIMEBASE.store(packed, Ordering::Relaxed);
+    }
+    MachTimebaseInfo {
+        numer: (packed >> 32) as u32,
+        denom: packed as u32,
+    }
+}
+
+// `libc` deprecated its mach bindings in favour of the `mach2` crate.
+#[cfg(target_os = "macos")]
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct MachTimebaseInfo {
+    pub numer: u32,
+    pub denom: u32,
+}
+
+#[cfg(target_os = "macos")]
+unsafe extern "C" {
+    fn mach_timebase_info(info: *mut MachTimebaseInfo) -> i32;
+    fn mach_absolute_time() -> u64;
+}
+
+/// `performance.now()`, with the browser's timer resolution.
+#[cfg(target_arch = "wasm32")]
+pub struct WasmClock;
+
+#[cfg(target_arch = "wasm32")]
+impl Clock for WasmClock {
+    fn now_nanos() -> u64 {
+        let millis = web_sys::window()
+            .and_then(|window| window.performance())
+            .map_or(0.0, |performance| performance.now());
+        (millis * 1_000_000.0) as u64
+    }
+}
+
+/// Always returns zero, for platforms without a clock.
+pub struct NullClock;
+
+impl Clock for NullClock {
+    fn now_nanos() -> u64 {
+        0
+    }
+}
+
+#[cfg(target_os = "macos")]
+pub type PlatformClock = MacOSClock;
+#[cfg(target_arch = "wasm32")]
+pub type PlatformClock = WasmClock;
+#[cfg(not(any(target_os = "macos", target_arch = "wasm32")))]
+pub type PlatformClock = NullClock;
+
+/// Allocation totals since startup, see [`CountingAllocator`].
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct AllocationStats {
+    /// Calls to `alloc`, `alloc_zeroed` and `realloc`.
+    pub allocations: u64,
+    /// Bytes requested by those calls.
+    pub bytes: u64,
+}
+
+impl core::ops::Sub for AllocationStats {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        Self {
+            allocations: self.allocations - rhs.allocations,
+            bytes: self.bytes - rhs.bytes,
+        }
+    }
+}
+
+static ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+static ALLOCATED_BYTES: AtomicU64 = AtomicU64::new(0);
+
+/// Wraps a global allocator and counts its allocations.
+///
+/// ```ignore
+/// #[global_allocator]
+/// static ALLOCATOR: CountingAllocator<std::alloc::System> =
+///     CountingAllocator::new(std::alloc::System);
+/// ```
+///
+/// In debug builds the game is loaded from a dylib, which must install the allocator
+/// itself and [`export_allocation_stats!`](crate::export_allocation_stats) for its
+/// allocations to be counted.
+pub struct CountingAllocator<A>(A);
+
+impl<A> CountingAllocator<A> {
+    pub const fn new(allocator: A) -> Self {
+        Self(allocator)
+    }
+
+    fn count(&self, size: usize) {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_add(size as u64, Ordering::Relaxed);
+    }
+}
+
+unsafe impl<A: GlobalAlloc> GlobalAlloc for CountingAllocator<A> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.count(layout.size());
+        unsafe { self.0.alloc(layout) }
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        self.count(layout.size());
+        unsafe { self.0.alloc_zeroed(layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        self.count(new_size);
+        unsafe { self.0.realloc(ptr, layout, new_size) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { self.0.dealloc(ptr, layout) }
+    }
+}
+
+/// Totals counted by a [`CountingAllocator`], zero if none is installed.
+pub fn allocation_stats() -> AllocationStats {
+    AllocationStats {
+        allocations: ALLOCATIONS.load(Ordering::Relaxed),
+        bytes: ALLOCATED_BYTES.load(Ordering::Relaxed),
+    }
+}
+
+/// Exports the game dylib's [`allocation_stats`], which the platform's own copy of the
+/// counters does not see while hot reloading.
+///
+/// ```ignore
+/// #[global_allocator]
+/// static ALLOCATOR: CountingAllocator<std::alloc::System> =
+///     CountingAllocator::new(std::alloc::System);
+///
+/// glazer::export_allocation_stats!();
+/// ```
+#[macro_export]
+macro_rules! export_allocation_stats {
+    () => {
+        #[doc(hidden)]
+        #[unsafe(no_mangle)]
+        pub extern "C" fn glazer_allocation_stats() -> $crate::debug::AllocationStats {
+            $crate::debug::allocation_stats()
+        }
+    };
+}
+
+fn time_nanos<R>(mut f: impl FnMut() -> R) -> (u64, R) {
+    let start = PlatformClock::now_nanos();
+    let result = f();
+    let duration = PlatformClock::now_nanos().saturating_sub(start);
+    (duration, result)
+}
+
+pub fn debug_time_secs<R>(f: impl FnMut() -> R) -> (f32, R) {
+    let (nanos, result) = time_nanos(f);
+    (nanos as f32 / 1_000_000_000.0, result)
+}
+
+pub fn debug_time_millis<R>(f: impl FnMut() -> R) -> (u128, R) {
+    let (nanos, result) = time_nanos(f);
+    (nanos as u128 / 1_000_000, result)
+}
+
+pub fn debug_time_nanos<R>(f: impl FnMut() -> R) -> (u128, R) {
+    let (nanos, result) = time_nanos(f);
+    (nanos as u128, result)
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use std::alloc::System;
+
+    // The only test which uses the shared counters.
+    #[test]
+    fn counts_allocations_per_frame() {
+        let allocator = CountingAllocator::new(System);
+        let small = Layout::from_size_align(16, 8).unwrap();
+        let large = Layout::from_size_align(64, 8).unwrap();
+
+        let before = allocation_stats();
+        let ptr = unsafe {
+            let ptr = allocator.alloc(small);
+            allocator.realloc(ptr, small, large.size())
+        };
+        let first_frame = allocation_stats() - before;
+        assert_eq!(
+            first_frame,
+            AllocationStats {
+                allocations: 2,
+                bytes: 16 + 64,
+            }
+        );
+
+        // Frees are not counted, a frame's totals start from the previous frame's.
+        let before = allocation_stats();
+        unsafe { allocator.dealloc(ptr, large) };
+        assert_eq!(allocation_stats() - before, AllocationStats::default());
+
+        let before = allocation_stats();
+        let word = Layout::new::<u32>();
+        unsafe { allocator.dealloc(allocator.alloc_zeroed(word), word) };
+        assert_eq!(
+            allocation_stats() - before,
+            AllocationStats {
+                allocations: 1,
+                bytes: 4,
+            }
+        );
+    }
+
+    #[cfg(target_os = "macos")]
+    #[test]
+    fn mach_clock_is_monotonic() {
+        let timebase = mach_timebase();
+        assert!(timebase.numer > 0 && timebase.denom > 0);
+        let start = MacOSClock::now_nanos();
+        assert!(MacOSClock::now_nanos() >= start);
+    }
+}
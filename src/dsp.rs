@@ -0,0 +1,361 @@
+//! Audio synthesis in fixed size blocks, with control rate parameter updates between them.
+//!
+//! Everything here works on mono `f32` samples in caller owned state, without allocating,
+//! so it can run inside `update_and_render`:
+//!
+//! ```ignore
+//! dsp::for_each_block(&mut mono[..frames], |block| {
+//!     // Parameters hold for a block and step along their ramp between blocks.
+//!     filter.set_cutoff(cutoff.next_block(block.len()), sample_rate);
+//!     noise.process(block);
+//!     filter.process(block);
+//!     envelope.process(block);
+//! });
+//! dsp::write_i16(&mono[..frames], update.samples, update.channels);
+//! ```
+
+/// Samples between control rate updates.
+pub const BLOCK_LEN: usize = 64;
+
+/// A step in a chain of audio processing, which fills or modifies `samples` in place.
+pub trait Block {
+    fn process(&mut self, samples: &mut [f32]);
+}
+
+/// Calls `f` with each [`BLOCK_LEN`] chunk of `samples`, the last may be shorter.
+pub fn for_each_block(samples: &mut [f32], mut f: impl FnMut(&mut [f32])) {
+    for block in samples.chunks_mut(BLOCK_LEN) {
+        f(block);
+    }
+}
+
+/// Writes mono `samples` into every channel of the interleaved `output`, clamped to
+/// `-1.0..=1.0`.
+///
+/// Writes nothing when `channels` is zero.
+pub fn write_i16(samples: &[f32], output: &mut [i16], channels: usize) {
+    if channels == 0 {
+        return;
+    }
+    for (sample, frame) in samples.iter().zip(output.chunks_exact_mut(channels)) {
+        frame.fill((sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16);
+    }
+}
+
+/// A control parameter which moves linearly to its target over `ramp_samples`.
+///
+/// The value is constant within a block and steps between blocks, so it reaches the
+/// target after `ramp_samples` samples in steps small enough not to click.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Smoothed {
+    value: f32,
+    target: f32,
+    ramp_samples: usize,
+    // change per sample, and samples left until `target`
+    step: f32,
+    remaining: usize,
+}
+
+impl Smoothed {
+    pub const fn new(value: f32, ramp_samples: usize) -> Self {
+        Self {
+            value,
+            target: value,
+            ramp_samples,
+            step: 0.0,
+            remaining: 0,
+        }
+    }
+
+    /// Starts a new ramp from the current value to `target`.
+    pub fn set(&mut self, target: f32) {
+        self.target = target;
+        self.remaining = self.ramp_samples;
+        if self.ramp_samples == 0 {
+            self.value = target;
+        } else {
+            self.step = (target - self.value) / self.ramp_samples as f32;
+        }
+    }
+
+    /// The current value.
+    pub fn value(&self) -> f32 {
+        self.value
+    }
+
+    /// Advances by `len` samples, returning the value at the end of the block.
+    pub fn next_block(&mut self, len: usize) -> f32 {
+        if self.remaining <= len {
+            self.remaining = 0;
+            self.value = self.target;
+        } else {
+            self.remaining -= len;
+            self.value += self.step * len as f32;
+        }
+        self.value
+    }
+}
+
+/// A one pole lowpass filter.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct OnePole {
+    state: f32,
+    coefficient: f32,
+}
+
+impl OnePole {
+    pub fn new(cutoff: f32, sample_rate: f32) -> Self {
+        let mut filter = Self::default();
+        filter.set_cutoff(cutoff, sample_rate);
+        filter
+    }
+
+    /// Sets the cutoff frequency in Hz.
+    pub fn set_cutoff(&mut self, cutoff: f32, sample_rate: f32) {
+        // `1 - e^-w` without `exp`, which `core` does not provide.
+        let w = core::f32::consts::TAU * cutoff.max(0.0) / sample_rate;
+        self.coefficient = w / (1.0 + w);
+    }
+}
+
+impl Block for OnePole {
+    fn process(&mut self, samples: &mut [f32]) {
+        for sample in samples {
+            self.state += self.coefficient * (*sample - self.state);
+            *sample = self.state;
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum Stage {
+    #[default]
+    Idle,
+    Attack,
+    Decay,
+    Sustain,
+    Release,
+}
+
+/// An attack, decay, sustain, release envelope which scales the samples it processes.
+///
+/// Times are in seconds and `sustain` is a level from `0.0` to `1.0`.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Adsr {
+    pub attack: f32,
+    pub decay: f32,
+    pub sustain: f32,
+    pub release: f32,
+    sample_rate: f32,
+    stage: Stage,
+    level: f32,
+}
+
+impl Adsr {
+    pub fn new(attack: f32, decay: f32, sustain: f32, release: f32, sample_rate: f32) -> Self {
+        Self {
+            attack,
+            decay,
+            sustain,
+            release,
+            sample_rate,
+            stage: Stage::Idle,
+            level: 0.0,
+        }
+    }
+
+    /// Starts the attack when `on`, otherwise the release.
+    pub fn gate(&mut self, on: bool) {
+        self.stage = match (on, self.stage) {
+            (true, _) => Stage::Attack,
+            (false, Stage::Idle) => Stage::Idle,
+            (false, _) => Stage::Release,
+        };
+    }
+
+    /// The current level, from `0.0` to `1.0`.
+    pub fn level(&self) -> f32 {
+        self.level
+    }
+
+    /// Whether the envelope has finished releasing.
+    pub fn is_idle(&self) -> bool {
+        self.stage == Stage::Idle
+    }
+
+    // Change in level per sample for a segment of `seconds`.
+    fn step(&self, seconds: f32) -> f32 {
+        let samples = seconds * self.sample_rate;
+        if samples > 1.0 { 1.0 / samples } else { 1.0 }
+    }
+
+    fn sample(&mut self) -> f32 {
+        match self.stage {
+            Stage::Idle => self.level = 0.0,
+            Stage::Attack => {
+                self.level += self.step(self.attack);
+                if self.level >= 1.0 {
+                    self.level = 1.0;
+                    self.stage = Stage::Decay;
+                }
+            }
+            Stage::Decay => {
+                self.level -= self.step(self.decay) * (1.0 - self.sustain);
+                if self.level <= self.sustain {
+                    self.level = self.sustain;
+                    self.stage = Stage::Sustain;
+                }
+            }
+            Stage::Sustain => self.level = self.sustain,
+            Stage::Release => {
+                self.level -= self.step(self.release);
+                if self.level <= 0.0 {
+                    self.level = 0.0;
+                    self.stage = Stage::Idle;
+                }
+            }
+        }
+        self.level
+    }
+}
+
+impl Block for Adsr {
+    fn process(&mut self, samples: &mut [f32]) {
+        for sample in samples {
+            *sample *= self.sample();
+        }
+    }
+}
+
+/// White noise from `-1.0` to `1.0`, which replaces the samples it processes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Noise {
+    state: u32,
+}
+
+impl Default for Noise {
+    fn default() -> Self {
+        Self::new(0x9e37_79b9)
+    }
+}
+
+impl Noise {
+    /// A generator seeded with `seed`, zero is replaced with a fixed seed.
+    pub fn new(seed: u32) -> Self {
+        Self {
+            state: if seed == 0 { 0x9e37_79b9 } else { seed },
+        }
+    }
+
+    pub fn sample(&mut self) -> f32 {
+        // xorshift32
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 17;
+        self.state ^= self.state << 5;
+        self.state as f32 / u32::MAX as f32 * 2.0 - 1.0
+    }
+}
+
+impl Block for Noise {
+    fn process(&mut self, samples: &mut [f32]) {
+        for sample in samples {
+            *sample = self.sample();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn write_i16_fills_every_channel() {
+        let mut output = [0; 6];
+        write_i16(&[1.0, -2.0, 0.0], &mut output, 2);
+        assert_eq!(output, [i16::MAX, i16::MAX, -i16::MAX, -i16::MAX, 0, 0]);
+
+        write_i16(&[0.0], &mut output, 0);
+        assert_eq!(output[0], i16::MAX);
+    }
+
+    #[test]
+    fn smoothed_ramps_linearly_to_target() {
+        let mut smoothed = Smoothed::new(0.0, 256);
+        smoothed.set(1.0);
+        assert_eq!(smoothed.next_block(64), 0.25);
+        assert_eq!(smoothed.next_block(64), 0.5);
+        assert_eq!(smoothed.next_block(64), 0.75);
+        assert_eq!(smoothed.next_block(64), 1.0);
+        assert_eq!(smoothed.next_block(64), 1.0);
+
+        // A new target ramps from wherever the last one stopped.
+        smoothed.set(0.0);
+        assert_eq!(smoothed.next_block(128), 0.5);
+        smoothed.set(1.0);
+        assert_eq!(smoothed.next_block(128), 0.75);
+        assert_eq!(smoothed.next_block(1000), 1.0);
+
+        let mut instant = Smoothed::new(0.0, 0);
+        instant.set(3.0);
+        assert_eq!(instant.value(), 3.0);
+        assert_eq!(instant.next_block(1), 3.0);
+    }
+
+    #[test]
+    fn one_pole_step_response() {
+        let mut filter = OnePole::new(1000.0, 48_000.0);
+        let mut samples = [1.0; 512];
+        filter.process(&mut samples);
+        assert!(samples[0] > 0.0 && samples[0] < 1.0);
+        assert!(samples.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert!((samples[511] - 1.0).abs() < 1e-3);
+
+        let mut closed = OnePole::new(0.0, 48_000.0);
+        let mut samples = [1.0; 64];
+        closed.process(&mut samples);
+        assert!(samples.iter().all(|&sample| sample == 0.0));
+    }
+
+    #[test]
+    fn adsr_stages() {
+        // At one sample per second: 4 samples of attack, 4 of decay to half, 2 of release.
+        let mut envelope = Adsr::new(4.0, 4.0, 0.5, 2.0, 1.0);
+        assert!(envelope.is_idle());
+        envelope.gate(false);
+        assert!(envelope.is_idle());
+
+        envelope.gate(true);
+        let mut samples = [1.0; 12];
+        envelope.process(&mut samples);
+        assert_eq!(
+            samples,
+            [
+                0.25, 0.5, 0.75, 1.0, 0.875, 0.75, 0.625, 0.5, 0.5, 0.5, 0.5, 0.5
+            ]
+        );
+        assert!(!envelope.is_idle());
+
+        envelope.gate(false);
+        let mut samples = [1.0; 3];
+        envelope.process(&mut samples);
+        assert_eq!(samples, [0.0, 0.0, 0.0]);
+        assert!(envelope.is_idle());
+        assert_eq!(envelope.level(), 0.0);
+    }
+
+    #[test]
+    fn noise_is_deterministic_and_bounded() {
+        let mut a = Noise::new(7);
+        let mut b = Noise::new(7);
+        let mut c = Noise::new(8);
+        let mut differs = false;
+        for _ in 0..1000 {
+            let sample = a.sample();
+            assert_eq!(sample, b.sample());
+            assert!((-1.0..=1.0).contains(&sample));
+            differs |= sample != c.sample();
+        }
+        assert!(differs);
+        assert_eq!(Noise::new(0), Noise::default());
+    }
+}
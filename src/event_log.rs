@@ -0,0 +1,85 @@
+//! Fixed capacity record of recent platform events, written out after a crash.
+
+use core::fmt::{self, Write};
+
+const CAPACITY: usize = 2048;
+// Longer entries are truncated.
+const TEXT_LEN: usize = 112;
+
+#[derive(Clone, Copy)]
+struct Entry {
+    // seconds
+    time: f64,
+    len: u8,
+    text: [u8; TEXT_LEN],
+}
+
+impl Entry {
+    const EMPTY: Self = Self {
+        time: 0.0,
+        len: 0,
+        text: [0; TEXT_LEN],
+    };
+
+    fn text(&self) -> &str {
+        // Only whole characters are ever copied in.
+        core::str::from_utf8(&self.text[..self.len as usize]).unwrap_or("")
+    }
+}
+
+impl Write for Entry {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.chars() {
+            let start = self.len as usize;
+            let end = start + c.len_utf8();
+            if end > TEXT_LEN {
+                break;
+            }
+            c.encode_utf8(&mut self.text[start..end]);
+            self.len = end as u8;
+        }
+        Ok(())
+    }
+}
+
+/// A ring of the most recent `CAPACITY` events, recording one never allocates.
+pub(crate) struct EventLog {
+    entries: [Entry; CAPACITY],
+    // the oldest entry once the ring is full
+    next: usize,
+    len: usize,
+}
+
+impl EventLog {
+    pub const fn new() -> Self {
+        Self {
+            entries: [Entry::EMPTY; CAPACITY],
+            next: 0,
+            len: 0,
+        }
+    }
+
+    /// Records `args` at `time` seconds, overwriting the oldest entry when full.
+    pub fn record(&mut self, time: f64, args: fmt::Arguments) {
+        let entry = &mut self.entries[self.next];
+        entry.time = time;
+        entry.len = 0;
+        let _ = entry.write_fmt(args);
+        self.next = (self.next + 1) % CAPACITY;
+        self.len = (self.len + 1).min(CAPACITY);
+    }
+
+    /// Writes the entries from the last `seconds`, oldest first and one per line.
+    pub fn write_recent(&self, out: &mut impl Write, seconds: f64) -> fmt::Result {
+        let first = (self.next + CAPACITY - self.len) % CAPACITY;
+        let newest = self.entries[(self.next + CAPACITY - 1) % CAPACITY].time;
+        for i in 0..self.len {
+            let entry = &self.entries[(first + i) % CAPACITY];
+            if entry.time < newest - seconds {
+                continue;
+            }
+            writeln!(out, "[{:>10.4}] {}", entry.time, entry.text())?;
+        }
+        Ok(())
+    }
+}
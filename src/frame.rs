@@ -0,0 +1,247 @@
+//! Row and pixel iteration over a frame buffer, without indexing by hand.
+
+/// A `width * height` view into a frame buffer, or a rectangle of one.
+///
+/// ```ignore
+/// let mut frame = Frame::new(update.frame_buffer, update.width, update.height);
+/// for (y, row) in frame.rows() {
+///     row.fill(background(y));
+/// }
+/// frame.rect_mut(8, 8, 32, 32).fill(WHITE);
+/// ```
+#[derive(Debug)]
+pub struct Frame<'a, Pixels> {
+    pixels: &'a mut [Pixels],
+    width: usize,
+    height: usize,
+    // pixels from the start of one row to the next
+    stride: usize,
+}
+
+impl<'a, Pixels> Frame<'a, Pixels> {
+    pub fn new(pixels: &'a mut [Pixels], width: usize, height: usize) -> Self {
+        assert!(
+            pixels.len() >= width * height,
+            "frame buffer is smaller than {width}x{height}"
+        );
+        Self {
+            pixels,
+            width,
+            height,
+            stride: width,
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Each row from the top, with its `y`.
+    pub fn rows(&mut self) -> impl Iterator<Item = (usize, &mut [Pixels])> {
+        let width = self.width;
+        self.pixels
+            .chunks_mut(self.stride.max(1))
+            .take(self.height)
+            .map(move |row| &mut row[..width])
+            .enumerate()
+    }
+
+    /// Each pixel row by row, with its `x` and `y`.
+    ///
+    /// A `for` loop over this is several times slower than one over [`Frame::rows`],
+    /// `for_each` is as fast, see `examples/frame_iteration.rs`.
+    pub fn pixels(&mut self) -> impl Iterator<Item = (usize, usize, &mut Pixels)> {
+        self.rows().flat_map(|(y, row)| {
+            row.iter_mut()
+                .enumerate()
+                .map(move |(x, pixel)| (x, y, pixel))
+        })
+    }
+
+    /// A view of `width * height` pixels at `x`, `y`, clipped to this frame.
+    pub fn rect_mut(
+        &mut self,
+        x: usize,
+        y: usize,
+        width: usize,
+        height: usize,
+    ) -> Frame<'_, Pixels> {
+        let x = x.min(self.width);
+        let y = y.min(self.height);
+        let width = width.min(self.width - x);
+        let height = height.min(self.height - y);
+        let pixels = if width == 0 || height == 0 {
+            &mut []
+        } else {
+            let start = y * self.stride + x;
+            let end = (y + height - 1) * self.stride + x + width;
+            &mut self.pixels[start..end]
+        };
+        Frame {
+            pixels,
+            width,
+            height,
+            stride: self.stride,
+        }
+    }
+
+    fn index(&self, x: usize, y: usize) -> Option<usize> {
+        (x < self.width && y < self.height).then(|| y * self.stride + x)
+    }
+
+    /// The pixel at `x`, `y`, or `None` outside the frame.
+    pub fn get(&self, x: usize, y: usize) -> Option<&Pixels> {
+        self.index(x, y).map(|index| &self.pixels[index])
+    }
+
+    pub fn get_mut(&mut self, x: usize, y: usize) -> Option<&mut Pixels> {
+        self.index(x, y).map(|index| &mut self.pixels[index])
+    }
+
+    /// Sets the pixel at `x`, `y`, doing nothing outside the frame.
+    pub fn set(&mut self, x: usize, y: usize, pixel: Pixels) {
+        if let Some(index) = self.index(x, y) {
+            self.pixels[index] = pixel;
+        }
+    }
+}
+
+impl<Pixels: Copy> Frame<'_, Pixels> {
+    pub fn fill(&mut self, pixel: Pixels) {
+        for (_, row) in self.rows() {
+            row.fill(pixel);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    #[test]
+    fn get_and_set_are_bounds_checked() {
+        let mut pixels = vec![0u32; 4 * 3];
+        let mut frame = Frame::new(&mut pixels, 4, 3);
+        frame.set(3, 2, 1);
+        frame.set(4, 0, 2);
+        frame.set(0, 3, 3);
+        frame.set(usize::MAX, usize::MAX, 4);
+        assert_eq!(frame.get(3, 2), Some(&1));
+        assert_eq!(frame.get(4, 0), None);
+        assert_eq!(frame.get(0, 3), None);
+        assert_eq!(frame.get_mut(usize::MAX, 0), None);
+        assert_eq!(pixels.iter().sum::<u32>(), 1);
+        assert_eq!(pixels[2 * 4 + 3], 1);
+    }
+
+    #[test]
+    fn rect_mut_is_clipped() {
+        let mut pixels = vec![0u32; 4 * 3];
+        let mut frame = Frame::new(&mut pixels, 4, 3);
+
+        let rect = frame.rect_mut(2, 1, 10, 10);
+        assert_eq!((rect.width(), rect.height()), (2, 2));
+
+        for (x, y) in [(4, 0), (0, 3), (usize::MAX, usize::MAX)] {
+            let mut rect = frame.rect_mut(x, y, 2, 2);
+            assert_eq!(rect.width() * rect.height(), 0);
+            assert_eq!(rect.rows().count(), 0);
+            rect.fill(9);
+        }
+        let mut rect = frame.rect_mut(1, 1, 0, 2);
+        rect.fill(9);
+        assert!(pixels.iter().all(|&pixel| pixel == 0));
+
+        let mut frame = Frame::new(&mut pixels, 4, 3);
+        let mut rect = frame.rect_mut(2, 1, usize::MAX, usize::MAX);
+        rect.fill(1);
+        assert_eq!(rect.get(1, 1), Some(&1));
+        assert_eq!(rect.get(2, 0), None);
+        rect.set(0, 2, 5);
+        #[rustfmt::skip]
+        assert_eq!(pixels, [
+            0, 0, 0, 0,
+            0, 0, 1, 1,
+            0, 0, 1, 1,
+        ]);
+    }
+
+    #[test]
+    fn nested_rects_offset_from_their_parent() {
+        let mut pixels = vec![0u32; 5 * 5];
+        let mut frame = Frame::new(&mut pixels, 5, 5);
+        let mut outer = frame.rect_mut(1, 1, 3, 3);
+        outer.rect_mut(1, 1, 5, 5).fill(1);
+        outer.set(0, 0, 2);
+        #[rustfmt::skip]
+        assert_eq!(pixels, [
+            0, 0, 0, 0, 0,
+            0, 2, 0, 0, 0,
+            0, 0, 1, 1, 0,
+            0, 0, 1, 1, 0,
+            0, 0, 0, 0, 0,
+        ]);
+    }
+
+    #[test]
+    fn iteration_matches_indexing_by_hand() {
+        let (width, height) = (7, 5);
+        let mut by_hand = vec![0usize; width * height];
+        for y in 0..height {
+            for x in 0..width {
+                by_hand[y * width + x] = x * 100 + y;
+            }
+        }
+
+        let mut pixels = vec![0usize; width * height];
+        let mut frame = Frame::new(&mut pixels, width, height);
+        let order: Vec<(usize, usize)> = frame
+            .pixels()
+            .map(|(x, y, pixel)| {
+                *pixel = x * 100 + y;
+                (x, y)
+            })
+            .collect();
+        assert_eq!(pixels, by_hand);
+        assert!(
+            order
+                .windows(2)
+                .all(|pair| (pair[0].1, pair[0].0) < (pair[1].1, pair[1].0))
+        );
+
+        let mut frame = Frame::new(&mut pixels, width, height);
+        for (y, row) in frame.rows() {
+            assert_eq!(row.len(), width);
+            assert_eq!(row[0], y);
+        }
+        assert_eq!(frame.rows().count(), height);
+    }
+
+    #[test]
+    fn empty_frames() {
+        let mut pixels: [u32; 0] = [];
+        let mut frame = Frame::new(&mut pixels, 0, 0);
+        assert_eq!(frame.rows().count(), 0);
+        assert_eq!(frame.pixels().count(), 0);
+        assert_eq!(frame.get(0, 0), None);
+        frame.fill(1);
+
+        let mut pixels = [0u32; 4];
+        let mut frame = Frame::new(&mut pixels, 0, 4);
+        assert!(frame.rows().all(|(_, row)| row.is_empty()));
+        assert_eq!(frame.pixels().count(), 0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn new_rejects_small_buffers() {
+        let mut pixels = [0u32; 5];
+        Frame::new(&mut pixels, 3, 2);
+    }
+}
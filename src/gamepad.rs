@@ -0,0 +1,173 @@
+//! Game controllers, polled by the platform before each update, see
+//! [`PlatformUpdate::gamepads`](crate::PlatformUpdate::gamepads).
+//!
+//! Values are raw, without a dead zone, so games apply their own filtering.
+//!
+//! ```ignore
+//! if let Some((_, pad)) = update.gamepads.iter().next() {
+//!     let (x, y) = pad.left_stick;
+//!     camera.x += x * update.delta;
+//!     if pad.is_pressed(GamepadButton::South) {
+//!         jump();
+//!     }
+//! }
+//! ```
+
+/// Controllers tracked at once, later ones are ignored until a slot frees.
+pub const MAX_GAMEPADS: usize = 4;
+
+/// A button named by its position, e.g. `South` is A on an Xbox controller and cross on
+/// a PlayStation controller.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GamepadButton {
+    South,
+    East,
+    West,
+    North,
+    LeftShoulder,
+    RightShoulder,
+    LeftStick,
+    RightStick,
+    DPadUp,
+    DPadDown,
+    DPadLeft,
+    DPadRight,
+    Start,
+    Select,
+    Home,
+}
+
+impl GamepadButton {
+    /// Every button, in declaration order.
+    pub const ALL: [Self; 15] = [
+        Self::South,
+        Self::East,
+        Self::West,
+        Self::North,
+        Self::LeftShoulder,
+        Self::RightShoulder,
+        Self::LeftStick,
+        Self::RightStick,
+        Self::DPadUp,
+        Self::DPadDown,
+        Self::DPadLeft,
+        Self::DPadRight,
+        Self::Start,
+        Self::Select,
+        Self::Home,
+    ];
+}
+
+/// The state of one controller.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Gamepad {
+    /// `x` and `y` in `-1.0..=1.0`, positive right and up.
+    pub left_stick: (f32, f32),
+    /// See `left_stick`.
+    pub right_stick: (f32, f32),
+    /// How far the trigger is pulled, in `0.0..=1.0`.
+    pub left_trigger: f32,
+    /// See `left_trigger`.
+    pub right_trigger: f32,
+    // bit per `GamepadButton`
+    buttons: u16,
+}
+
+impl Gamepad {
+    /// Whether `button` is held, as of the poll before this update.
+    pub fn is_pressed(&self, button: GamepadButton) -> bool {
+        self.buttons & (1 << button as u16) != 0
+    }
+
+    pub(crate) fn set_pressed(&mut self, button: GamepadButton, pressed: bool) {
+        if pressed {
+            self.buttons |= 1 << button as u16;
+        } else {
+            self.buttons &= !(1 << button as u16);
+        }
+    }
+}
+
+/// Connected controllers by slot, see [`Input::GamepadConnected`].
+///
+/// A controller keeps its slot until it disconnects.
+///
+/// [`Input::GamepadConnected`]: crate::Input::GamepadConnected
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Gamepads {
+    pub(crate) slots: [Option<Gamepad>; MAX_GAMEPADS],
+}
+
+impl Gamepads {
+    /// The controller in `slot`, `None` if it is empty.
+    pub fn get(&self, slot: usize) -> Option<&Gamepad> {
+        self.slots.get(slot)?.as_ref()
+    }
+
+    /// Connected controllers and their slots.
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &Gamepad)> {
+        self.slots
+            .iter()
+            .enumerate()
+            .filter_map(|(slot, pad)| Some((slot, pad.as_ref()?)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+
+    #[test]
+    fn buttons_are_independent() {
+        let mut pad = Gamepad::default();
+        for button in GamepadButton::ALL {
+            assert!(!pad.is_pressed(button));
+            pad.set_pressed(button, true);
+            for other in GamepadButton::ALL {
+                assert_eq!(
+                    pad.is_pressed(other),
+                    other == button,
+                    "{button:?} {other:?}"
+                );
+            }
+            pad.set_pressed(button, true);
+            assert!(pad.is_pressed(button));
+            pad.set_pressed(button, false);
+            assert_eq!(pad, Gamepad::default());
+        }
+
+        for button in GamepadButton::ALL {
+            pad.set_pressed(button, true);
+        }
+        assert!(
+            GamepadButton::ALL
+                .iter()
+                .all(|&button| pad.is_pressed(button))
+        );
+        pad.set_pressed(GamepadButton::Home, false);
+        assert!(!pad.is_pressed(GamepadButton::Home));
+        assert!(pad.is_pressed(GamepadButton::Select));
+    }
+
+    #[test]
+    fn iterates_connected_slots_in_order() {
+        let pad = |x| Gamepad {
+            left_trigger: x,
+            ..Gamepad::default()
+        };
+        let mut gamepads = Gamepads::default();
+        assert_eq!(gamepads.iter().count(), 0);
+
+        gamepads.slots[3] = Some(pad(3.0));
+        gamepads.slots[1] = Some(pad(1.0));
+        let connected: Vec<_> = gamepads
+            .iter()
+            .map(|(slot, pad)| (slot, pad.left_trigger))
+            .collect();
+        assert_eq!(connected, [(1, 1.0), (3, 3.0)]);
+        assert_eq!(gamepads.get(1), Some(&pad(1.0)));
+        assert_eq!(gamepads.get(0), None);
+        assert_eq!(gamepads.get(MAX_GAMEPADS), None);
+    }
+}
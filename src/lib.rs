@@ -1,6 +1,39 @@
 #![no_std]
 extern crate alloc;
 
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::ops::Range;
+
+pub mod axis;
+pub mod command;
+pub mod debug;
+pub mod dsp;
+mod event_log;
+pub mod frame;
+pub mod gamepad;
+
+#[cfg(feature = "std")]
+pub mod audio_capture;
+mod overlay;
+pub mod pipeline;
+pub mod profiling;
+pub mod sprite;
+#[cfg(feature = "std")]
+mod state_file;
+pub mod task;
+
+pub use command::{Command, Commands, Cursor};
+pub use debug::{debug_time_millis, debug_time_nanos, debug_time_secs};
+pub use frame::Frame;
+pub use gamepad::{Gamepad, GamepadButton, Gamepads};
+pub use overlay::{OverlayPosition, PerformanceOverlayConfig};
+pub use pipeline::{
+    ColorFilter, ColorMatrixPipeline, CrtPipeline, GammaCorrectionPipeline, IdentityPipeline,
+    RenderPipeline,
+};
+
 #[cfg(target_os = "macos")]
 mod appkit;
 #[cfg(target_os = "macos")]
@@ -14,15 +47,12 @@ pub fn run<Memory, Pixels>(
     handle_input: fn(PlatformInput<Memory>),
     update_and_render: fn(PlatformUpdate<Memory, Pixels>),
     shared_lib_path: &str,
-) where
+) -> Result<Memory, Error>
+where
     Pixels: 'static,
     Memory: 'static,
 {
-    assert!(
-        core::mem::size_of::<Pixels>() == 4,
-        "`Pixels` must be 4 bytes"
-    );
-    platform::run(
+    App::new(
         memory,
         frame_buffer,
         width,
@@ -30,7 +60,1004 @@ pub fn run<Memory, Pixels>(
         handle_input,
         update_and_render,
         shared_lib_path,
-    );
+    )
+    .run()
+}
+
+pub struct App<'a, Memory, Pixels> {
+    pub(crate) memory: Memory,
+    pub(crate) frame_buffer: &'a mut [Pixels],
+    pub(crate) width: usize,
+    pub(crate) height: usize,
+    pub(crate) handle_input: fn(PlatformInput<Memory>),
+    pub(crate) update_and_render: fn(PlatformUpdate<Memory, Pixels>),
+    pub(crate) shared_lib_path: &'a str,
+    pub(crate) print_handler: Option<PrintHandler<Memory>>,
+    pub(crate) view: ViewConfig,
+    pub(crate) startup_checks: Vec<StartupCheck>,
+    pub(crate) audio_routes: Vec<AudioRoute>,
+    #[cfg(feature = "std")]
+    pub(crate) state_file: Option<state_file::StateFile>,
+    pub(crate) config: Config,
+}
+
+/// Verifies a requirement of the app before it starts, returning a message for the user
+/// on failure.
+pub type StartupCheck = fn() -> Result<(), String>;
+
+/// Renders `width * height * 4` bytes of RGBA pixels for printing.
+pub type PrintHandler<Memory> = fn(&Memory, usize, usize) -> Vec<u8>;
+
+/// Settings for the game view which, unlike [`Config`], are not `Copy`.
+pub(crate) struct ViewConfig {
+    pub title: String,
+    pub render_pipeline: Option<Box<dyn RenderPipeline>>,
+    pub drag_areas: Vec<Rect>,
+    pub color_filters: Vec<(ColorFilter, ColorMatrixPipeline)>,
+    pub post_stages: Vec<(i32, PostStageFn)>,
+}
+
+/// A post stage with the game's `Pixels` erased, see [`App::add_post_stage`].
+pub(crate) type PostStageFn = Box<dyn FnMut(&mut [u8], usize, usize)>;
+
+/// Priorities of the built-in post stages, see [`App::add_post_stage`].
+pub mod post_stage {
+    /// [`App::with_color_filter_key`](crate::App::with_color_filter_key).
+    pub const COLOR_FILTER: i32 = 100;
+    /// [`App::with_present_filter`](crate::App::with_present_filter), display emulation
+    /// applies to the finished frame, scaled up to the window's resolution.
+    pub const PRESENT_FILTER: i32 = 500;
+    /// [`App::with_performance_overlay`](crate::App::with_performance_overlay), debug UI
+    /// is drawn last so it is never filtered.
+    pub const PERFORMANCE_OVERLAY: i32 = 1000;
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct Config {
+    pub print_support: bool,
+    pub services_menu: bool,
+    pub audio_thread_priority: AudioThreadPriority,
+    pub audio_format: AudioFormat,
+    pub audio_fallback_format: Option<AudioFormat>,
+    pub error_dialog: Option<fn(&str) -> ErrorAction>,
+    pub latency_probe: Option<KeyCode>,
+    pub input_method_editor: bool,
+    pub assert_no_frame_allocations: bool,
+    pub latency_mode: Option<LatencyMode>,
+    pub opengl: bool,
+    pub metal_hud: bool,
+    pub color_filter_key: Option<KeyCode>,
+    pub window_animations: bool,
+    pub touchpad_zoom_to_fullscreen: bool,
+    pub performance_overlay: Option<PerformanceOverlayConfig>,
+    pub first_present: FirstPresent,
+    pub media_keys: bool,
+    pub max_mouse_delta: Option<f32>,
+    pub mouse_input_mode: MouseInputMode,
+    pub pause_on_focus_loss: bool,
+    pub idle_threshold: Option<f32>,
+    pub idle_mouse_dead_zone: f32,
+    pub deliver_shortcut_keys: bool,
+    pub present_filter: PresentFilter,
+    pub preferred_fps: Option<u32>,
+    pub ui_layer: bool,
+    pub preserve_ui_layer: bool,
+    // restored from the state file, centered when `None`
+    pub window_origin: Option<(f64, f64)>,
+    // restore the state file without `--resume`
+    #[cfg(feature = "std")]
+    pub resume: bool,
+    // seconds of game time between saves to the state file
+    #[cfg(feature = "std")]
+    pub state_save_interval: Option<f32>,
+    pub resizable_window: bool,
+    pub transparent_titlebar: bool,
+    pub hide_title: bool,
+    pub window_shadow: bool,
+}
+
+/// How to proceed after a non-fatal platform error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorAction {
+    /// Attempt the failed operation again.
+    Retry,
+    /// Carry on without the failed resource, e.g. without audio.
+    Continue,
+    /// Stop the app.
+    Abort,
+}
+
+/// Why [`App::run`] returned without the `Memory` value, or a resource could not be
+/// created.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// Every failed `App::with_startup_check`, the window was never opened.
+    StartupChecks(Vec<String>),
+    /// A platform error was answered with [`ErrorAction::Abort`] before the run loop started.
+    Aborted(String),
+    /// [`CustomCursor::from_rgba`] was given an image it can not use.
+    InvalidCursor(String),
+}
+
+impl core::fmt::Display for Error {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::StartupChecks(failures) => {
+                write!(f, "startup checks failed: {}", failures.join(", "))
+            }
+            Self::Aborted(message) => write!(f, "aborted: {message}"),
+            Self::InvalidCursor(reason) => write!(f, "invalid cursor: {reason}"),
+        }
+    }
+}
+
+/// What the window shows before the first frame is presented, see
+/// [`App::with_first_present`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum FirstPresent {
+    /// Shows the window immediately, blank until the first frame.
+    #[default]
+    Immediate,
+    /// Keeps the window hidden until the first frame is presented.
+    Hidden,
+    /// Shows the window immediately, filled with an RGBA color until the first frame.
+    Splash([u8; 4]),
+}
+
+/// How [`Input::MouseMoved`] deltas are measured, see [`App::with_mouse_input_mode`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum MouseInputMode {
+    /// Points the cursor moved, following the user's pointer acceleration.
+    #[default]
+    Accelerated,
+    /// Device motion before pointer acceleration, for 1:1 aiming and camera control.
+    Raw,
+}
+
+/// A filter applied to the finished frame when it is presented, see
+/// [`App::with_present_filter`].
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum PresentFilter {
+    #[default]
+    None,
+    Crt(CrtPipeline),
+}
+
+/// Trade-off between frame pacing and power use, see [`App::latency_mode`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LatencyMode {
+    /// Lets the OS delay the frame timer by a few milliseconds to coalesce wakeups, and
+    /// throttle the app while it is in the background.
+    PowerSaving,
+    /// Fires the frame timer without delay, runs the main thread at the user interactive
+    /// QoS class and opts out of App Nap.
+    ///
+    /// This keeps the CPU awake more often and noticeably shortens battery life.
+    LowLatency,
+}
+
+/// Scheduling policy for the thread which runs the audio render callback.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum AudioThreadPriority {
+    /// Leaves the thread's scheduling as the OS configured it.
+    #[default]
+    Default,
+    /// Mach time constraint scheduling, all values are in nanoseconds.
+    ///
+    /// `period_ns` is the interval between callbacks, `computation_ns` the time needed to
+    /// run one, and `constraint_ns` the deadline for finishing it after it starts.
+    RealTime {
+        period_ns: u64,
+        computation_ns: u64,
+        constraint_ns: u64,
+    },
+}
+
+/// Sample encoding used by the audio output device.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SampleFormat {
+    #[default]
+    I16,
+    F32,
+}
+
+/// Format requested from the audio output device.
+///
+/// The game always writes `i16` samples, which are converted when the device uses
+/// [`SampleFormat::F32`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AudioFormat {
+    pub sample_format: SampleFormat,
+    pub sample_rate: f32,
+}
+
+impl AudioFormat {
+    pub const I16_44100: Self = Self::new(SampleFormat::I16, 44_100.0);
+    pub const I16_48000: Self = Self::new(SampleFormat::I16, 48_000.0);
+    pub const F32_44100: Self = Self::new(SampleFormat::F32, 44_100.0);
+    pub const F32_48000: Self = Self::new(SampleFormat::F32, 48_000.0);
+
+    pub const fn new(sample_format: SampleFormat, sample_rate: f32) -> Self {
+        Self {
+            sample_format,
+            sample_rate,
+        }
+    }
+}
+
+impl Default for AudioFormat {
+    fn default() -> Self {
+        Self::I16_44100
+    }
+}
+
+/// A rectangle in points from the top left of the window's content.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Rect {
+    pub x: f32,
+    pub y: f32,
+    pub w: f32,
+    pub h: f32,
+}
+
+impl Rect {
+    pub const fn new(x: f32, y: f32, w: f32, h: f32) -> Self {
+        Self { x, y, w, h }
+    }
+
+    pub fn contains(&self, x: f32, y: f32) -> bool {
+        x >= self.x && x < self.x + self.w && y >= self.y && y < self.y + self.h
+    }
+}
+
+/// Distances in from each edge of a rectangle.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Insets {
+    pub top: f32,
+    pub left: f32,
+    pub bottom: f32,
+    pub right: f32,
+}
+
+/// Window edges the cursor is pressed against, see [`set_cursor_confined`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CursorEdges(pub u8);
+
+impl CursorEdges {
+    pub const NONE: Self = Self(0);
+    pub const LEFT: Self = Self(1);
+    pub const RIGHT: Self = Self(1 << 1);
+    pub const TOP: Self = Self(1 << 2);
+    pub const BOTTOM: Self = Self(1 << 3);
+
+    pub fn contains(&self, edges: Self) -> bool {
+        self.0 & edges.0 == edges.0
+    }
+}
+
+impl core::ops::BitOr for CursorEdges {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self::Output {
+        Self(self.0 | rhs.0)
+    }
+}
+
+/// Sends a range of the game's audio channels to channels of an output device.
+///
+/// `source_channels` index into the interleaved frames of [`PlatformUpdate::samples`],
+/// and are paired in order with `output_channels` of the device.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AudioRoute {
+    pub source_channels: Range<usize>,
+    /// A CoreAudio `AudioDeviceID`.
+    pub output_device_id: u32,
+    pub output_channels: Range<usize>,
+}
+
+/// Time from a latency probe key press to the presentation of the next frame.
+///
+/// This covers event delivery, queued updates and drawing, but not the display itself.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct LatencyStats {
+    /// Number of measured presses.
+    pub samples: u32,
+    /// Seconds.
+    pub last: f64,
+    /// Seconds.
+    pub min: f64,
+    /// Seconds.
+    pub max: f64,
+    /// Seconds.
+    pub total: f64,
+}
+
+impl LatencyStats {
+    /// Mean latency in seconds.
+    pub fn avg(&self) -> f64 {
+        if self.samples == 0 {
+            0.0
+        } else {
+            self.total / self.samples as f64
+        }
+    }
+
+    pub(crate) fn record(&mut self, latency: f64) {
+        if self.samples == 0 {
+            self.min = latency;
+            self.max = latency;
+        } else {
+            self.min = self.min.min(latency);
+            self.max = self.max.max(latency);
+        }
+        self.samples += 1;
+        self.last = latency;
+        self.total += latency;
+    }
+}
+
+impl AudioThreadPriority {
+    /// Time constraints for a callback which renders `buffer_frames` at `sample_rate`.
+    pub fn real_time(sample_rate: f32, buffer_frames: usize) -> Self {
+        let period_ns = (buffer_frames as f64 / sample_rate as f64 * 1_000_000_000.0) as u64;
+        Self::RealTime {
+            period_ns,
+            computation_ns: period_ns / 2,
+            constraint_ns: period_ns,
+        }
+    }
+}
+
+impl<'a, Memory, Pixels> App<'a, Memory, Pixels>
+where
+    Pixels: 'static,
+    Memory: 'static,
+{
+    pub fn new(
+        memory: Memory,
+        frame_buffer: &'a mut [Pixels],
+        width: usize,
+        height: usize,
+        handle_input: fn(PlatformInput<Memory>),
+        update_and_render: fn(PlatformUpdate<Memory, Pixels>),
+        shared_lib_path: &'a str,
+    ) -> Self {
+        Self {
+            memory,
+            frame_buffer,
+            width,
+            height,
+            handle_input,
+            update_and_render,
+            shared_lib_path,
+            print_handler: None,
+            view: ViewConfig {
+                title: String::from("glazer app"),
+                render_pipeline: None,
+                drag_areas: Vec::new(),
+                color_filters: Vec::new(),
+                post_stages: Vec::new(),
+            },
+            startup_checks: Vec::new(),
+            audio_routes: Vec::new(),
+            #[cfg(feature = "std")]
+            state_file: None,
+            config: Config {
+                window_animations: true,
+                window_shadow: true,
+                ..Default::default()
+            },
+        }
+    }
+
+    /// Adds a "Print..." item to the File menu which prints the current frame.
+    pub fn with_print_support(mut self) -> Self {
+        self.config.print_support = true;
+        self
+    }
+
+    /// Renders the printed image with `on_print` instead of the realtime frame buffer.
+    ///
+    /// `on_print` receives the size to render at, which may be larger than the
+    /// window, and must return `width * height * 4` bytes of RGBA pixels.
+    pub fn with_print_handler(mut self, on_print: PrintHandler<Memory>) -> Self {
+        self.config.print_support = true;
+        self.print_handler = Some(on_print);
+        self
+    }
+
+    /// Adds a Services submenu to the application menu and registers the app as a
+    /// services provider.
+    ///
+    /// Selected text sent to the `processText` service is delivered as
+    /// [`Input::ServiceAction`] with an `action_id` of `com.glazer.processText`. The
+    /// service only shows up in other apps once it is declared under `NSServices` in the
+    /// bundle's Info.plist.
+    pub fn with_services_menu(mut self) -> Self {
+        self.config.services_menu = true;
+        self
+    }
+
+    /// Asks `f` how to proceed when the platform hits a non-fatal error, such as the
+    /// audio device being unavailable.
+    ///
+    /// By default an alert offering to retry or abort is shown.
+    pub fn with_error_dialog(mut self, f: fn(&str) -> ErrorAction) -> Self {
+        self.config.error_dialog = Some(f);
+        self
+    }
+
+    /// Sets the scheduling policy of the audio callback thread.
+    ///
+    /// See [`AudioThreadPriority::real_time`] for values derived from the audio format.
+    pub fn with_audio_callback_thread_priority(mut self, priority: AudioThreadPriority) -> Self {
+        self.config.audio_thread_priority = priority;
+        self
+    }
+
+    /// Initializes audio with `preferred`, or with `fallback` if the device rejects it.
+    ///
+    /// The format in use is reported by [`PlatformUpdate::actual_audio_format`].
+    pub fn with_audio_format_negotiation(
+        mut self,
+        preferred: AudioFormat,
+        fallback: AudioFormat,
+    ) -> Self {
+        self.config.audio_format = preferred;
+        self.config.audio_fallback_format = Some(fallback);
+        self
+    }
+
+    /// Opens an output for each device in `routes` instead of the default output.
+    ///
+    /// The game writes as many channels as the highest routed source channel, and device
+    /// channels without a route are silent. Routing is ignored on the web.
+    pub fn with_audio_routing(mut self, routes: &[AudioRoute]) -> Self {
+        for route in routes {
+            assert_eq!(
+                route.source_channels.len(),
+                route.output_channels.len(),
+                "audio route must map as many source channels as output channels"
+            );
+        }
+        self.audio_routes = routes.to_vec();
+        self
+    }
+
+    /// Runs `check` before the window is created.
+    ///
+    /// Every check runs, and if any fail their messages are shown in an alert and
+    /// [`App::run`] returns [`Error::StartupChecks`] with them, without opening the window.
+    pub fn with_startup_check(mut self, check: StartupCheck) -> Self {
+        self.startup_checks.push(check);
+        self
+    }
+
+    /// Picks the window title from `(locale, title)` pairs using [`system_locale`].
+    ///
+    /// Locales match exactly, e.g. `"ja_JP"`, or by language alone, e.g. `"ja"`. The first
+    /// title is used when none match.
+    pub fn with_localized_title(mut self, titles: &[(&str, &str)]) -> Self {
+        if let Some(title) = localized_title(titles, &system_locale()) {
+            self.view.title = String::from(title);
+        }
+        self
+    }
+
+    /// Routes key presses through the system input method, which reports composed text
+    /// with the `Input::Ime*` events.
+    ///
+    /// Key presses consumed by an in-progress composition are not reported as
+    /// [`Input::Key`]. Position the candidate window with [`set_ime_area`].
+    pub fn with_input_method_editor_enabled(mut self, enabled: bool) -> Self {
+        self.config.input_method_editor = enabled;
+        self
+    }
+
+    /// Panics in debug builds when `update_and_render` allocates.
+    ///
+    /// Allocations are only seen through a [`debug::CountingAllocator`], which a hot
+    /// reloaded game dylib exports with [`export_allocation_stats!`].
+    pub fn assert_no_frame_allocations(mut self, strict: bool) -> Self {
+        self.config.assert_no_frame_allocations = strict;
+        self
+    }
+
+    /// Post-processes each frame with `pipeline` before it is presented.
+    ///
+    /// The frame buffer itself is left untouched, the pipeline writes into a separate
+    /// buffer which is presented instead.
+    pub fn with_render_pipeline(mut self, pipeline: Box<dyn RenderPipeline>) -> Self {
+        self.view.render_pipeline = Some(pipeline);
+        self
+    }
+
+    /// Updates at the display refresh rate divided by a whole number, whichever is
+    /// closest to `fps`, e.g. 60 on a 120Hz ProMotion display.
+    ///
+    /// Frame timer ticks in between are skipped, so `delta` is always the interval between
+    /// presented frames. The cadence is chosen again when the window changes display.
+    pub fn with_preferred_fps(mut self, fps: u32) -> Self {
+        self.config.preferred_fps = Some(fps.max(1));
+        self
+    }
+
+    /// Adds a UI layer at the window's native resolution, see
+    /// [`PlatformUpdate::ui_frame_buffer`].
+    ///
+    /// The layer is drawn over the scaled up frame with straight alpha. It is cleared to
+    /// transparent before each update unless `preserve` is set, and is always cleared
+    /// when its size changes, e.g. on a display with a different scale factor.
+    pub fn with_ui_layer(mut self, preserve: bool) -> Self {
+        self.config.ui_layer = true;
+        self.config.preserve_ui_layer = preserve;
+        self
+    }
+
+    /// Filters each presented frame, after the render pipeline and color filters. It can be
+    /// changed while running with [`set_present_filter`].
+    ///
+    /// The filter runs on the frame scaled up by the largest whole number which fits the
+    /// window, and post stages after [`post_stage::PRESENT_FILTER`] run at that size too.
+    /// Printing uses the unfiltered frame buffer, [`screenshot`] can capture either.
+    pub fn with_present_filter(mut self, filter: PresentFilter) -> Self {
+        self.config.present_filter = filter;
+        self
+    }
+
+    /// Configures timer coalescing and thread priority, left as the OS defaults if unset.
+    ///
+    /// The audio render thread is configured separately with
+    /// [`App::with_audio_callback_thread_priority`].
+    pub fn latency_mode(mut self, mode: LatencyMode) -> Self {
+        self.config.latency_mode = Some(mode);
+        self
+    }
+
+    /// Renders with OpenGL through [`PlatformUpdate::gl_context`] instead of the frame
+    /// buffer.
+    ///
+    /// The context is double buffered with a depth buffer and uses the 3.2 core profile.
+    /// The frame buffer is still passed to `update_and_render`, but is not presented.
+    pub fn with_opengl_context(mut self) -> Self {
+        self.config.opengl = true;
+        self
+    }
+
+    /// Cycles the presented frame through each [`ColorFilter`] and back to unfiltered
+    /// when `key` is pressed, logging the active filter.
+    ///
+    /// Filters apply after the render pipeline. The key is consumed, see
+    /// [`App::with_shortcut_key_delivery`].
+    #[cfg(feature = "std")]
+    pub fn with_color_filter_key(mut self, key: KeyCode) -> Self {
+        self.config.color_filter_key = Some(key);
+        self.view.color_filters = ColorFilter::ALL
+            .iter()
+            .map(|filter| (*filter, ColorMatrixPipeline::from_filter(*filter)))
+            .collect();
+        self
+    }
+
+    /// Enables the system animations when the window opens and closes, on by default.
+    pub fn with_window_animations(mut self, enabled: bool) -> Self {
+        self.config.window_animations = enabled;
+        self
+    }
+
+    /// Lets the user resize the window, reported with [`Input::WindowResized`].
+    ///
+    /// The frame buffer keeps its size and is stretched to fill the window. The UI layer
+    /// from [`App::with_ui_layer`] follows the window's size.
+    pub fn with_resizable_window(mut self, enabled: bool) -> Self {
+        self.config.resizable_window = enabled;
+        self
+    }
+
+    /// Extends the frame under a transparent title bar, so only the window controls are
+    /// drawn over it.
+    ///
+    /// The frame keeps its size, [`PlatformUpdate::safe_area_insets`] gives the part
+    /// covered by the title bar.
+    pub fn with_transparent_titlebar(mut self, enabled: bool) -> Self {
+        self.config.transparent_titlebar = enabled;
+        self
+    }
+
+    /// Leaves the title text out of the title bar, the window keeps its title in the
+    /// Window menu and Mission Control.
+    pub fn with_title_hidden(mut self, hidden: bool) -> Self {
+        self.config.hide_title = hidden;
+        self
+    }
+
+    /// Draws the system shadow around the window, on by default.
+    pub fn with_window_shadow(mut self, enabled: bool) -> Self {
+        self.config.window_shadow = enabled;
+        self
+    }
+
+    /// Toggles full screen with a decisive trackpad pinch, out to enter and in to leave, or
+    /// a smart zoom double tap.
+    pub fn with_touchpad_zoom_to_fullscreen(mut self, enabled: bool) -> Self {
+        self.config.touchpad_zoom_to_fullscreen = enabled;
+        self
+    }
+
+    /// Runs `stage` on each presented frame, in place, after the render pipeline.
+    ///
+    /// Stages run in ascending `priority` and stages with the same priority run in the
+    /// order they were added. See [`post_stage`] for the priorities of the built-in
+    /// stages, e.g. a filter which the performance overlay should not be affected by needs
+    /// a priority below [`post_stage::PERFORMANCE_OVERLAY`].
+    ///
+    /// While a present filter is set, stages after [`post_stage::PRESENT_FILTER`] get the
+    /// scaled up frame, so `width` and `height` can differ from the frame buffer's.
+    pub fn add_post_stage(mut self, priority: i32, stage: fn(&mut [Pixels], usize, usize)) -> Self {
+        let stage = move |pixels: &mut [u8], width, height| {
+            // `run` checks that `Pixels` is 4 bytes, so its alignment is at most 4, which
+            // the platform's front buffer is allocated with.
+            debug_assert!(pixels.as_ptr().cast::<u32>().is_aligned());
+            let len = pixels.len() / 4;
+            let pixels =
+                unsafe { core::slice::from_raw_parts_mut(pixels.as_mut_ptr().cast(), len) };
+            stage(pixels, width, height);
+        };
+        self.view.post_stages.push((priority, Box::new(stage)));
+        self
+    }
+
+    /// Moves the window when a mouse press starts inside any of `rects`.
+    ///
+    /// Presses which start a drag are not reported to the game.
+    pub fn with_window_drag_areas(mut self, rects: &[Rect]) -> Self {
+        self.view.drag_areas = rects.to_vec();
+        self
+    }
+
+    /// Shows the Metal Performance HUD over Metal layers in the window, off by default.
+    ///
+    /// This sets `MTL_HUD_ENABLED` for the process when the app starts, which is how
+    /// Metal enables the HUD, rather than `MTLShowPerformanceStats` or the device's
+    /// `isHeadless`, which is read only. Child processes inherit the variable.
+    ///
+    /// The frame buffer is drawn without Metal, so this has no effect unless the game
+    /// renders with Metal itself.
+    pub fn with_metal_hud(mut self, enabled: bool) -> Self {
+        self.config.metal_hud = enabled;
+        self
+    }
+
+    /// Reports the play/pause, next, previous, volume and mute keys as [`Input::Key`]
+    /// while the app is active.
+    ///
+    /// Captured keys no longer control the system, e.g. the volume keys stop changing the
+    /// volume.
+    pub fn with_media_key_capture(mut self, enabled: bool) -> Self {
+        self.config.media_keys = enabled;
+        self
+    }
+
+    /// Limits the total [`Input::MouseMoved`] distance delivered before each update to
+    /// `max` points.
+    ///
+    /// Motion during a long frame, e.g. a loading hitch, is still delivered as individual
+    /// events, but once their combined length reaches `max` the rest are shortened or
+    /// dropped, so a camera driven by mouse deltas can not spin far in a single frame.
+    pub fn with_max_mouse_delta_per_frame(mut self, max: f32) -> Self {
+        self.config.max_mouse_delta = Some(max);
+        self
+    }
+
+    /// Sets how [`Input::MouseMoved`] deltas are measured, [`MouseInputMode::Accelerated`]
+    /// by default.
+    pub fn with_mouse_input_mode(mut self, mode: MouseInputMode) -> Self {
+        self.config.mouse_input_mode = mode;
+        self
+    }
+
+    /// Stops calling `update_and_render` while the window is not focused, leaving the last
+    /// frame on screen.
+    ///
+    /// Audio already queued fades out instead of cutting off, and fades back in once the
+    /// window is focused again. Off by default, for games which handle pausing themselves.
+    pub fn with_pause_on_focus_loss(mut self, enabled: bool) -> Self {
+        self.config.pause_on_focus_loss = enabled;
+        self
+    }
+
+    /// Sets [`PlatformUpdate::idle`] once no input has arrived for `threshold` seconds,
+    /// e.g. to start an attract loop.
+    ///
+    /// Mouse motion only counts as input once it adds up to `mouse_dead_zone` points, so
+    /// sensor jitter does not keep the game awake.
+    pub fn with_idle_detection(mut self, threshold: f32, mouse_dead_zone: f32) -> Self {
+        self.config.idle_threshold = Some(threshold);
+        self.config.idle_mouse_dead_zone = mouse_dead_zone;
+        self
+    }
+
+    /// Also delivers keys consumed by a platform shortcut, e.g.
+    /// [`App::with_color_filter_key`], to `handle_input`. Off by default.
+    ///
+    /// Menu shortcuts such as Cmd+Q are handled by the menu and never delivered.
+    pub fn with_shortcut_key_delivery(mut self, enabled: bool) -> Self {
+        self.config.deliver_shortcut_keys = enabled;
+        self
+    }
+
+    /// Sets what the window shows before the first frame is presented.
+    ///
+    /// The first present can be delayed while the game loads with
+    /// [`hold_first_present`].
+    pub fn with_first_present(mut self, first_present: FirstPresent) -> Self {
+        self.config.first_present = first_present;
+        self
+    }
+
+    /// Draws frame timing and audio graphs over the presented frame.
+    ///
+    /// The overlay is drawn after the render pipeline, so the frame buffer passed to
+    /// `update_and_render` is left untouched. Nothing is recorded or drawn unless this is
+    /// called, in release builds too.
+    pub fn with_performance_overlay(mut self, config: PerformanceOverlayConfig) -> Self {
+        self.config.performance_overlay = Some(config);
+        self
+    }
+
+    /// Measures the time from pressing `key` to presenting the next frame.
+    ///
+    /// The frame is marked with a white square in the top left corner and each
+    /// measurement is logged. The running totals are reported by
+    /// [`PlatformUpdate::latency`].
+    pub fn with_latency_probe(mut self, key: KeyCode) -> Self {
+        self.config.latency_probe = Some(key);
+        self
+    }
+
+    /// Saves the game's `Memory` and the window position to `path` when the app quits,
+    /// and restores them before the first frame when launched with `--resume`.
+    ///
+    /// See [`App::with_resume`] to always restore, and [`App::with_state_save_interval`]
+    /// to also save while running. A restored window position which is no longer on any
+    /// screen is ignored and the window is centered.
+    ///
+    /// A file saved with a different `version`, or a `Memory` of a different size, is
+    /// ignored with a logged error and the app starts fresh, as it does when the file is
+    /// missing or corrupt. Bump `version` whenever the meaning of `Memory`'s bytes changes.
+    ///
+    /// # Safety
+    ///
+    /// `Memory` must be plain data, valid for any bytes saved by an earlier run with the
+    /// same `version`. It must not contain padding, pointers or references.
+    #[cfg(feature = "std")]
+    pub unsafe fn with_state_file(mut self, path: &str, version: u32) -> Self {
+        self.state_file = Some(state_file::StateFile {
+            path: String::from(path),
+            version,
+            last_save: 0.0,
+        });
+        self
+    }
+
+    /// Restores the state file from [`App::with_state_file`] on launch even without the
+    /// `--resume` argument.
+    #[cfg(feature = "std")]
+    pub fn with_resume(mut self, enabled: bool) -> Self {
+        self.config.resume = enabled;
+        self
+    }
+
+    /// Also saves the state file from [`App::with_state_file`] every `seconds` of game
+    /// time, so a power loss only loses the progress since the last save.
+    #[cfg(feature = "std")]
+    pub fn with_state_save_interval(mut self, seconds: f32) -> Self {
+        self.config.state_save_interval = Some(seconds);
+        self
+    }
+
+    /// Runs the app until it is quit, with the Quit menu item, Cmd+Q, or by closing the
+    /// window, then returns the `Memory` value.
+    ///
+    /// Audio is stopped and the window is hidden before returning. Quitting from outside
+    /// the app, e.g. logging out, still exits the process without returning.
+    pub fn run(self) -> Result<Memory, Error> {
+        assert!(
+            core::mem::size_of::<Pixels>() == 4,
+            "`Pixels` must be 4 bytes"
+        );
+        platform::run(self)
+    }
+}
+
+/// The user's locale identifier, e.g. `"en_US"`.
+pub fn system_locale() -> String {
+    platform::system_locale()
+}
+
+/// Tells the input method where text is being inserted, in points from the top left of
+/// the window's content.
+///
+/// The composition and candidate windows are placed next to this area, see
+/// [`App::with_input_method_editor_enabled`].
+pub fn set_ime_area(x: f32, y: f32, w: f32, h: f32) {
+    platform::set_ime_area(x, y, w, h);
+}
+
+/// Sets the window's opacity from `0.0`, transparent, to `1.0`, opaque.
+///
+/// Applied at the start of the next frame.
+pub fn set_window_opacity(opacity: f32) {
+    platform::set_window_opacity(opacity);
+}
+
+/// Writes the last 5 seconds of platform events to `path`, returning whether it was
+/// written, e.g. to attach to a bug report.
+///
+/// Inputs, frame deltas, error log lines and audio underruns are recorded. The same log
+/// is written to `crash-<timestamp>.log` in the working directory after a panic.
+pub fn dump_event_log(path: &str) -> bool {
+    platform::dump_event_log(path)
+}
+
+/// Replaces the [`PresentFilter`], applied from the next presented frame.
+pub fn set_present_filter(filter: PresentFilter) {
+    platform::set_present_filter(filter);
+}
+
+/// Which frame [`screenshot`] captures.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScreenshotStage {
+    /// The frame the [`PresentFilter`] read, scaled up to the presented size but not
+    /// filtered. The presented frame when there is no present filter.
+    BeforePresentFilter,
+    /// The frame as presented, including post stages after the present filter.
+    Presented,
+}
+
+/// `width * height` RGBA pixels, see [`screenshot`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Screenshot {
+    pub pixels: Vec<u8>,
+    pub width: usize,
+    pub height: usize,
+}
+
+/// Copies the last presented frame, `None` before the first present or when the game
+/// presents with OpenGL.
+pub fn screenshot(stage: ScreenshotStage) -> Option<Screenshot> {
+    platform::screenshot(stage)
+}
+
+/// Keeps the cursor inside the window's content while the window is focused, e.g. for
+/// edge scrolling, without hiding it or switching to relative motion.
+///
+/// Mouse deltas are still delivered at the edges. [`Input::CursorAtEdge`] reports which
+/// edges the cursor is pressed against.
+pub fn set_cursor_confined(confined: bool) {
+    platform::set_cursor_confined(confined);
+}
+
+/// Shows or hides the cursor while it is over the window. The same as issuing
+/// [`Command::SetCursorVisible`].
+///
+/// Only one hide is ever outstanding with AppKit, so repeated calls can not get out of
+/// balance. A hidden cursor is shown while the window is not focused, and hidden again
+/// when focus returns.
+pub fn set_cursor_visible(visible: bool) {
+    platform::set_cursor_visible(visible);
+}
+
+/// A cursor image, see [`set_custom_cursor`]. Clones share the image, which is released
+/// with the last of them.
+#[derive(Debug, Clone)]
+pub struct CustomCursor(platform::CustomCursor);
+
+impl CustomCursor {
+    /// A cursor from `width * height` straight alpha RGBA pixels, with its hotspot
+    /// `hotspot_x`, `hotspot_y` pixels from the top left. One image pixel is one point.
+    pub fn from_rgba(
+        data: &[u8],
+        width: u32,
+        height: u32,
+        hotspot_x: u32,
+        hotspot_y: u32,
+    ) -> Result<Self, Error> {
+        let (w, h) = (width as usize, height as usize);
+        if w == 0 || h == 0 || data.len() != w * h * 4 {
+            return Err(Error::InvalidCursor(alloc::format!(
+                "{} bytes for a {width}x{height} image",
+                data.len()
+            )));
+        }
+        if hotspot_x >= width || hotspot_y >= height {
+            return Err(Error::InvalidCursor(alloc::format!(
+                "hotspot {hotspot_x}, {hotspot_y} is outside the {width}x{height} image"
+            )));
+        }
+        platform::CustomCursor::from_rgba(data, w, h, (hotspot_x, hotspot_y))
+            .map(Self)
+            .ok_or_else(|| Error::InvalidCursor(String::from("the platform rejected the image")))
+    }
+}
+
+/// Replaces the cursor over the window with `cursor`, until the next
+/// [`Command::SetCursor`].
+pub fn set_custom_cursor(cursor: &CustomCursor) {
+    platform::set_custom_cursor(&cursor.0);
+}
+
+/// Hides the cursor and holds it at the window's center while the window is focused, for
+/// first person cameras. The same as issuing [`Command::SetCursorGrab`].
+///
+/// [`Input::MouseMoved`] is still delivered. The lock is lifted while the window is not
+/// focused and once `run` returns, and unlocking shows the cursor where it was locked.
+pub fn set_cursor_locked(locked: bool) {
+    platform::set_cursor_locked(locked);
+}
+
+/// Excludes the window's contents from screenshots, screen recording and screen sharing.
+///
+/// Applied at the start of the next frame.
+pub fn set_content_protection(enabled: bool) {
+    platform::set_content_protection(enabled);
+}
+
+/// Delays the first present, until [`release_first_present`] is called or 5 seconds
+/// have passed.
+///
+/// Must be called before the first `update_and_render` returns.
+pub fn hold_first_present() {
+    platform::set_first_present_held(true);
+}
+
+/// Presents the next frame if the first present was held by [`hold_first_present`].
+pub fn release_first_present() {
+    platform::set_first_present_held(false);
+}
+
+fn localized_title<'t>(titles: &[(&str, &'t str)], locale: &str) -> Option<&'t str> {
+    // `en-US` and `en_US` are both common spellings.
+    let normalize = |c: char| {
+        if c == '-' {
+            '_'
+        } else {
+            c.to_ascii_lowercase()
+        }
+    };
+    let eq = |a: &str, b: &str| a.chars().map(normalize).eq(b.chars().map(normalize));
+    fn language(locale: &str) -> &str {
+        locale.split(['_', '-']).next().unwrap_or(locale)
+    }
+
+    titles
+        .iter()
+        .find(|(l, _)| eq(l, locale))
+        .or_else(|| {
+            titles
+                .iter()
+                .find(|(l, _)| eq(language(l), language(locale)))
+        })
+        .or(titles.first())
+        .map(|(_, title)| *title)
+}
+
+/// OpenGL context drawing into the window, see [`App::with_opengl_context`].
+#[derive(Debug)]
+pub struct OpenGLContext {
+    pub(crate) context: core::ptr::NonNull<core::ffi::c_void>,
+}
+
+impl OpenGLContext {
+    /// Makes this the current context, required before issuing OpenGL calls.
+    pub fn make_current(&self) {
+        platform::opengl_make_current(self);
+    }
+
+    /// Presents the back buffer.
+    pub fn swap_buffers(&self) {
+        platform::opengl_swap_buffers(self);
+    }
 }
 
 #[repr(C)]
@@ -39,26 +1066,70 @@ pub struct PlatformUpdate<'a, T, Pixels> {
     // logic
     pub memory: &'a mut T,
     pub delta: f32,
+    /// `delta` without the precision loss of `f32`.
+    pub delta_f64: f64,
+    /// Seconds accumulated from every `delta` since the first update.
+    pub time: f64,
+    /// Seconds of `time` since the last input was delivered.
+    pub seconds_since_last_input: f32,
+    /// `false` unless enabled with [`App::with_idle_detection`].
+    pub idle: bool,
+    /// Requests to the platform, applied in order once `update_and_render` returns.
+    pub commands: Commands<'a>,
+    /// Connected game controllers, read just before this update.
+    pub gamepads: Gamepads,
+    /// Where the cursor is in frame buffer pixels from the top left, read just before
+    /// this update. Outside the frame buffer while the cursor is outside the window.
+    pub mouse_x: f32,
+    pub mouse_y: f32,
 
     // graphics
     pub frame_buffer: &'a mut [Pixels],
     pub width: usize,
     pub height: usize,
+    /// `Some` when enabled with [`App::with_opengl_context`].
+    pub gl_context: Option<&'a OpenGLContext>,
+    /// Frame buffer pixels at each edge covered by window chrome, such as a title bar
+    /// with [`App::with_transparent_titlebar`], which should not hold controls.
+    pub safe_area_insets: Insets,
+    /// `Some` when enabled with [`App::with_ui_layer`], `ui_width * ui_height` pixels
+    /// drawn over `frame_buffer` at the window's native resolution.
+    ///
+    /// Post stages and the render pipeline only see `frame_buffer`.
+    pub ui_frame_buffer: Option<&'a mut [Pixels]>,
+    pub ui_width: usize,
+    pub ui_height: usize,
 
     // audio
     pub samples: &'a mut [i16],
     pub sample_rate: f32,
     pub channels: usize,
+    /// Format negotiated with the audio device, see [`App::with_audio_format_negotiation`].
+    pub actual_audio_format: AudioFormat,
+
+    // debug
+    /// Latency measurements, `None` unless [`App::with_latency_probe`] is enabled.
+    pub latency: Option<LatencyStats>,
 }
 
+/// An input event for `handle_input`.
+///
+/// Every input received before an update is delivered before that update, in the order
+/// it arrived.
 #[derive(Debug)]
 pub struct PlatformInput<'a, T> {
     pub memory: &'a mut T,
     pub input: Input,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
 pub enum Input {
+    /// A key press or release.
+    ///
+    /// Menu shortcuts never arrive here, and neither do platform shortcut keys unless
+    /// [`App::with_shortcut_key_delivery`] is enabled. Keys pressed with Command are
+    /// delivered, but never reach the input method, so they do not produce text.
     Key {
         code: KeyCode,
         modifiers: KeyModifiers,
@@ -69,9 +1140,188 @@ pub enum Input {
         dx: f32,
         dy: f32,
     },
+    /// Scrolling by `dx`, `dy` points, positive when the content should move right and
+    /// down.
+    ///
+    /// Scroll wheel lines are converted to points, and the direction follows the user's
+    /// natural scrolling setting.
+    MouseScroll {
+        dx: f32,
+        dy: f32,
+        phase: ScrollPhase,
+    },
+    /// A mouse button press or release, at `x`, `y` in frame buffer pixels from the top
+    /// left.
+    ///
+    /// `clicks` counts presses in quick succession, 2 for the press of a double click.
+    MouseButton {
+        button: MouseButton,
+        modifiers: KeyModifiers,
+        pressed: bool,
+        x: f32,
+        y: f32,
+        clicks: u32,
+    },
+    /// Another app sent a service request, see [`App::with_services_menu`].
+    ///
+    /// `action_id` names the service, `com.glazer.processText` for selected text, which
+    /// is sent as UTF-8 `data`.
+    ServiceAction {
+        action_id: String,
+        data: Vec<u8>,
+    },
+    /// Files or folders dragged from the OS were dropped on the window, as absolute
+    /// paths.
+    FileDrop {
+        paths: Vec<String>,
+    },
+    /// The window was moved to another Space, or a Space transition such as Mission
+    /// Control finished.
+    WindowMoved,
+    /// The window moved to a different display, monitor information should be
+    /// queried again.
+    DisplayChanged,
+    /// The window's content was resized to `width * height` pixels at the display's
+    /// native resolution, see [`App::with_resizable_window`].
+    WindowResized {
+        width: usize,
+        height: usize,
+    },
+    /// The window gained or lost keyboard focus.
+    ///
+    /// Keys held when focus is lost are not released with [`Input::Key`], so any held
+    /// key state should be cleared when `focused` is `false`.
+    WindowFocused {
+        focused: bool,
+    },
+    /// The edges a confined cursor is pressed against changed, empty once it moves away
+    /// from them, see [`set_cursor_confined`].
+    CursorAtEdge {
+        edges: CursorEdges,
+    },
+    /// A game controller connected and took `slot` in [`PlatformUpdate::gamepads`].
+    GamepadConnected {
+        slot: usize,
+    },
+    /// The game controller in `slot` disconnected, freeing the slot.
+    GamepadDisconnected {
+        slot: usize,
+    },
+    /// The input method started composing text.
+    ImeStartComposition,
+    /// The text being composed changed, `cursor` is a byte offset into `text`.
+    ImeUpdateComposition {
+        text: String,
+        cursor: usize,
+    },
+    /// The input method finished composing, or text was typed directly.
+    ImeCommit {
+        text: String,
+    },
+}
+
+/// Where a scroll is in a trackpad gesture, see [`Input::MouseScroll`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScrollPhase {
+    /// Fingers touched the trackpad, scrolling may follow.
+    MayBegin,
+    Began,
+    Changed,
+    /// The fingers lifted, momentum scrolling may follow.
+    Ended,
+    /// Scrolling continues after the fingers lifted, until it slows to a stop.
+    Momentum,
+    /// A scroll wheel, or a device without gestures.
+    Wheel,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MouseButton {
+    Left,
+    Right,
+    Middle,
+    Back,
+    Forward,
+    /// Any further button, numbered from 5.
+    Other(u8),
+}
+
+impl MouseButton {
+    /// Stable name for config files, e.g. `"left"` or `"back"`, and `"mouse5"` for
+    /// `Other(5)`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Left => "left",
+            Self::Right => "right",
+            Self::Middle => "middle",
+            Self::Back => "back",
+            Self::Forward => "forward",
+            Self::Other(number) => {
+                let (bytes, len) = &OTHER_MOUSE_BUTTON_NAMES[*number as usize];
+                core::str::from_utf8(&bytes[..*len]).expect("names are ASCII")
+            }
+        }
+    }
+
+    /// Parses a name produced by [`MouseButton::as_str`], ignoring case and surrounding
+    /// whitespace.
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(s: &str) -> Option<Self> {
+        let s = s.trim();
+        if let Some(number) = s
+            .get(..5)
+            .filter(|prefix| prefix.eq_ignore_ascii_case("mouse"))
+            .map(|_| &s[5..])
+            .filter(|number| number.bytes().all(|byte| byte.is_ascii_digit()))
+            .and_then(|number| number.parse().ok())
+        {
+            return Some(Self::Other(number));
+        }
+        [
+            Self::Left,
+            Self::Right,
+            Self::Middle,
+            Self::Back,
+            Self::Forward,
+        ]
+        .into_iter()
+        .find(|button| button.as_str().eq_ignore_ascii_case(s))
+    }
+}
+
+// `"mouse0"` to `"mouse255"` and their lengths, so `MouseButton::as_str` can return a
+// static name for every `MouseButton::Other`.
+static OTHER_MOUSE_BUTTON_NAMES: [([u8; 8], usize); 256] = {
+    let mut names = [(*b"mouse\0\0\0", 0); 256];
+    let mut number = 0;
+    while number < 256 {
+        let digits = [number / 100, number / 10 % 10, number % 10];
+        // without leading zeros
+        let mut digit = if number >= 100 {
+            0
+        } else if number >= 10 {
+            1
+        } else {
+            2
+        };
+        let mut len = 5;
+        while digit < 3 {
+            names[number].0[len] = b'0' + digits[digit] as u8;
+            len += 1;
+            digit += 1;
+        }
+        names[number].1 = len;
+        number += 1;
+    }
+    names
+};
+
+/// A physical key, named after its position on a US keyboard.
+///
+/// New keys may be added, so matches need a wildcard arm. Prefer the classifiers, e.g.
+/// [`KeyCode::is_letter`], to listing keys.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
 pub enum KeyCode {
     KeyA,
     KeyB,
@@ -128,12 +1378,14 @@ pub enum KeyCode {
 
     CapsLock,
     LeftAlt,
+    LeftCommand,
     LeftControl,
     LeftShift,
     LockingCapsLock,
     LockingNumLock,
     LockingScrollLock,
     RightAlt,
+    RightCommand,
     RightControl,
     RightShift,
     ScrollLock,
@@ -153,9 +1405,406 @@ pub enum KeyCode {
     Return,
     Tab,
 
+    F1,
+    F2,
+    F3,
+    F4,
+    F5,
+    F6,
+    F7,
+    F8,
+    F9,
+    F10,
+    F11,
+    F12,
+    F13,
+    F14,
+    F15,
+    F16,
+    F17,
+    F18,
+    F19,
+
+    Numpad0,
+    Numpad1,
+    Numpad2,
+    Numpad3,
+    Numpad4,
+    Numpad5,
+    Numpad6,
+    Numpad7,
+    Numpad8,
+    Numpad9,
+    NumpadAdd,
+    NumpadSubtract,
+    NumpadMultiply,
+    NumpadDivide,
+    NumpadDecimal,
+    NumpadEquals,
+    NumpadEnter,
+    NumpadClear,
+
+    MediaPlayPause,
+    MediaNext,
+    MediaPrevious,
+    VolumeUp,
+    VolumeDown,
+    Mute,
+
     Unknown,
 }
 
+impl KeyCode {
+    /// Every key code, in declaration order.
+    pub const ALL: [Self; 121] = [
+        Self::KeyA,
+        Self::KeyB,
+        Self::KeyC,
+        Self::KeyD,
+        Self::KeyE,
+        Self::KeyF,
+        Self::KeyG,
+        Self::KeyH,
+        Self::KeyI,
+        Self::KeyJ,
+        Self::KeyK,
+        Self::KeyL,
+        Self::KeyM,
+        Self::KeyN,
+        Self::KeyO,
+        Self::KeyP,
+        Self::KeyQ,
+        Self::KeyR,
+        Self::KeyS,
+        Self::KeyT,
+        Self::KeyU,
+        Self::KeyV,
+        Self::KeyW,
+        Self::KeyX,
+        Self::KeyY,
+        Self::KeyZ,
+        Self::Num0,
+        Self::Num1,
+        Self::Num2,
+        Self::Num3,
+        Self::Num4,
+        Self::Num5,
+        Self::Num6,
+        Self::Num7,
+        Self::Num8,
+        Self::Num9,
+        Self::Backslash,
+        Self::CloseBracket,
+        Self::Comma,
+        Self::EqualSign,
+        Self::Hyphen,
+        Self::NonUSBackslash,
+        Self::NonUSPound,
+        Self::OpenBracket,
+        Self::Period,
+        Self::Quote,
+        Self::Semicolon,
+        Self::Separator,
+        Self::Slash,
+        Self::Spacebar,
+        Self::CapsLock,
+        Self::LeftAlt,
+        Self::LeftCommand,
+        Self::LeftControl,
+        Self::LeftShift,
+        Self::LockingCapsLock,
+        Self::LockingNumLock,
+        Self::LockingScrollLock,
+        Self::RightAlt,
+        Self::RightCommand,
+        Self::RightControl,
+        Self::RightShift,
+        Self::ScrollLock,
+        Self::LeftArrow,
+        Self::RightArrow,
+        Self::UpArrow,
+        Self::DownArrow,
+        Self::PageUp,
+        Self::PageDown,
+        Self::Home,
+        Self::End,
+        Self::DeleteForward,
+        Self::DeleteOrBackspace,
+        Self::Escape,
+        Self::Insert,
+        Self::Return,
+        Self::Tab,
+        Self::F1,
+        Self::F2,
+        Self::F3,
+        Self::F4,
+        Self::F5,
+        Self::F6,
+        Self::F7,
+        Self::F8,
+        Self::F9,
+        Self::F10,
+        Self::F11,
+        Self::F12,
+        Self::F13,
+        Self::F14,
+        Self::F15,
+        Self::F16,
+        Self::F17,
+        Self::F18,
+        Self::F19,
+        Self::Numpad0,
+        Self::Numpad1,
+        Self::Numpad2,
+        Self::Numpad3,
+        Self::Numpad4,
+        Self::Numpad5,
+        Self::Numpad6,
+        Self::Numpad7,
+        Self::Numpad8,
+        Self::Numpad9,
+        Self::NumpadAdd,
+        Self::NumpadSubtract,
+        Self::NumpadMultiply,
+        Self::NumpadDivide,
+        Self::NumpadDecimal,
+        Self::NumpadEquals,
+        Self::NumpadEnter,
+        Self::NumpadClear,
+        Self::MediaPlayPause,
+        Self::MediaNext,
+        Self::MediaPrevious,
+        Self::VolumeUp,
+        Self::VolumeDown,
+        Self::Mute,
+        Self::Unknown,
+    ];
+
+    /// Stable name for config files, e.g. `"a"`, `"left_shift"` or `"page_up"`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::KeyA => "a",
+            Self::KeyB => "b",
+            Self::KeyC => "c",
+            Self::KeyD => "d",
+            Self::KeyE => "e",
+            Self::KeyF => "f",
+            Self::KeyG => "g",
+            Self::KeyH => "h",
+            Self::KeyI => "i",
+            Self::KeyJ => "j",
+            Self::KeyK => "k",
+            Self::KeyL => "l",
+            Self::KeyM => "m",
+            Self::KeyN => "n",
+            Self::KeyO => "o",
+            Self::KeyP => "p",
+            Self::KeyQ => "q",
+            Self::KeyR => "r",
+            Self::KeyS => "s",
+            Self::KeyT => "t",
+            Self::KeyU => "u",
+            Self::KeyV => "v",
+            Self::KeyW => "w",
+            Self::KeyX => "x",
+            Self::KeyY => "y",
+            Self::KeyZ => "z",
+            Self::Num0 => "0",
+            Self::Num1 => "1",
+            Self::Num2 => "2",
+            Self::Num3 => "3",
+            Self::Num4 => "4",
+            Self::Num5 => "5",
+            Self::Num6 => "6",
+            Self::Num7 => "7",
+            Self::Num8 => "8",
+            Self::Num9 => "9",
+            Self::Backslash => "backslash",
+            Self::CloseBracket => "close_bracket",
+            Self::Comma => "comma",
+            Self::EqualSign => "equal_sign",
+            Self::Hyphen => "hyphen",
+            Self::NonUSBackslash => "non_us_backslash",
+            Self::NonUSPound => "non_us_pound",
+            Self::OpenBracket => "open_bracket",
+            Self::Period => "period",
+            Self::Quote => "quote",
+            Self::Semicolon => "semicolon",
+            Self::Separator => "separator",
+            Self::Slash => "slash",
+            Self::Spacebar => "space",
+            Self::CapsLock => "caps_lock",
+            Self::LeftAlt => "left_alt",
+            Self::LeftCommand => "left_command",
+            Self::LeftControl => "left_control",
+            Self::LeftShift => "left_shift",
+            Self::LockingCapsLock => "locking_caps_lock",
+            Self::LockingNumLock => "locking_num_lock",
+            Self::LockingScrollLock => "locking_scroll_lock",
+            Self::RightAlt => "right_alt",
+            Self::RightCommand => "right_command",
+            Self::RightControl => "right_control",
+            Self::RightShift => "right_shift",
+            Self::ScrollLock => "scroll_lock",
+            Self::LeftArrow => "left",
+            Self::RightArrow => "right",
+            Self::UpArrow => "up",
+            Self::DownArrow => "down",
+            Self::PageUp => "page_up",
+            Self::PageDown => "page_down",
+            Self::Home => "home",
+            Self::End => "end",
+            Self::DeleteForward => "delete_forward",
+            Self::DeleteOrBackspace => "backspace",
+            Self::Escape => "escape",
+            Self::Insert => "insert",
+            Self::Return => "return",
+            Self::Tab => "tab",
+            Self::F1 => "f1",
+            Self::F2 => "f2",
+            Self::F3 => "f3",
+            Self::F4 => "f4",
+            Self::F5 => "f5",
+            Self::F6 => "f6",
+            Self::F7 => "f7",
+            Self::F8 => "f8",
+            Self::F9 => "f9",
+            Self::F10 => "f10",
+            Self::F11 => "f11",
+            Self::F12 => "f12",
+            Self::F13 => "f13",
+            Self::F14 => "f14",
+            Self::F15 => "f15",
+            Self::F16 => "f16",
+            Self::F17 => "f17",
+            Self::F18 => "f18",
+            Self::F19 => "f19",
+            Self::Numpad0 => "numpad_0",
+            Self::Numpad1 => "numpad_1",
+            Self::Numpad2 => "numpad_2",
+            Self::Numpad3 => "numpad_3",
+            Self::Numpad4 => "numpad_4",
+            Self::Numpad5 => "numpad_5",
+            Self::Numpad6 => "numpad_6",
+            Self::Numpad7 => "numpad_7",
+            Self::Numpad8 => "numpad_8",
+            Self::Numpad9 => "numpad_9",
+            Self::NumpadAdd => "numpad_add",
+            Self::NumpadSubtract => "numpad_subtract",
+            Self::NumpadMultiply => "numpad_multiply",
+            Self::NumpadDivide => "numpad_divide",
+            Self::NumpadDecimal => "numpad_decimal",
+            Self::NumpadEquals => "numpad_equals",
+            Self::NumpadEnter => "numpad_enter",
+            Self::NumpadClear => "numpad_clear",
+            Self::MediaPlayPause => "media_play_pause",
+            Self::MediaNext => "media_next",
+            Self::MediaPrevious => "media_previous",
+            Self::VolumeUp => "volume_up",
+            Self::VolumeDown => "volume_down",
+            Self::Mute => "mute",
+            Self::Unknown => "unknown",
+        }
+    }
+
+    /// Parses a name produced by [`KeyCode::as_str`], ignoring case and surrounding
+    /// whitespace.
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(s: &str) -> Option<Self> {
+        let s = s.trim();
+        Self::ALL
+            .into_iter()
+            .find(|code| code.as_str().eq_ignore_ascii_case(s))
+    }
+
+    /// `KeyA` through `KeyZ`.
+    pub fn is_letter(&self) -> bool {
+        (Self::KeyA as u8..=Self::KeyZ as u8).contains(&(*self as u8))
+    }
+
+    /// `Num0` through `Num9`.
+    pub fn is_digit(&self) -> bool {
+        (Self::Num0 as u8..=Self::Num9 as u8).contains(&(*self as u8))
+    }
+
+    /// `F1` through `F19`.
+    pub fn is_function(&self) -> bool {
+        (Self::F1 as u8..=Self::F19 as u8).contains(&(*self as u8))
+    }
+
+    /// `Numpad0` through `NumpadClear`.
+    pub fn is_numpad(&self) -> bool {
+        (Self::Numpad0 as u8..=Self::NumpadClear as u8).contains(&(*self as u8))
+    }
+
+    /// Shift, control, alt, command and caps lock.
+    pub fn is_modifier(&self) -> bool {
+        matches!(
+            self,
+            Self::LeftShift
+                | Self::RightShift
+                | Self::LeftControl
+                | Self::RightControl
+                | Self::LeftAlt
+                | Self::RightAlt
+                | Self::LeftCommand
+                | Self::RightCommand
+                | Self::CapsLock
+        )
+    }
+
+    /// The arrow keys, page up, page down, home and end.
+    pub fn is_navigation(&self) -> bool {
+        matches!(
+            self,
+            Self::LeftArrow
+                | Self::RightArrow
+                | Self::UpArrow
+                | Self::DownArrow
+                | Self::PageUp
+                | Self::PageDown
+                | Self::Home
+                | Self::End
+        )
+    }
+
+    /// The character typed by the key on a US layout without shift, `None` for keys
+    /// which do not type a printable character.
+    ///
+    /// Use the text input events for text entry, this ignores the user's layout.
+    pub fn to_char_unshifted(&self) -> Option<char> {
+        if self.is_letter() {
+            return Some((b'a' + (*self as u8 - Self::KeyA as u8)) as char);
+        }
+        if self.is_digit() {
+            return Some((b'0' + (*self as u8 - Self::Num0 as u8)) as char);
+        }
+        if (Self::Numpad0 as u8..=Self::Numpad9 as u8).contains(&(*self as u8)) {
+            return Some((b'0' + (*self as u8 - Self::Numpad0 as u8)) as char);
+        }
+        Some(match self {
+            Self::Backslash => '\\',
+            Self::CloseBracket => ']',
+            Self::Comma => ',',
+            Self::EqualSign => '=',
+            Self::Hyphen => '-',
+            Self::OpenBracket => '[',
+            Self::Period => '.',
+            Self::Quote => '\'',
+            Self::Semicolon => ';',
+            Self::Slash => '/',
+            Self::Spacebar => ' ',
+            Self::NumpadAdd => '+',
+            Self::NumpadSubtract => '-',
+            Self::NumpadMultiply => '*',
+            Self::NumpadDivide => '/',
+            Self::NumpadDecimal => '.',
+            Self::NumpadEquals => '=',
+            _ => return None,
+        })
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct KeyModifiers(pub u8);
 
@@ -169,6 +1818,52 @@ impl KeyModifiers {
     pub const NUMERIC_PAD: Self = Self(1 << 5);
     pub const HELP: Self = Self(1 << 6);
     pub const FUNCTION: Self = Self(1 << 7);
+
+    // Stable names used by `Display` and `from_str`.
+    const NAMES: [(Self, &'static str); 8] = [
+        (Self::CONTROL, "ctrl"),
+        (Self::OPTION, "alt"),
+        (Self::SHIFT, "shift"),
+        (Self::COMMAND, "cmd"),
+        (Self::CAPSLOCK, "capslock"),
+        (Self::NUMERIC_PAD, "numpad"),
+        (Self::HELP, "help"),
+        (Self::FUNCTION, "fn"),
+    ];
+
+    /// Parses modifiers joined by `+`, e.g. `"ctrl+shift"`, ignoring case and whitespace.
+    ///
+    /// An empty string is [`KeyModifiers::CLEAR`].
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(s: &str) -> Option<Self> {
+        let s = s.trim();
+        if s.is_empty() {
+            return Some(Self::CLEAR);
+        }
+        s.split('+').try_fold(Self::CLEAR, |mods, name| {
+            let name = name.trim();
+            Self::NAMES
+                .iter()
+                .find(|(_, n)| n.eq_ignore_ascii_case(name))
+                .map(|(modifier, _)| mods | *modifier)
+        })
+    }
+}
+
+impl core::fmt::Display for KeyModifiers {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let mut first = true;
+        for (modifier, name) in Self::NAMES {
+            if self.0 & modifier.0 != 0 {
+                if !first {
+                    f.write_str("+")?;
+                }
+                f.write_str(name)?;
+                first = false;
+            }
+        }
+        Ok(())
+    }
 }
 
 impl core::ops::BitOr for KeyModifiers {
@@ -187,6 +1882,60 @@ impl core::ops::BitAnd for KeyModifiers {
     }
 }
 
+// Hot reload
+
+/// Size, alignment and version of the game's `Memory`, see [`export_memory_layout`].
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryLayout {
+    pub size: usize,
+    pub align: usize,
+    pub version: u32,
+}
+
+impl MemoryLayout {
+    pub const fn of<T>(version: u32) -> Self {
+        Self {
+            size: core::mem::size_of::<T>(),
+            align: core::mem::align_of::<T>(),
+            version,
+        }
+    }
+}
+
+/// Exports the layout of the game's `Memory` from the game dylib, so hot reloads which
+/// would reinterpret the running memory with a different layout are refused.
+///
+/// Bump the version whenever the meaning of the bytes changes without changing the size,
+/// e.g. when fields are reordered. A reload which only changes the version is accepted if
+/// a migration function is given, which is called with the running memory and the old
+/// version before the new code first runs.
+///
+/// ```ignore
+/// glazer::export_memory_layout!(Memory, 2);
+/// glazer::export_memory_layout!(Memory, 3, |memory: &mut Memory, old_version| { ... });
+/// ```
+#[macro_export]
+macro_rules! export_memory_layout {
+    ($memory:ty, $version:expr) => {
+        #[doc(hidden)]
+        #[unsafe(no_mangle)]
+        pub extern "C" fn glazer_memory_layout() -> $crate::MemoryLayout {
+            $crate::MemoryLayout::of::<$memory>($version)
+        }
+    };
+    ($memory:ty, $version:expr, $migrate:expr) => {
+        $crate::export_memory_layout!($memory, $version);
+
+        #[doc(hidden)]
+        #[unsafe(no_mangle)]
+        pub extern "C" fn glazer_migrate_memory(memory: *mut u8, old_version: u32) {
+            let migrate: fn(&mut $memory, u32) = $migrate;
+            migrate(unsafe { &mut *memory.cast::<$memory>() }, old_version);
+        }
+    };
+}
+
 // Debug utility
 
 #[macro_export]
@@ -206,14 +1955,147 @@ pub fn __log(str: &str) {
     platform::log(str);
 }
 
-pub fn debug_time_secs<R>(f: impl FnMut() -> R) -> (f32, R) {
-    platform::debug_time_secs(f)
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::format;
 
-pub fn debug_time_millis<R>(f: impl FnMut() -> R) -> (u128, R) {
-    platform::debug_time_millis(f)
-}
+    #[test]
+    fn key_code_names_round_trip() {
+        for code in KeyCode::ALL {
+            let name = code.as_str();
+            assert_eq!(KeyCode::from_str(name), Some(code), "{name}");
+            assert_eq!(
+                KeyCode::from_str(&name.to_uppercase()),
+                Some(code),
+                "{name}"
+            );
+            assert_eq!(
+                KeyCode::from_str(&format!(" \t{name}\n")),
+                Some(code),
+                "{name}"
+            );
+        }
+        assert_eq!(KeyCode::from_str("page up"), None);
+        assert_eq!(KeyCode::from_str(""), None);
+    }
 
-pub fn debug_time_nanos<R>(f: impl FnMut() -> R) -> (u128, R) {
-    platform::debug_time_nanos(f)
+    #[test]
+    fn key_code_classifiers_are_disjoint() {
+        let classifiers: [fn(&KeyCode) -> bool; 6] = [
+            KeyCode::is_letter,
+            KeyCode::is_digit,
+            KeyCode::is_function,
+            KeyCode::is_numpad,
+            KeyCode::is_modifier,
+            KeyCode::is_navigation,
+        ];
+        let mut counts = [0; 6];
+        for code in KeyCode::ALL {
+            let matches = classifiers.map(|classifier| classifier(&code));
+            assert!(matches.iter().filter(|&&m| m).count() <= 1, "{code:?}");
+            for (count, matched) in counts.iter_mut().zip(matches) {
+                *count += matched as usize;
+            }
+        }
+        assert_eq!(counts, [26, 10, 19, 18, 9, 8]);
+    }
+
+    #[test]
+    fn key_code_chars_are_printable() {
+        for code in KeyCode::ALL {
+            let c = code.to_char_unshifted();
+            if let Some(c) = c {
+                assert!(c == ' ' || c.is_ascii_graphic(), "{code:?}");
+                assert!(!c.is_ascii_uppercase(), "{code:?}");
+            }
+            if code.is_letter() {
+                assert!(c.is_some_and(|c| c.is_ascii_lowercase()), "{code:?}");
+            }
+            if code.is_digit() {
+                assert!(c.is_some_and(|c| c.is_ascii_digit()), "{code:?}");
+            }
+            if code.is_function() || code.is_modifier() || code.is_navigation() {
+                assert_eq!(c, None, "{code:?}");
+            }
+        }
+        assert_eq!(KeyCode::KeyQ.to_char_unshifted(), Some('q'));
+        assert_eq!(KeyCode::Num7.to_char_unshifted(), Some('7'));
+        assert_eq!(KeyCode::Numpad7.to_char_unshifted(), Some('7'));
+        assert_eq!(KeyCode::Spacebar.to_char_unshifted(), Some(' '));
+        for code in [
+            KeyCode::Return,
+            KeyCode::Tab,
+            KeyCode::Escape,
+            KeyCode::DeleteOrBackspace,
+            KeyCode::NumpadEnter,
+            KeyCode::NumpadClear,
+            KeyCode::Unknown,
+        ] {
+            assert_eq!(code.to_char_unshifted(), None, "{code:?}");
+        }
+    }
+
+    #[test]
+    fn key_modifiers_parse() {
+        let ctrl_shift = KeyModifiers::CONTROL | KeyModifiers::SHIFT;
+        assert_eq!(KeyModifiers::from_str("ctrl+shift"), Some(ctrl_shift));
+        assert_eq!(KeyModifiers::from_str("Shift + CTRL"), Some(ctrl_shift));
+        assert_eq!(
+            KeyModifiers::from_str("  cmd  "),
+            Some(KeyModifiers::COMMAND)
+        );
+        assert_eq!(
+            KeyModifiers::from_str("alt+alt"),
+            Some(KeyModifiers::OPTION)
+        );
+        assert_eq!(KeyModifiers::from_str(""), Some(KeyModifiers::CLEAR));
+        assert_eq!(KeyModifiers::from_str(" "), Some(KeyModifiers::CLEAR));
+        assert_eq!(KeyModifiers::from_str("ctrl+"), None);
+        assert_eq!(KeyModifiers::from_str("+ctrl"), None);
+        assert_eq!(KeyModifiers::from_str("ctrl+super"), None);
+        assert_eq!(KeyModifiers::from_str("ctrl shift"), None);
+    }
+
+    #[test]
+    fn key_modifiers_display_round_trips() {
+        for bits in 0..=u8::MAX {
+            let mods = KeyModifiers(bits);
+            assert_eq!(KeyModifiers::from_str(&format!("{mods}")), Some(mods));
+        }
+        let mods = KeyModifiers::COMMAND | KeyModifiers::CONTROL;
+        assert_eq!(format!("{mods}"), "ctrl+cmd");
+    }
+
+    #[test]
+    fn mouse_button_names_round_trip() {
+        for button in [
+            MouseButton::Left,
+            MouseButton::Right,
+            MouseButton::Middle,
+            MouseButton::Back,
+            MouseButton::Forward,
+        ] {
+            let name = button.as_str();
+            assert_eq!(MouseButton::from_str(name), Some(button));
+            assert_eq!(
+                MouseButton::from_str(&format!(" {} ", name.to_uppercase())),
+                Some(button)
+            );
+        }
+        for number in 0..=u8::MAX {
+            let button = MouseButton::Other(number);
+            assert_eq!(button.as_str(), format!("mouse{number}"));
+            assert_eq!(MouseButton::from_str(button.as_str()), Some(button));
+        }
+        assert_eq!(
+            MouseButton::from_str(" Mouse12 "),
+            Some(MouseButton::Other(12))
+        );
+        for name in [
+            "other", "mouse", "mouse256", "mouse-1", "mouse+5", "mousex", "mous",
+        ] {
+            assert_eq!(MouseButton::from_str(name), None, "{name}");
+        }
+    }
 }
@@ -0,0 +1,329 @@
+//! Built-in performance HUD drawn over the presented frame.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// Corner of the window the performance overlay is drawn in.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum OverlayPosition {
+    #[default]
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+/// What the performance overlay shows, see
+/// [`App::with_performance_overlay`](crate::App::with_performance_overlay).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PerformanceOverlayConfig {
+    pub position: OverlayPosition,
+    /// The frame rate in digits, followed by a bar which is full at 120 frames per
+    /// second.
+    pub show_fps: bool,
+    /// A graph of recent frame times with a line at the 60Hz budget.
+    pub show_frame_time: bool,
+    /// A bar whose length is the fill level of the audio ring buffer.
+    pub show_audio_fill: bool,
+    /// Frames in the frame time graph, one pixel wide each.
+    pub history_frames: usize,
+}
+
+impl Default for PerformanceOverlayConfig {
+    fn default() -> Self {
+        Self {
+            position: OverlayPosition::default(),
+            show_fps: true,
+            show_frame_time: true,
+            show_audio_fill: true,
+            history_frames: 120,
+        }
+    }
+}
+
+// Frame time at the top of the graph, in seconds.
+const GRAPH_MAX_FRAME_TIME: f32 = 1.0 / 20.0;
+const GRAPH_HEIGHT: usize = 48;
+const BAR_HEIGHT: usize = 4;
+const MARGIN: usize = 4;
+const MAX_FPS: f32 = 120.0;
+const DIGIT_WIDTH: usize = 3;
+const DIGIT_HEIGHT: usize = 5;
+
+// 3x5 digits, one bit per pixel row by row from the top left.
+const DIGITS: [u16; 10] = [
+    0b111_101_101_101_111,
+    0b010_110_010_010_111,
+    0b111_001_111_100_111,
+    0b111_001_111_001_111,
+    0b101_101_111_001_001,
+    0b111_100_111_001_111,
+    0b111_100_111_101_111,
+    0b111_001_001_001_001,
+    0b111_101_111_101_111,
+    0b111_101_111_001_111,
+];
+
+const BACKGROUND: [u8; 4] = [16, 16, 16, 255];
+const BUDGET: [u8; 4] = [255, 255, 255, 255];
+const TEXT: [u8; 4] = [255, 255, 255, 255];
+const GOOD: [u8; 4] = [64, 200, 64, 255];
+const SLOW: [u8; 4] = [230, 200, 40, 255];
+const BAD: [u8; 4] = [230, 40, 40, 255];
+const AUDIO: [u8; 4] = [64, 140, 230, 255];
+
+/// Frame time history and drawing for [`PerformanceOverlayConfig`].
+pub(crate) struct PerformanceOverlay {
+    config: PerformanceOverlayConfig,
+    // seconds, a ring buffer with the oldest frame at `next`
+    frame_times: Vec<f32>,
+    next: usize,
+}
+
+impl PerformanceOverlay {
+    pub fn new(config: PerformanceOverlayConfig) -> Self {
+        Self {
+            config,
+            frame_times: vec![0.0; config.history_frames.max(1)],
+            next: 0,
+        }
+    }
+
+    pub fn record(&mut self, delta: f32) {
+        self.frame_times[self.next] = delta;
+        self.next = (self.next + 1) % self.frame_times.len();
+    }
+
+    /// Draws into `width * height` RGBA `pixels`, with `audio_fill` from `0.0` to `1.0`.
+    pub fn draw(&self, pixels: &mut [u8], width: usize, height: usize, audio_fill: Option<f32>) {
+        let config = &self.config;
+        let w = self.frame_times.len().min(width.saturating_sub(MARGIN * 2));
+        let mut h = 0;
+        if config.show_frame_time {
+            h += GRAPH_HEIGHT;
+        }
+        if config.show_fps {
+            h += DIGIT_HEIGHT;
+        }
+        if config.show_audio_fill && audio_fill.is_some() {
+            h += BAR_HEIGHT;
+        }
+        if w == 0 || h == 0 || h + MARGIN * 2 > height {
+            return;
+        }
+
+        let x0 = match config.position {
+            OverlayPosition::TopLeft | OverlayPosition::BottomLeft => MARGIN,
+            OverlayPosition::TopRight | OverlayPosition::BottomRight => width - MARGIN - w,
+        };
+        let mut y = match config.position {
+            OverlayPosition::TopLeft | OverlayPosition::TopRight => MARGIN,
+            OverlayPosition::BottomLeft | OverlayPosition::BottomRight => height - MARGIN - h,
+        };
+        let mut fill = |x: usize, y: usize, w: usize, h: usize, color: [u8; 4]| {
+            for row in y..y + h {
+                let start = (row * width + x) * 4;
+                for pixel in pixels[start..start + w * 4].chunks_exact_mut(4) {
+                    pixel.copy_from_slice(&color);
+                }
+            }
+        };
+        fill(x0, y, w, h, BACKGROUND);
+
+        if config.show_frame_time {
+            // Oldest on the left, most recent on the right.
+            let len = self.frame_times.len();
+            let skip = len - w;
+            for column in 0..w {
+                let delta = self.frame_times[(self.next + skip + column) % len];
+                let bar = ((delta / GRAPH_MAX_FRAME_TIME).min(1.0) * GRAPH_HEIGHT as f32) as usize;
+                let color = if delta <= 1.0 / 55.0 {
+                    GOOD
+                } else if delta <= 1.0 / 28.0 {
+                    SLOW
+                } else {
+                    BAD
+                };
+                fill(x0 + column, y + GRAPH_HEIGHT - bar, 1, bar, color);
+            }
+            let budget = ((1.0 / 60.0) / GRAPH_MAX_FRAME_TIME * GRAPH_HEIGHT as f32) as usize;
+            fill(x0, y + GRAPH_HEIGHT - budget, w, 1, BUDGET);
+            y += GRAPH_HEIGHT;
+        }
+
+        if config.show_fps {
+            let len = self.frame_times.len();
+            let last = self.frame_times[(self.next + len - 1) % len];
+            let fps = if last > 0.0 { 1.0 / last } else { 0.0 };
+            let rounded = (fps + 0.5).min(999.0) as usize;
+            let digits = if rounded >= 100 {
+                3
+            } else if rounded >= 10 {
+                2
+            } else {
+                1
+            };
+            // Clipped to the overlay, which can be narrower than the digits.
+            for i in 0..digits {
+                let digit = rounded / 10usize.pow((digits - 1 - i) as u32) % 10;
+                for bit in 0..DIGIT_WIDTH * DIGIT_HEIGHT {
+                    let x = i * (DIGIT_WIDTH + 1) + bit % DIGIT_WIDTH;
+                    if x < w && DIGITS[digit] >> (DIGIT_WIDTH * DIGIT_HEIGHT - 1 - bit) & 1 == 1 {
+                        fill(x0 + x, y + bit / DIGIT_WIDTH, 1, 1, TEXT);
+                    }
+                }
+            }
+            let text = digits * (DIGIT_WIDTH + 1) + 1;
+            let bar_width = w.saturating_sub(text);
+            let bar = ((fps / MAX_FPS).min(1.0) * bar_width as f32) as usize;
+            fill(x0 + text.min(w), y, bar, DIGIT_HEIGHT, GOOD);
+            y += DIGIT_HEIGHT;
+        }
+
+        if config.show_audio_fill
+            && let Some(audio_fill) = audio_fill
+        {
+            let bar = (audio_fill.clamp(0.0, 1.0) * w as f32) as usize;
+            fill(x0, y, bar, BAR_HEIGHT, AUDIO);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::String;
+
+    const WIDTH: usize = 200;
+    const HEIGHT: usize = 100;
+
+    fn draw(config: PerformanceOverlayConfig, width: usize, height: usize) -> Vec<u8> {
+        let mut overlay = PerformanceOverlay::new(config);
+        overlay.record(1.0 / 60.0);
+        let mut pixels = vec![0; width * height * 4];
+        overlay.draw(&mut pixels, width, height, Some(0.5));
+        pixels
+    }
+
+    // Inclusive corners of the pixels drawn.
+    fn bounds(pixels: &[u8], width: usize) -> Option<((usize, usize), (usize, usize))> {
+        let drawn = pixels
+            .chunks_exact(4)
+            .enumerate()
+            .filter(|(_, pixel)| *pixel != [0; 4])
+            .map(|(i, _)| (i % width, i / width));
+        drawn.fold(None, |bounds, (x, y)| {
+            let ((x0, y0), (x1, y1)) = bounds.unwrap_or(((x, y), (x, y)));
+            Some(((x0.min(x), y0.min(y)), (x1.max(x), y1.max(y))))
+        })
+    }
+
+    #[test]
+    fn draws_in_each_corner() {
+        // The default history is 120 frames wide, the rows 48 + 5 + 4 high.
+        let (w, h) = (120, GRAPH_HEIGHT + DIGIT_HEIGHT + BAR_HEIGHT);
+        for (position, top_left) in [
+            (OverlayPosition::TopLeft, (MARGIN, MARGIN)),
+            (OverlayPosition::TopRight, (WIDTH - MARGIN - w, MARGIN)),
+            (OverlayPosition::BottomLeft, (MARGIN, HEIGHT - MARGIN - h)),
+            (
+                OverlayPosition::BottomRight,
+                (WIDTH - MARGIN - w, HEIGHT - MARGIN - h),
+            ),
+        ] {
+            let config = PerformanceOverlayConfig {
+                position,
+                ..PerformanceOverlayConfig::default()
+            };
+            let pixels = draw(config, WIDTH, HEIGHT);
+            let bottom_right = (top_left.0 + w - 1, top_left.1 + h - 1);
+            assert_eq!(
+                bounds(&pixels, WIDTH),
+                Some((top_left, bottom_right)),
+                "{position:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn labels_the_frame_rate() {
+        let config = PerformanceOverlayConfig {
+            show_frame_time: false,
+            show_audio_fill: false,
+            ..PerformanceOverlayConfig::default()
+        };
+        let pixels = draw(config, WIDTH, HEIGHT);
+        let text = (0..DIGIT_HEIGHT).map(|row| {
+            (0..7)
+                .map(|column| {
+                    let i = ((MARGIN + row) * WIDTH + MARGIN + column) * 4;
+                    if pixels[i..i + 4] == TEXT { '#' } else { '.' }
+                })
+                .collect::<String>()
+        });
+        let sixty = [
+            "###.###", //
+            "#...#.#", //
+            "###.#.#", //
+            "#.#.#.#", //
+            "###.###",
+        ];
+        for (row, expected) in text.zip(sixty) {
+            assert_eq!(row, expected);
+        }
+        // Half of the bar after the digits, 60 of 120 frames per second.
+        let bar = pixels[(MARGIN * WIDTH + MARGIN) * 4..((MARGIN + 1) * WIDTH) * 4]
+            .chunks_exact(4)
+            .filter(|pixel| *pixel == GOOD)
+            .count();
+        assert_eq!(bar, (120 - 9) / 2);
+    }
+
+    #[test]
+    fn empty_history_draws_one_frame() {
+        let config = PerformanceOverlayConfig {
+            history_frames: 0,
+            ..PerformanceOverlayConfig::default()
+        };
+        let pixels = draw(config, WIDTH, HEIGHT);
+        assert_eq!(
+            bounds(&pixels, WIDTH).map(|((x0, _), (x1, _))| x1 - x0 + 1),
+            Some(1)
+        );
+    }
+
+    #[test]
+    fn small_frames_do_not_panic() {
+        for (width, height) in [
+            (0, 0),
+            (1, 1),
+            (8, 8),
+            (9, 100),
+            (12, 100),
+            (200, 60),
+            (200, 5),
+        ] {
+            for position in [
+                OverlayPosition::TopLeft,
+                OverlayPosition::TopRight,
+                OverlayPosition::BottomLeft,
+                OverlayPosition::BottomRight,
+            ] {
+                let config = PerformanceOverlayConfig {
+                    position,
+                    ..PerformanceOverlayConfig::default()
+                };
+                draw(config, width, height);
+            }
+        }
+        // Two frames wide, so the digits are cut off.
+        let config = PerformanceOverlayConfig {
+            show_frame_time: false,
+            show_audio_fill: false,
+            history_frames: 2,
+            ..PerformanceOverlayConfig::default()
+        };
+        let pixels = draw(config, WIDTH, HEIGHT);
+        assert_eq!(bounds(&pixels, WIDTH), Some(((4, 4), (5, 8))));
+    }
+}
@@ -0,0 +1,564 @@
+//! CPU post-processing applied to the frame buffer before it is presented.
+
+#[cfg(feature = "std")]
+extern crate std;
+
+/// Transforms each frame before it is presented, see
+/// [`App::with_render_pipeline`](crate::App::with_render_pipeline).
+///
+/// `input` and `output` hold `width * height` RGBA pixels.
+pub trait RenderPipeline {
+    fn process(&mut self, input: &[u8], output: &mut [u8], width: usize, height: usize);
+}
+
+/// Presents frames unchanged.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct IdentityPipeline;
+
+impl RenderPipeline for IdentityPipeline {
+    fn process(&mut self, input: &[u8], output: &mut [u8], _width: usize, _height: usize) {
+        output.copy_from_slice(input);
+    }
+}
+
+/// Raises the color channels to `1 / gamma`, leaving alpha untouched.
+#[derive(Debug, Clone)]
+pub struct GammaCorrectionPipeline {
+    lut: [u8; 256],
+}
+
+#[cfg(feature = "std")]
+impl GammaCorrectionPipeline {
+    pub fn new(gamma: f32) -> Self {
+        let mut lut = [0; 256];
+        for (i, value) in lut.iter_mut().enumerate() {
+            let corrected = (i as f32 / 255.0).powf(1.0 / gamma);
+            *value = (corrected * 255.0 + 0.5) as u8;
+        }
+        Self { lut }
+    }
+}
+
+impl RenderPipeline for GammaCorrectionPipeline {
+    fn process(&mut self, input: &[u8], output: &mut [u8], _width: usize, _height: usize) {
+        for (src, dst) in input.chunks_exact(4).zip(output.chunks_exact_mut(4)) {
+            dst[0] = self.lut[src[0] as usize];
+            dst[1] = self.lut[src[1] as usize];
+            dst[2] = self.lut[src[2] as usize];
+            dst[3] = src[3];
+        }
+    }
+}
+
+/// Built-in color matrices for [`ColorMatrixPipeline`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorFilter {
+    /// Simulates missing green cones.
+    Deuteranopia,
+    /// Simulates missing red cones.
+    Protanopia,
+    /// Simulates missing blue cones.
+    Tritanopia,
+    /// Relative luminance as grayscale.
+    Luminance,
+    /// The red channel as grayscale.
+    Red,
+    /// The green channel as grayscale.
+    Green,
+    /// The blue channel as grayscale.
+    Blue,
+}
+
+impl ColorFilter {
+    pub const ALL: [Self; 7] = [
+        Self::Deuteranopia,
+        Self::Protanopia,
+        Self::Tritanopia,
+        Self::Luminance,
+        Self::Red,
+        Self::Green,
+        Self::Blue,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Deuteranopia => "deuteranopia",
+            Self::Protanopia => "protanopia",
+            Self::Tritanopia => "tritanopia",
+            Self::Luminance => "luminance",
+            Self::Red => "red",
+            Self::Green => "green",
+            Self::Blue => "blue",
+        }
+    }
+
+    /// Row major matrix applied to linear RGB.
+    ///
+    /// The color vision deficiency matrices are from Machado et al. 2009 at full
+    /// severity.
+    pub fn matrix(&self) -> [[f32; 3]; 3] {
+        const LUMINANCE: [f32; 3] = [0.2126, 0.7152, 0.0722];
+        match self {
+            Self::Deuteranopia => [
+                [0.367322, 0.860646, -0.227968],
+                [0.280085, 0.672501, 0.047413],
+                [-0.011820, 0.042940, 0.968881],
+            ],
+            Self::Protanopia => [
+                [0.152286, 1.052583, -0.204868],
+                [0.114503, 0.786281, 0.099216],
+                [-0.003882, -0.048116, 1.051998],
+            ],
+            Self::Tritanopia => [
+                [1.255528, -0.076749, -0.178779],
+                [-0.078411, 0.930809, 0.147602],
+                [0.004733, 0.691367, 0.303900],
+            ],
+            Self::Luminance => [LUMINANCE; 3],
+            Self::Red => [[1.0, 0.0, 0.0]; 3],
+            Self::Green => [[0.0, 1.0, 0.0]; 3],
+            Self::Blue => [[0.0, 0.0, 1.0]; 3],
+        }
+    }
+}
+
+// Resolution of the linear to sRGB table.
+const LINEAR_STEPS: usize = 4096;
+// Fractional bits kept when summing a row's contributions into a `to_srgb` index.
+const CONTRIBUTION_BITS: u32 = 4;
+
+/// Multiplies each pixel's linear RGB by a 3x3 matrix, leaving alpha untouched.
+///
+/// Each matrix entry is premultiplied into a table per input channel, so a pixel is
+/// twelve lookups and integer adds, around 1ms at 640x360 on one core.
+#[derive(Debug, Clone)]
+pub struct ColorMatrixPipeline {
+    // `[output][input][value]`, linear contribution in `to_srgb` steps with
+    // `CONTRIBUTION_BITS` of fraction.
+    contributions: [[[i32; 256]; 3]; 3],
+    to_srgb: [u8; LINEAR_STEPS],
+}
+
+#[cfg(feature = "std")]
+impl ColorMatrixPipeline {
+    pub fn new(matrix: [[f32; 3]; 3]) -> Self {
+        let mut to_linear = [0.0; 256];
+        for (i, value) in to_linear.iter_mut().enumerate() {
+            let c = i as f32 / 255.0;
+            *value = if c <= 0.04045 {
+                c / 12.92
+            } else {
+                ((c + 0.055) / 1.055).powf(2.4)
+            };
+        }
+        let mut to_srgb = [0; LINEAR_STEPS];
+        for (i, value) in to_srgb.iter_mut().enumerate() {
+            let l = i as f32 / (LINEAR_STEPS - 1) as f32;
+            let c = if l <= 0.0031308 {
+                l * 12.92
+            } else {
+                1.055 * l.powf(1.0 / 2.4) - 0.055
+            };
+            *value = (c * 255.0 + 0.5) as u8;
+        }
+        let scale = ((LINEAR_STEPS - 1) << CONTRIBUTION_BITS) as f32;
+        let contributions = matrix
+            .map(|row| row.map(|weight| to_linear.map(|l| (weight * l * scale).round() as i32)));
+        Self {
+            contributions,
+            to_srgb,
+        }
+    }
+
+    pub fn from_filter(filter: ColorFilter) -> Self {
+        Self::new(filter.matrix())
+    }
+}
+
+impl ColorMatrixPipeline {
+    /// Applies the matrix to `pixels` in place.
+    pub fn apply(&self, pixels: &mut [u8]) {
+        for pixel in pixels.chunks_exact_mut(4) {
+            let [r, g, b] = self.transform([pixel[0], pixel[1], pixel[2]]);
+            pixel[0] = r;
+            pixel[1] = g;
+            pixel[2] = b;
+        }
+    }
+
+    #[inline(always)]
+    fn transform(&self, [r, g, b]: [u8; 3]) -> [u8; 3] {
+        core::array::from_fn(|channel| {
+            let [from_r, from_g, from_b] = &self.contributions[channel];
+            let l = from_r[r as usize] + from_g[g as usize] + from_b[b as usize];
+            let index = (l + (1 << (CONTRIBUTION_BITS - 1))) >> CONTRIBUTION_BITS;
+            self.to_srgb[index.clamp(0, LINEAR_STEPS as i32 - 1) as usize]
+        })
+    }
+}
+
+impl RenderPipeline for ColorMatrixPipeline {
+    fn process(&mut self, input: &[u8], output: &mut [u8], _width: usize, _height: usize) {
+        for (src, dst) in input.chunks_exact(4).zip(output.chunks_exact_mut(4)) {
+            let [r, g, b] = self.transform([src[0], src[1], src[2]]);
+            dst[0] = r;
+            dst[1] = g;
+            dst[2] = b;
+            dst[3] = src[3];
+        }
+    }
+}
+
+/// Emulates a CRT: darkened scanlines, a slight horizontal blur, barrel distortion and
+/// aperture grille tinting.
+///
+/// Each amount is from `0.0`, off, to `1.0`, though `barrel_distortion` is usually
+/// kept small.
+///
+/// As a [`PresentFilter`](crate::PresentFilter) it runs on the frame scaled up to the
+/// window by a whole number, so scanlines and the grille are as fine as the display
+/// allows. As a render pipeline it runs at the frame buffer's resolution.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CrtPipeline {
+    /// How much every other row is darkened.
+    pub scanline_darkness: f32,
+    /// How much of each neighbouring column is mixed into a pixel.
+    pub horizontal_blur: f32,
+    /// How far the corners are pulled in, pixels outside the curved screen are black.
+    pub barrel_distortion: f32,
+    /// How much each column is tinted towards red, green or blue in turn.
+    pub aperture_grille: f32,
+}
+
+impl Default for CrtPipeline {
+    fn default() -> Self {
+        Self {
+            scanline_darkness: 0.4,
+            horizontal_blur: 0.25,
+            barrel_distortion: 0.05,
+            aperture_grille: 0.15,
+        }
+    }
+}
+
+impl CrtPipeline {
+    // Column of `input` sampled for `x`, `y` of the output, `None` off the screen.
+    #[inline(always)]
+    fn source(&self, x: usize, y: usize, width: usize, height: usize) -> Option<(usize, usize)> {
+        if self.barrel_distortion == 0.0 {
+            return Some((x, y));
+        }
+        let u = (x as f32 + 0.5) / width as f32 * 2.0 - 1.0;
+        let v = (y as f32 + 0.5) / height as f32 * 2.0 - 1.0;
+        let scale = 1.0 + self.barrel_distortion * (u * u + v * v);
+        let (u, v) = (u * scale, v * scale);
+        if !(-1.0..1.0).contains(&u) || !(-1.0..1.0).contains(&v) {
+            return None;
+        }
+        // Rounding can land just past the last pixel.
+        Some((
+            (((u + 1.0) * 0.5 * width as f32) as usize).min(width - 1),
+            (((v + 1.0) * 0.5 * height as f32) as usize).min(height - 1),
+        ))
+    }
+}
+
+impl RenderPipeline for CrtPipeline {
+    fn process(&mut self, input: &[u8], output: &mut [u8], width: usize, height: usize) {
+        if width == 0 || height == 0 {
+            return;
+        }
+        let pixel = |x: usize, y: usize| {
+            let i = (y * width + x) * 4;
+            [input[i] as f32, input[i + 1] as f32, input[i + 2] as f32]
+        };
+        for (y, row) in output.chunks_exact_mut(width * 4).take(height).enumerate() {
+            let scanline = if y % 2 == 1 {
+                1.0 - self.scanline_darkness
+            } else {
+                1.0
+            };
+            for (x, dst) in row.chunks_exact_mut(4).enumerate() {
+                let Some((sx, sy)) = self.source(x, y, width, height) else {
+                    dst[..3].fill(0);
+                    dst[3] = 255;
+                    continue;
+                };
+                let center = pixel(sx, sy);
+                let left = pixel(sx.saturating_sub(1), sy);
+                let right = pixel((sx + 1).min(width - 1), sy);
+                let grille = x % 3;
+                for channel in 0..3 {
+                    let blurred = center[channel] * (1.0 - self.horizontal_blur)
+                        + (left[channel] + right[channel]) * 0.5 * self.horizontal_blur;
+                    let tint = if channel == grille {
+                        1.0
+                    } else {
+                        1.0 - self.aperture_grille
+                    };
+                    dst[channel] = (blurred * scanline * tint).clamp(0.0, 255.0) as u8;
+                }
+                dst[3] = input[(sy * width + sx) * 4 + 3];
+            }
+        }
+    }
+}
+
+// Scales `input`, `width * height` RGBA pixels, up by `scale` into `output` without
+// filtering.
+pub(crate) fn upscale_nearest(
+    input: &[u8],
+    output: &mut [u8],
+    width: usize,
+    height: usize,
+    scale: usize,
+) {
+    let output_row = width * scale * 4;
+    for (y, row) in input.chunks_exact(width * 4).take(height).enumerate() {
+        let first = y * scale * output_row;
+        let (scaled, repeats) = output[first..first + scale * output_row].split_at_mut(output_row);
+        for (dst, src) in scaled.chunks_exact_mut(scale * 4).zip(row.chunks_exact(4)) {
+            for dst in dst.chunks_exact_mut(4) {
+                dst.copy_from_slice(src);
+            }
+        }
+        for repeat in repeats.chunks_exact_mut(output_row) {
+            repeat.copy_from_slice(scaled);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    // `width * height` opaque pixels with distinct colors.
+    fn gradient(width: usize, height: usize) -> Vec<u8> {
+        (0..width * height)
+            .flat_map(|i| [(i * 7) as u8, (i * 13) as u8, (i * 29) as u8, 255])
+            .collect()
+    }
+
+    fn crt_only(pipeline: CrtPipeline, input: &[u8], width: usize, height: usize) -> Vec<u8> {
+        let mut output = vec![0; input.len()];
+        let mut pipeline = pipeline;
+        pipeline.process(input, &mut output, width, height);
+        output
+    }
+
+    const CRT_OFF: CrtPipeline = CrtPipeline {
+        scanline_darkness: 0.0,
+        horizontal_blur: 0.0,
+        barrel_distortion: 0.0,
+        aperture_grille: 0.0,
+    };
+
+    // Every channel value in each channel, with varying alpha.
+    #[cfg(feature = "std")]
+    fn every_value() -> Vec<u8> {
+        (0..=255u8)
+            .flat_map(|v| [v, 255 - v, v.wrapping_mul(37), v ^ 0x5a])
+            .collect()
+    }
+
+    #[cfg(feature = "std")]
+    fn srgb_to_linear(c: u8) -> f32 {
+        let c = c as f32 / 255.0;
+        if c <= 0.04045 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    }
+
+    #[cfg(feature = "std")]
+    fn linear_to_srgb(l: f32) -> u8 {
+        let l = l.clamp(0.0, 1.0);
+        let c = if l <= 0.0031308 {
+            l * 12.92
+        } else {
+            1.055 * l.powf(1.0 / 2.4) - 0.055
+        };
+        (c * 255.0 + 0.5) as u8
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn gamma_tables_span_the_range() {
+        for gamma in [0.5, 1.0, 1.8, 2.2, 3.0] {
+            let lut = GammaCorrectionPipeline::new(gamma).lut;
+            assert_eq!((lut[0], lut[255]), (0, 255), "{gamma}");
+            assert!(lut.windows(2).all(|pair| pair[0] <= pair[1]), "{gamma}");
+        }
+        // Brightens the midtones above one, darkens them below.
+        assert!(GammaCorrectionPipeline::new(2.2).lut[128] > 128);
+        assert!(GammaCorrectionPipeline::new(0.5).lut[128] < 128);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn gamma_one_keeps_pixels() {
+        let input = every_value();
+        let mut output = vec![0; input.len()];
+        GammaCorrectionPipeline::new(1.0).process(&input, &mut output, 256, 1);
+        assert_eq!(output, input);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn srgb_tables_span_the_range() {
+        let pipeline = ColorMatrixPipeline::new([[1.0, 0.0, 0.0]; 3]);
+        let to_linear = &pipeline.contributions[0][0];
+        assert_eq!(to_linear[0], 0);
+        assert_eq!(
+            to_linear[255],
+            ((LINEAR_STEPS - 1) << CONTRIBUTION_BITS) as i32
+        );
+        assert!(to_linear.windows(2).all(|pair| pair[0] < pair[1]));
+
+        let to_srgb = &pipeline.to_srgb;
+        assert_eq!((to_srgb[0], to_srgb[LINEAR_STEPS - 1]), (0, 255));
+        assert!(to_srgb.windows(2).all(|pair| pair[0] <= pair[1]));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn identity_matrix_keeps_pixels() {
+        let identity = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+        let input = every_value();
+        let mut output = vec![0; input.len()];
+        ColorMatrixPipeline::new(identity).process(&input, &mut output, 256, 1);
+        assert_eq!(output, input);
+
+        let mut pixels = input.clone();
+        ColorMatrixPipeline::new(identity).apply(&mut pixels);
+        assert_eq!(pixels, input);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn luminance_gives_equal_channels() {
+        let input = every_value();
+        let mut output = vec![0; input.len()];
+        ColorMatrixPipeline::from_filter(ColorFilter::Luminance).process(
+            &input,
+            &mut output,
+            256,
+            1,
+        );
+        for (src, dst) in input.chunks_exact(4).zip(output.chunks_exact(4)) {
+            assert_eq!(dst[0], dst[1], "{src:?}");
+            assert_eq!(dst[1], dst[2], "{src:?}");
+            assert_eq!(dst[3], src[3]);
+        }
+        // Weights sum to one, so grays stay put.
+        let mut gray = [128, 128, 128, 255];
+        ColorMatrixPipeline::from_filter(ColorFilter::Luminance).apply(&mut gray);
+        assert_eq!(gray, [128, 128, 128, 255]);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn custom_matrix_goes_through_linear_light() {
+        // Rotates the channels, exact through the tables.
+        let rotate = [[0.0, 1.0, 0.0], [0.0, 0.0, 1.0], [1.0, 0.0, 0.0]];
+        let input = every_value();
+        let mut output = vec![0; input.len()];
+        ColorMatrixPipeline::new(rotate).process(&input, &mut output, 256, 1);
+        for (src, dst) in input.chunks_exact(4).zip(output.chunks_exact(4)) {
+            assert_eq!(dst, [src[1], src[2], src[0], src[3]]);
+        }
+
+        // Within a step of converting with `powf`, halving in linear light rather than
+        // halving the sRGB value.
+        let matrix = [[0.5, 0.0, 0.0], [0.25, 0.25, 0.25], [-0.5, 0.0, 1.5]];
+        ColorMatrixPipeline::new(matrix).process(&input, &mut output, 256, 1);
+        for (src, dst) in input.chunks_exact(4).zip(output.chunks_exact(4)) {
+            let linear = [src[0], src[1], src[2]].map(srgb_to_linear);
+            for (row, &value) in matrix.iter().zip(dst) {
+                let l = row[0] * linear[0] + row[1] * linear[1] + row[2] * linear[2];
+                assert!(value.abs_diff(linear_to_srgb(l)) <= 1, "{src:?} {dst:?}");
+            }
+        }
+        let mut white = [255, 255, 255, 255];
+        ColorMatrixPipeline::new(matrix).apply(&mut white);
+        assert_eq!(white[..3], [188, 225, 255]);
+    }
+
+    #[test]
+    fn crt_darkens_alternate_rows() {
+        let input = [200, 100, 50, 255].repeat(3 * 4);
+        let crt = CrtPipeline {
+            scanline_darkness: 0.5,
+            ..CRT_OFF
+        };
+        let output = crt_only(crt, &input, 3, 4);
+        for (y, row) in output.chunks_exact(3 * 4).enumerate() {
+            let expected = if y % 2 == 1 {
+                [100, 50, 25, 255]
+            } else {
+                [200, 100, 50, 255]
+            };
+            assert!(
+                row.chunks_exact(4).all(|pixel| pixel == expected),
+                "row {y}"
+            );
+        }
+
+        // Everything off is a copy.
+        let input = gradient(5, 3);
+        assert_eq!(crt_only(CRT_OFF, &input, 5, 3), input);
+    }
+
+    #[test]
+    fn crt_barrel_keeps_the_center() {
+        let input = gradient(5, 5);
+        let crt = CrtPipeline {
+            barrel_distortion: 0.3,
+            ..CRT_OFF
+        };
+        let output = crt_only(crt, &input, 5, 5);
+        let center = (2 * 5 + 2) * 4;
+        assert_eq!(output[center..center + 4], input[center..center + 4]);
+        // The corners are pulled in past the edge of the screen.
+        assert_eq!(output[..4], [0, 0, 0, 255]);
+    }
+
+    #[test]
+    fn crt_edges_stay_in_bounds() {
+        for (width, height) in [(1, 1), (1, 7), (7, 1), (2, 2), (3, 5), (64, 36)] {
+            let input = gradient(width, height);
+            for barrel_distortion in [-0.9, -0.1, 0.0, 0.05, 1.0] {
+                let crt = CrtPipeline {
+                    barrel_distortion,
+                    ..CrtPipeline::default()
+                };
+                crt_only(crt, &input, width, height);
+            }
+        }
+        crt_only(CrtPipeline::default(), &[], 0, 0);
+        crt_only(CrtPipeline::default(), &[], 0, 3);
+        crt_only(CrtPipeline::default(), &[], 3, 0);
+    }
+
+    #[test]
+    fn upscales_without_filtering() {
+        let input = gradient(2, 2);
+        let mut output = vec![0; 6 * 6 * 4];
+        upscale_nearest(&input, &mut output, 2, 2, 3);
+        for y in 0..6 {
+            for x in 0..6 {
+                let (src, dst) = (((y / 3) * 2 + x / 3) * 4, (y * 6 + x) * 4);
+                assert_eq!(output[dst..dst + 4], input[src..src + 4], "{x} {y}");
+            }
+        }
+
+        let mut output = vec![0; input.len()];
+        upscale_nearest(&input, &mut output, 2, 2, 1);
+        assert_eq!(output, input);
+    }
+}
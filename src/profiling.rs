@@ -0,0 +1,125 @@
+//! Intervals for external profilers, shown as signposts in Instruments.
+//!
+//! Nothing is emitted unless the `profiling` feature is enabled, so scopes can be left
+//! in game code.
+
+use core::ffi::CStr;
+
+/// An interval which ends when dropped, see [`scope`].
+#[must_use = "the interval ends when the scope is dropped"]
+pub struct Scope {
+    #[cfg(feature = "profiling")]
+    id: u64,
+    #[cfg(feature = "profiling")]
+    name: &'static CStr,
+}
+
+/// Begins an interval named `name` in glazer's signpost track, which ends when the
+/// returned [`Scope`] is dropped. Scopes may nest.
+pub fn scope(name: &'static CStr) -> Scope {
+    begin(name, None)
+}
+
+/// Begins an interval labelled with the frame index, used by the platform.
+pub(crate) fn frame_scope(name: &'static CStr, frame: u64) -> Scope {
+    begin(name, Some(frame))
+}
+
+#[cfg(not(feature = "profiling"))]
+fn begin(_name: &'static CStr, _frame: Option<u64>) -> Scope {
+    Scope {}
+}
+
+#[cfg(feature = "profiling")]
+fn begin(name: &'static CStr, frame: Option<u64>) -> Scope {
+    let id = signpost::next_id();
+    signpost::emit(signpost::INTERVAL_BEGIN, id, name, frame);
+    Scope { id, name }
+}
+
+#[cfg(feature = "profiling")]
+impl Drop for Scope {
+    fn drop(&mut self) {
+        signpost::emit(signpost::INTERVAL_END, self.id, self.name, None);
+    }
+}
+
+/// The os_signpost C API, which is only macros over `_os_signpost_emit_with_name_impl`.
+///
+/// Nothing here allocates once the log exists, so intervals can be emitted from the audio
+/// thread.
+#[cfg(feature = "profiling")]
+mod signpost {
+    use core::ffi::{CStr, c_char, c_void};
+    use core::ptr::null_mut;
+    use core::sync::atomic::{AtomicPtr, AtomicU64, Ordering};
+
+    pub const INTERVAL_BEGIN: u8 = 1;
+    pub const INTERVAL_END: u8 = 2;
+
+    unsafe extern "C" {
+        static __dso_handle: c_void;
+        fn os_log_create(subsystem: *const c_char, category: *const c_char) -> *mut c_void;
+        fn os_signpost_enabled(log: *mut c_void) -> bool;
+        fn _os_signpost_emit_with_name_impl(
+            dso: *const c_void,
+            log: *mut c_void,
+            kind: u8,
+            id: u64,
+            name: *const c_char,
+            format: *const c_char,
+            buf: *mut u8,
+            size: u32,
+        );
+    }
+
+    static LOG: AtomicPtr<c_void> = AtomicPtr::new(null_mut());
+    // Zero and `u64::MAX` are reserved signpost ids.
+    static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+    pub fn next_id() -> u64 {
+        NEXT_ID.fetch_add(1, Ordering::Relaxed)
+    }
+
+    fn log() -> *mut c_void {
+        let log = LOG.load(Ordering::Acquire);
+        if !log.is_null() {
+            return log;
+        }
+        // Racing threads may each create a log, they are all the same track.
+        let log = unsafe { os_log_create(c"glazer".as_ptr(), c"PointsOfInterest".as_ptr()) };
+        LOG.store(log, Ordering::Release);
+        log
+    }
+
+    pub fn emit(kind: u8, id: u64, name: &'static CStr, frame: Option<u64>) {
+        let log = log();
+        if log.is_null() || !unsafe { os_signpost_enabled(log) } {
+            return;
+        }
+        // The os_log argument encoding: a summary byte and argument count, then each
+        // argument as a descriptor byte, its size and its bytes.
+        let mut buf = [0u8; 12];
+        let (format, size) = match frame {
+            Some(frame) => {
+                buf[1] = 1;
+                buf[3] = 8;
+                buf[4..12].copy_from_slice(&frame.to_ne_bytes());
+                (c"frame %llu", 12)
+            }
+            None => (c"", 2),
+        };
+        unsafe {
+            _os_signpost_emit_with_name_impl(
+                &__dso_handle,
+                log,
+                kind,
+                id,
+                name.as_ptr(),
+                format.as_ptr(),
+                buf.as_mut_ptr(),
+                size,
+            );
+        }
+    }
+}
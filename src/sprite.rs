@@ -0,0 +1,708 @@
+//! Blits RGBA sprites into a frame buffer with rotation, scaling, tinting and blending.
+//!
+//! ```ignore
+//! let ship = Sprite::new(&memory.ship_pixels, 32, 32);
+//! let transform = Transform2D {
+//!     pos: [memory.x, memory.y],
+//!     rotation: memory.heading,
+//!     origin: [16.0, 16.0],
+//!     ..Default::default()
+//! };
+//! sprite::draw_sprite(frame, width, height, &ship, transform, &Tint::NONE, Blend::Alpha);
+//! ```
+
+use core::f32::consts::{FRAC_PI_2, PI, TAU};
+
+/// `w * h` RGBA pixels, row by row from the top.
+#[derive(Debug, Clone, Copy)]
+pub struct Sprite<'a> {
+    pub pixels: &'a [[u8; 4]],
+    pub w: usize,
+    pub h: usize,
+    /// How pixels are sampled when the sprite is rotated or scaled.
+    pub sampling: Sampling,
+}
+
+impl<'a> Sprite<'a> {
+    pub fn new(pixels: &'a [[u8; 4]], w: usize, h: usize) -> Self {
+        assert!(pixels.len() >= w * h, "sprite is smaller than {w}x{h}");
+        Self {
+            pixels,
+            w,
+            h,
+            sampling: Sampling::Nearest,
+        }
+    }
+
+    pub fn with_sampling(mut self, sampling: Sampling) -> Self {
+        self.sampling = sampling;
+        self
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Sampling {
+    #[default]
+    Nearest,
+    /// Interpolates the four nearest pixels, clamped at the sprite's edges.
+    Bilinear,
+}
+
+/// Where a sprite is drawn, in frame buffer pixels with a top left origin.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transform2D {
+    /// Where `origin` is drawn.
+    pub pos: [f32; 2],
+    /// Clockwise, in radians, around `origin`.
+    pub rotation: f32,
+    pub scale: [f32; 2],
+    /// The point of the sprite, in its pixels, which is placed at `pos` and rotated and
+    /// scaled around.
+    pub origin: [f32; 2],
+}
+
+impl Default for Transform2D {
+    fn default() -> Self {
+        Self {
+            pos: [0.0; 2],
+            rotation: 0.0,
+            scale: [1.0; 2],
+            origin: [0.0; 2],
+        }
+    }
+}
+
+/// How sprite pixels are combined with the frame buffer.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Blend {
+    /// Overwrites the frame buffer, including its alpha.
+    Replace,
+    /// Non-premultiplied source over.
+    #[default]
+    Alpha,
+    /// Adds the color scaled by alpha, saturating.
+    Additive,
+}
+
+/// A color multiply applied to sprite pixels, in linear space.
+///
+/// The multiply is baked into sRGB lookup tables when the tint is made, so tinting costs
+/// the same as not tinting.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tint {
+    rgb: [[u8; 256]; 3],
+    alpha: u16,
+}
+
+impl Tint {
+    /// Leaves sprites unchanged.
+    pub const NONE: Self = {
+        let mut identity = [0; 256];
+        let mut i = 0;
+        while i < 256 {
+            identity[i] = i as u8;
+            i += 1;
+        }
+        Self {
+            rgb: [identity; 3],
+            alpha: 256,
+        }
+    };
+
+    /// Multiplies linear RGB by `rgb` and alpha by `alpha`, both from `0.0` to `1.0`.
+    pub fn new(rgb: [f32; 3], alpha: f32) -> Self {
+        // Linear values at each sRGB value, and halfway between them, where encoding
+        // rounds up to the next value.
+        let linear: [f32; 256] = core::array::from_fn(|i| srgb_to_linear(i as f32 / 255.0));
+        let rounding: [f32; 255] =
+            core::array::from_fn(|i| srgb_to_linear((i as f32 + 0.5) / 255.0));
+
+        let mut tables = [[0; 256]; 3];
+        for (table, factor) in tables.iter_mut().zip(rgb) {
+            let factor = factor.clamp(0.0, 1.0);
+            for (value, linear) in table.iter_mut().zip(linear) {
+                let tinted = linear * factor;
+                *value = rounding.partition_point(|&threshold| threshold <= tinted) as u8;
+            }
+        }
+        Self {
+            rgb: tables,
+            alpha: (alpha.clamp(0.0, 1.0) * 256.0 + 0.5) as u16,
+        }
+    }
+
+    #[inline(always)]
+    fn apply(&self, [r, g, b, a]: [u8; 4]) -> [u8; 4] {
+        [
+            self.rgb[0][r as usize],
+            self.rgb[1][g as usize],
+            self.rgb[2][b as usize],
+            ((a as u16 * self.alpha) >> 8) as u8,
+        ]
+    }
+}
+
+impl Default for Tint {
+    fn default() -> Self {
+        Self::NONE
+    }
+}
+
+/// Draws `sprite` into the `width * height` `frame_buffer` at `transform`, clipped to the
+/// frame. Nothing is drawn if `transform` is not finite.
+///
+/// Sprites which are not rotated or scaled are copied row by row, otherwise each covered
+/// frame pixel is mapped back into the sprite and sampled.
+pub fn draw_sprite(
+    frame_buffer: &mut [[u8; 4]],
+    width: usize,
+    height: usize,
+    sprite: &Sprite,
+    transform: Transform2D,
+    tint: &Tint,
+    blend: Blend,
+) {
+    assert!(
+        frame_buffer.len() >= width * height,
+        "frame buffer is smaller than {width}x{height}"
+    );
+    if sprite.w == 0 || sprite.h == 0 || transform.scale[0] == 0.0 || transform.scale[1] == 0.0 {
+        return;
+    }
+    let Transform2D {
+        pos,
+        rotation,
+        scale,
+        origin,
+    } = transform;
+    if !(rotation.is_finite() && [pos, scale, origin].iter().flatten().all(|v| v.is_finite())) {
+        return;
+    }
+    if transform.rotation == 0.0 && transform.scale == [1.0, 1.0] {
+        draw_unscaled(frame_buffer, width, height, sprite, transform, tint, blend);
+    } else {
+        draw_transformed(frame_buffer, width, height, sprite, transform, tint, blend);
+    }
+}
+
+fn draw_unscaled(
+    frame_buffer: &mut [[u8; 4]],
+    width: usize,
+    height: usize,
+    sprite: &Sprite,
+    transform: Transform2D,
+    tint: &Tint,
+    blend: Blend,
+) {
+    // The top left of the sprite, snapped to the nearest pixel.
+    let left = round(transform.pos[0] - transform.origin[0]);
+    let top = round(transform.pos[1] - transform.origin[1]);
+    let x0 = left.clamp(0, width as i64) as usize;
+    let x1 = left.saturating_add(sprite.w as i64).clamp(0, width as i64) as usize;
+    let y0 = top.clamp(0, height as i64) as usize;
+    let y1 = top.saturating_add(sprite.h as i64).clamp(0, height as i64) as usize;
+    if x0 == x1 {
+        return;
+    }
+    let sprite_x = (x0 as i64 - left) as usize;
+    let copy = blend == Blend::Replace && *tint == Tint::NONE;
+    for y in y0..y1 {
+        let sprite_row = (y as i64 - top) as usize * sprite.w + sprite_x;
+        let src = &sprite.pixels[sprite_row..sprite_row + (x1 - x0)];
+        let dst = &mut frame_buffer[y * width + x0..y * width + x1];
+        if copy {
+            dst.copy_from_slice(src);
+        } else {
+            for (dst, src) in dst.iter_mut().zip(src) {
+                *dst = blend_pixel(*dst, tint.apply(*src), blend);
+            }
+        }
+    }
+}
+
+fn draw_transformed(
+    frame_buffer: &mut [[u8; 4]],
+    width: usize,
+    height: usize,
+    sprite: &Sprite,
+    transform: Transform2D,
+    tint: &Tint,
+    blend: Blend,
+) {
+    let Transform2D {
+        pos,
+        rotation,
+        scale,
+        origin,
+    } = transform;
+    let (sin, cos) = sin_cos(rotation);
+
+    // Frame bounds of the rotated and scaled corners.
+    let (mut min_x, mut min_y) = (f32::INFINITY, f32::INFINITY);
+    let (mut max_x, mut max_y) = (f32::NEG_INFINITY, f32::NEG_INFINITY);
+    for [cx, cy] in [
+        [0.0, 0.0],
+        [sprite.w as f32, 0.0],
+        [0.0, sprite.h as f32],
+        [sprite.w as f32, sprite.h as f32],
+    ] {
+        let sx = (cx - origin[0]) * scale[0];
+        let sy = (cy - origin[1]) * scale[1];
+        let x = pos[0] + sx * cos - sy * sin;
+        let y = pos[1] + sx * sin + sy * cos;
+        min_x = min_x.min(x);
+        min_y = min_y.min(y);
+        max_x = max_x.max(x);
+        max_y = max_y.max(y);
+    }
+    let x0 = floor(min_x).clamp(0, width as i64) as usize;
+    let x1 = floor(max_x).saturating_add(1).clamp(0, width as i64) as usize;
+    let y0 = floor(min_y).clamp(0, height as i64) as usize;
+    let y1 = floor(max_y).saturating_add(1).clamp(0, height as i64) as usize;
+    if x0 >= x1 {
+        return;
+    }
+
+    // Sprite coordinates move by a constant step per frame pixel.
+    let (u_dx, v_dx) = (cos / scale[0], -sin / scale[1]);
+    let (u_dy, v_dy) = (sin / scale[0], cos / scale[1]);
+    let (w, h) = (sprite.w as f32, sprite.h as f32);
+    for y in y0..y1 {
+        // Sampled at pixel centers.
+        let fx = x0 as f32 + 0.5 - pos[0];
+        let fy = y as f32 + 0.5 - pos[1];
+        let mut u = origin[0] + fx * u_dx + fy * u_dy;
+        let mut v = origin[1] + fx * v_dx + fy * v_dy;
+        let row = &mut frame_buffer[y * width + x0..y * width + x1];
+        for dst in row {
+            if u >= 0.0 && u < w && v >= 0.0 && v < h {
+                let src = match sprite.sampling {
+                    Sampling::Nearest => sprite.pixels[v as usize * sprite.w + u as usize],
+                    Sampling::Bilinear => sample_bilinear(sprite, u, v),
+                };
+                *dst = blend_pixel(*dst, tint.apply(src), blend);
+            }
+            u += u_dx;
+            v += v_dx;
+        }
+    }
+}
+
+fn sample_bilinear(sprite: &Sprite, u: f32, v: f32) -> [u8; 4] {
+    let u = (u - 0.5).max(0.0);
+    let v = (v - 0.5).max(0.0);
+    let (x, y) = (u as usize, v as usize);
+    let x1 = (x + 1).min(sprite.w - 1);
+    let y1 = (y + 1).min(sprite.h - 1);
+    // Weights in 1/256ths, rounded so a sample a hair before a texel center is not
+    // pulled a step towards its neighbour.
+    let fx = ((u - x as f32) * 256.0 + 0.5) as u32;
+    let fy = ((v - y as f32) * 256.0 + 0.5) as u32;
+    let p = |x: usize, y: usize| sprite.pixels[y * sprite.w + x];
+    let (p00, p10, p01, p11) = (p(x, y), p(x1, y), p(x, y1), p(x1, y1));
+    core::array::from_fn(|i| {
+        let top = p00[i] as u32 * (256 - fx) + p10[i] as u32 * fx;
+        let bottom = p01[i] as u32 * (256 - fx) + p11[i] as u32 * fx;
+        ((top * (256 - fy) + bottom * fy + (1 << 15)) >> 16) as u8
+    })
+}
+
+#[inline(always)]
+fn blend_pixel(dst: [u8; 4], src: [u8; 4], blend: Blend) -> [u8; 4] {
+    let a = src[3] as u32;
+    match blend {
+        Blend::Replace => src,
+        Blend::Alpha => {
+            let mix = |s: u8, d: u8| ((s as u32 * a + d as u32 * (255 - a) + 127) / 255) as u8;
+            [
+                mix(src[0], dst[0]),
+                mix(src[1], dst[1]),
+                mix(src[2], dst[2]),
+                (a + (dst[3] as u32 * (255 - a) + 127) / 255) as u8,
+            ]
+        }
+        Blend::Additive => {
+            let add = |s: u8, d: u8| (d as u32 + (s as u32 * a + 127) / 255).min(255) as u8;
+            [
+                add(src[0], dst[0]),
+                add(src[1], dst[1]),
+                add(src[2], dst[2]),
+                dst[3],
+            ]
+        }
+    }
+}
+
+// `core` has no rounding, powers or trigonometry for floats.
+
+// `c` from `0.0` to `1.0`.
+fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        return c / 12.92;
+    }
+    // `x^2.4` is `x^2 * x^(2/5)`.
+    let x = (c + 0.055) / 1.055;
+    let root = fifth_root(x);
+    x * x * root * root
+}
+
+// `x` from about `0.04` to `1.0`.
+fn fifth_root(x: f32) -> f32 {
+    // A fifth of the exponent is within a few percent, Newton's method converges from there.
+    let mut root = f32::from_bits(x.to_bits() / 5 + 852_282_573);
+    for _ in 0..4 {
+        let root4 = root * root * root * root;
+        root -= (root4 * root - x) / (5.0 * root4);
+    }
+    root
+}
+
+fn floor(x: f32) -> i64 {
+    let i = x as i64;
+    if (i as f32) > x {
+        i.saturating_sub(1)
+    } else {
+        i
+    }
+}
+
+fn round(x: f32) -> i64 {
+    floor(x + 0.5)
+}
+
+/// `(sin, cos)` of `radians`, accurate to about `1e-6`.
+fn sin_cos(radians: f32) -> (f32, f32) {
+    // Into -PI..=PI, then onto -PI/2..=PI/2 where the series converges quickly.
+    let x = radians - TAU * floor(radians / TAU + 0.5) as f32;
+    let fold = |x: f32| {
+        if x > FRAC_PI_2 {
+            PI - x
+        } else if x < -FRAC_PI_2 {
+            -PI - x
+        } else {
+            x
+        }
+    };
+    let sin = |x: f32| {
+        let x2 = x * x;
+        x * (1.0
+            - x2 / 6.0
+                * (1.0 - x2 / 20.0 * (1.0 - x2 / 42.0 * (1.0 - x2 / 72.0 * (1.0 - x2 / 110.0)))))
+    };
+    let cos_x = if x > FRAC_PI_2 {
+        x - 3.0 * FRAC_PI_2
+    } else {
+        x + FRAC_PI_2
+    };
+    (sin(fold(x)), sin(fold(cos_x)))
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    const CLEAR: [u8; 4] = [0; 4];
+
+    // 2x2 sprite with a distinct opaque color per pixel.
+    const PIXELS: [[u8; 4]; 4] = [
+        [1, 0, 0, 255],
+        [2, 0, 0, 255],
+        [3, 0, 0, 255],
+        [4, 0, 0, 255],
+    ];
+
+    fn draw_at(pos: [f32; 2]) -> Vec<[u8; 4]> {
+        let mut frame = vec![CLEAR; 4 * 3];
+        let transform = Transform2D {
+            pos,
+            ..Default::default()
+        };
+        let sprite = Sprite::new(&PIXELS, 2, 2);
+        draw_sprite(
+            &mut frame,
+            4,
+            3,
+            &sprite,
+            transform,
+            &Tint::NONE,
+            Blend::Replace,
+        );
+        frame
+    }
+
+    // The red channel of each frame pixel, row by row.
+    fn reds(frame: &[[u8; 4]]) -> Vec<u8> {
+        frame.iter().map(|pixel| pixel[0]).collect()
+    }
+
+    #[test]
+    fn sin_cos_accuracy() {
+        let mut max_error: f64 = 0.0;
+        for i in -20_000..=20_000 {
+            let radians = i as f32 * 0.001;
+            let (sin, cos) = sin_cos(radians);
+            let radians = radians as f64;
+            max_error = max_error
+                .max((sin as f64 - radians.sin()).abs())
+                .max((cos as f64 - radians.cos()).abs());
+        }
+        assert!(max_error < 2e-6, "{max_error}");
+
+        let (sin, cos) = sin_cos(0.0);
+        assert!(sin == 0.0 && (cos - 1.0).abs() < 1e-6);
+        for radians in [FRAC_PI_2, PI, -PI, TAU, 100.0, -100.0] {
+            let (sin, cos) = sin_cos(radians);
+            assert!((sin * sin + cos * cos - 1.0).abs() < 1e-5, "{radians}");
+        }
+    }
+
+    #[test]
+    fn unscaled_is_clipped_at_negative_positions() {
+        #[rustfmt::skip]
+        assert_eq!(reds(&draw_at([-1.0, -1.0])), [
+            4, 0, 0, 0,
+            0, 0, 0, 0,
+            0, 0, 0, 0,
+        ]);
+        #[rustfmt::skip]
+        assert_eq!(reds(&draw_at([-1.0, 1.0])), [
+            0, 0, 0, 0,
+            2, 0, 0, 0,
+            4, 0, 0, 0,
+        ]);
+        for pos in [[-2.0, 0.0], [0.0, -2.0], [-1e9, -1e9], [f32::MIN, f32::MIN]] {
+            assert!(draw_at(pos).iter().all(|&pixel| pixel == CLEAR), "{pos:?}");
+        }
+    }
+
+    #[test]
+    fn unscaled_is_clipped_at_overflowing_positions() {
+        #[rustfmt::skip]
+        assert_eq!(reds(&draw_at([3.0, 2.0])), [
+            0, 0, 0, 0,
+            0, 0, 0, 0,
+            0, 0, 0, 1,
+        ]);
+        for pos in [[4.0, 0.0], [0.0, 3.0], [1e9, 1e9], [f32::MAX, f32::MAX]] {
+            assert!(draw_at(pos).iter().all(|&pixel| pixel == CLEAR), "{pos:?}");
+        }
+    }
+
+    #[test]
+    fn transformed_is_clipped() {
+        let sprite = Sprite::new(&PIXELS, 2, 2);
+        for pos in [
+            [-3.0, -3.0],
+            [7.0, 6.0],
+            [f32::MIN, 0.0],
+            [f32::MAX, f32::MAX],
+        ] {
+            let mut frame = vec![CLEAR; 4 * 3];
+            let transform = Transform2D {
+                pos,
+                rotation: 0.5,
+                scale: [2.0, 2.0],
+                origin: [1.0, 1.0],
+            };
+            draw_sprite(
+                &mut frame,
+                4,
+                3,
+                &sprite,
+                transform,
+                &Tint::NONE,
+                Blend::Alpha,
+            );
+            assert!(frame.iter().all(|&pixel| pixel == CLEAR), "{pos:?}");
+        }
+    }
+
+    fn draw_transformed_2x2(transform: Transform2D) -> Vec<[u8; 4]> {
+        let mut frame = vec![CLEAR; 4 * 3];
+        let sprite = Sprite::new(&PIXELS, 2, 2);
+        draw_sprite(
+            &mut frame,
+            4,
+            3,
+            &sprite,
+            transform,
+            &Tint::NONE,
+            Blend::Replace,
+        );
+        frame
+    }
+
+    #[test]
+    fn non_finite_transforms_draw_nothing() {
+        for transform in [
+            Transform2D {
+                pos: [f32::NAN, 1.0],
+                rotation: 0.5,
+                ..Default::default()
+            },
+            Transform2D {
+                pos: [1.0, f32::INFINITY],
+                scale: [2.0, 2.0],
+                ..Default::default()
+            },
+            Transform2D {
+                pos: [f32::NAN, 0.0],
+                ..Default::default()
+            },
+            Transform2D {
+                rotation: f32::NAN,
+                ..Default::default()
+            },
+            Transform2D {
+                scale: [f32::INFINITY, 1.0],
+                ..Default::default()
+            },
+            Transform2D {
+                origin: [0.0, f32::NEG_INFINITY],
+                rotation: 1.0,
+                ..Default::default()
+            },
+        ] {
+            let frame = draw_transformed_2x2(transform);
+            assert!(frame.iter().all(|&pixel| pixel == CLEAR), "{transform:?}");
+        }
+    }
+
+    #[test]
+    fn rotates_clockwise() {
+        let frame = draw_transformed_2x2(Transform2D {
+            pos: [2.0, 1.0],
+            rotation: FRAC_PI_2,
+            origin: [1.0, 1.0],
+            ..Default::default()
+        });
+        #[rustfmt::skip]
+        assert_eq!(reds(&frame), [
+            0, 3, 1, 0,
+            0, 4, 2, 0,
+            0, 0, 0, 0,
+        ]);
+    }
+
+    #[test]
+    fn scales_from_origin() {
+        let frame = draw_transformed_2x2(Transform2D {
+            scale: [2.0, 2.0],
+            ..Default::default()
+        });
+        #[rustfmt::skip]
+        assert_eq!(reds(&frame), [
+            1, 1, 2, 2,
+            1, 1, 2, 2,
+            3, 3, 4, 4,
+        ]);
+    }
+
+    #[test]
+    fn bilinear_interpolates_between_texels() {
+        let pixels = [[0, 0, 0, 255], [200, 0, 0, 255]];
+        let sprite = Sprite::new(&pixels, 2, 1).with_sampling(Sampling::Bilinear);
+        let mut frame = vec![CLEAR; 4];
+        let transform = Transform2D {
+            scale: [2.0, 1.0],
+            ..Default::default()
+        };
+        draw_sprite(
+            &mut frame,
+            4,
+            1,
+            &sprite,
+            transform,
+            &Tint::NONE,
+            Blend::Replace,
+        );
+        // Frame pixel centers are a quarter and three quarters of the way between the
+        // texel centers, and clamped at the edges.
+        assert_eq!(reds(&frame), [0, 50, 150, 200]);
+    }
+
+    #[test]
+    fn tint_multiplies_in_linear_space() {
+        assert_eq!(Tint::new([1.0; 3], 1.0), Tint::NONE);
+
+        let pixels = [[200, 200, 255, 255]];
+        let sprite = Sprite::new(&pixels, 1, 1);
+        let mut frame = vec![CLEAR; 1];
+        let tint = Tint::new([1.0, 0.0, 0.5], 0.5);
+        draw_sprite(
+            &mut frame,
+            1,
+            1,
+            &sprite,
+            Transform2D::default(),
+            &tint,
+            Blend::Replace,
+        );
+        // Half of linear white is about 0.735 in sRGB.
+        assert_eq!(frame[0], [200, 0, 188, 127]);
+    }
+
+    #[test]
+    fn tint_tables_match_powf() {
+        for factor in [0.0, 0.1, 0.25, 0.5, 0.9, 1.0] {
+            let tint = Tint::new([factor; 3], 1.0);
+            for i in 0..256 {
+                let c = i as f64 / 255.0;
+                let linear = if c <= 0.04045 {
+                    c / 12.92
+                } else {
+                    ((c + 0.055) / 1.055).powf(2.4)
+                } * factor as f64;
+                let c = if linear <= 0.0031308 {
+                    linear * 12.92
+                } else {
+                    1.055 * linear.powf(1.0 / 2.4) - 0.055
+                };
+                let expected = (c * 255.0).round() as i32;
+                let actual = tint.rgb[0][i] as i32;
+                assert!(
+                    (actual - expected).abs() <= 1,
+                    "{factor} {i}: {actual} {expected}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn alpha_blend_extremes() {
+        let dst = [10, 20, 30, 40];
+        assert_eq!(blend_pixel(dst, [200, 100, 50, 0], Blend::Alpha), dst);
+        assert_eq!(
+            blend_pixel(dst, [200, 100, 50, 255], Blend::Alpha),
+            [200, 100, 50, 255]
+        );
+        assert_eq!(
+            blend_pixel([0, 0, 0, 0], [255, 255, 255, 128], Blend::Alpha),
+            [128, 128, 128, 128]
+        );
+    }
+
+    #[test]
+    fn additive_blend_saturates() {
+        let dst = [200, 10, 255, 40];
+        assert_eq!(blend_pixel(dst, [100, 100, 100, 0], Blend::Additive), dst);
+        assert_eq!(
+            blend_pixel(dst, [100, 100, 100, 255], Blend::Additive),
+            [255, 110, 255, 40]
+        );
+        assert_eq!(
+            blend_pixel([0; 4], [255, 255, 255, 255], Blend::Additive),
+            [255, 255, 255, 0]
+        );
+    }
+
+    #[test]
+    fn replace_overwrites() {
+        let src = [1, 2, 3, 0];
+        assert_eq!(blend_pixel([9; 4], src, Blend::Replace), src);
+    }
+}
@@ -0,0 +1,287 @@
+//! Saves the game's memory and window position on quit, and restores them on a later
+//! launch, see [`App::with_state_file`](crate::App::with_state_file).
+//!
+//! The file is a fixed header followed by the bytes of `Memory`, all little endian:
+//! magic, format version, memory version, memory size and alignment, then the window's
+//! origin in screen coordinates.
+
+extern crate std;
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use std::fs::File;
+use std::io::{self, Write};
+
+const MAGIC: &[u8; 4] = b"GLZS";
+// Bumped whenever the header changes.
+const FORMAT_VERSION: u32 = 1;
+const HEADER_LEN: usize = 4 + 4 + 4 + 8 + 8 + 8 + 8;
+
+#[derive(Debug, Clone)]
+pub(crate) struct StateFile {
+    pub path: String,
+    pub version: u32,
+    // game time of the last periodic save
+    pub last_save: f64,
+}
+
+impl StateFile {
+    /// Overwrites `memory` with the saved state, returning the saved window origin.
+    ///
+    /// A missing file is a fresh start, any other problem is logged and also leaves
+    /// `memory` untouched.
+    ///
+    /// # Safety
+    ///
+    /// See [`App::with_state_file`](crate::App::with_state_file).
+    pub unsafe fn load<Memory>(&self, memory: &mut Memory) -> Option<(f64, f64)> {
+        let bytes = match std::fs::read(&self.path) {
+            Ok(bytes) => bytes,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return None,
+            Err(err) => {
+                crate::log!("ERROR: failed to read state file `{}`: {err}", self.path);
+                return None;
+            }
+        };
+        match self.parse::<Memory>(&bytes) {
+            Ok(origin) => {
+                unsafe {
+                    core::ptr::copy_nonoverlapping(
+                        bytes[HEADER_LEN..].as_ptr(),
+                        (memory as *mut Memory).cast::<u8>(),
+                        size_of::<Memory>(),
+                    );
+                }
+                crate::log!("resumed from state file `{}`", self.path);
+                Some(origin)
+            }
+            Err(reason) => {
+                crate::log!(
+                    "ERROR: ignoring state file `{}`, starting fresh: {reason}",
+                    self.path
+                );
+                None
+            }
+        }
+    }
+
+    fn parse<Memory>(&self, bytes: &[u8]) -> Result<(f64, f64), String> {
+        if bytes.len() < HEADER_LEN || &bytes[..4] != MAGIC {
+            return Err(String::from("not a state file"));
+        }
+        let u32_at = |at: usize| u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap());
+        let u64_at = |at: usize| u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap());
+        let format_version = u32_at(4);
+        if format_version != FORMAT_VERSION {
+            return Err(format!("unsupported format version {format_version}"));
+        }
+        let version = u32_at(8);
+        if version != self.version {
+            return Err(format!(
+                "memory version {version}, expected {}",
+                self.version
+            ));
+        }
+        let (size, align) = (u64_at(12), u64_at(20));
+        if size != size_of::<Memory>() as u64 || align != align_of::<Memory>() as u64 {
+            return Err(format!(
+                "memory is {size} bytes aligned to {align}, expected {} aligned to {}",
+                size_of::<Memory>(),
+                align_of::<Memory>()
+            ));
+        }
+        if bytes.len() != HEADER_LEN + size_of::<Memory>() {
+            return Err(format!("truncated to {} bytes", bytes.len()));
+        }
+        Ok((f64::from_bits(u64_at(28)), f64::from_bits(u64_at(36))))
+    }
+
+    /// Writes `memory` and the window origin, replacing the file only once the new one is
+    /// complete so a power loss never leaves a partial file.
+    ///
+    /// # Safety
+    ///
+    /// See [`App::with_state_file`](crate::App::with_state_file).
+    pub unsafe fn save<Memory>(
+        &self,
+        memory: &Memory,
+        window_origin: (f64, f64),
+    ) -> io::Result<()> {
+        let memory = unsafe {
+            core::slice::from_raw_parts((memory as *const Memory).cast::<u8>(), size_of::<Memory>())
+        };
+        let mut bytes = Vec::with_capacity(HEADER_LEN + memory.len());
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&self.version.to_le_bytes());
+        bytes.extend_from_slice(&(size_of::<Memory>() as u64).to_le_bytes());
+        bytes.extend_from_slice(&(align_of::<Memory>() as u64).to_le_bytes());
+        bytes.extend_from_slice(&window_origin.0.to_bits().to_le_bytes());
+        bytes.extend_from_slice(&window_origin.1.to_bits().to_le_bytes());
+        bytes.extend_from_slice(memory);
+
+        let temp_path = format!("{}.tmp", self.path);
+        let mut file = File::create(&temp_path)?;
+        file.write_all(&bytes)?;
+        file.sync_all()?;
+        std::fs::rename(&temp_path, &self.path)
+    }
+
+    /// Saves once `interval` seconds have passed since the last call which saved, logging
+    /// any failure.
+    ///
+    /// # Safety
+    ///
+    /// See [`App::with_state_file`](crate::App::with_state_file).
+    pub unsafe fn save_every<Memory>(
+        &mut self,
+        interval: f32,
+        time: f64,
+        memory: &Memory,
+        window_origin: (f64, f64),
+    ) {
+        if time - self.last_save < interval as f64 {
+            return;
+        }
+        self.last_save = time;
+        if let Err(err) = unsafe { self.save(memory, window_origin) } {
+            crate::log!("ERROR: failed to write state file `{}`: {err}", self.path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[repr(C)]
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    struct Memory {
+        score: u32,
+        position: [f32; 2],
+    }
+
+    const MEMORY: Memory = Memory {
+        score: 7,
+        position: [1.5, -2.0],
+    };
+
+    // Removed again when dropped.
+    struct TempFile(StateFile);
+
+    impl TempFile {
+        fn new(name: &str, version: u32) -> Self {
+            let path =
+                std::env::temp_dir().join(format!("glazer-state-{}-{name}", std::process::id()));
+            Self(StateFile {
+                path: String::from(path.to_str().unwrap()),
+                version,
+                last_save: 0.0,
+            })
+        }
+
+        fn saved_bytes(&self) -> Vec<u8> {
+            unsafe { self.0.save(&MEMORY, (10.0, 20.0)) }.unwrap();
+            std::fs::read(&self.0.path).unwrap()
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0.path);
+        }
+    }
+
+    #[test]
+    fn save_mutate_and_restore() {
+        let file = TempFile::new("round-trip", 3);
+        unsafe { file.0.save(&MEMORY, (10.0, -20.5)) }.unwrap();
+
+        let mut memory = MEMORY;
+        memory.score += 1;
+        memory.position[0] = 0.0;
+        assert_ne!(memory, MEMORY);
+
+        assert_eq!(unsafe { file.0.load(&mut memory) }, Some((10.0, -20.5)));
+        assert_eq!(memory, MEMORY);
+    }
+
+    #[test]
+    fn missing_or_mismatched_files_leave_memory_alone() {
+        let file = TempFile::new("missing", 1);
+        let mut memory = MEMORY;
+        memory.score = 0;
+        assert_eq!(unsafe { file.0.load(&mut memory) }, None);
+
+        let saved = TempFile::new("mismatched", 1);
+        saved.saved_bytes();
+        let newer = StateFile {
+            version: 2,
+            ..saved.0.clone()
+        };
+        assert_eq!(unsafe { newer.load(&mut memory) }, None);
+        assert_eq!(memory.score, 0);
+    }
+
+    #[test]
+    fn parse_accepts_saved_files() {
+        let file = TempFile::new("parse", 1);
+        let bytes = file.saved_bytes();
+        assert_eq!(bytes.len(), HEADER_LEN + size_of::<Memory>());
+        assert_eq!(file.0.parse::<Memory>(&bytes), Ok((10.0, 20.0)));
+    }
+
+    #[test]
+    fn parse_rejects_corrupt_files() {
+        let file = TempFile::new("corrupt", 1);
+        let bytes = file.saved_bytes();
+
+        assert!(file.0.parse::<Memory>(&[]).is_err());
+        assert!(file.0.parse::<Memory>(b"GLZS").is_err());
+
+        let mut magic = bytes.clone();
+        magic[0] = b'X';
+        assert!(file.0.parse::<Memory>(&magic).is_err());
+
+        let mut format = bytes.clone();
+        format[4] = FORMAT_VERSION as u8 + 1;
+        assert!(file.0.parse::<Memory>(&format).is_err());
+
+        for len in [HEADER_LEN - 1, HEADER_LEN, bytes.len() - 1] {
+            assert!(file.0.parse::<Memory>(&bytes[..len]).is_err(), "{len}");
+        }
+        let mut extended = bytes.clone();
+        extended.push(0);
+        assert!(file.0.parse::<Memory>(&extended).is_err());
+    }
+
+    #[test]
+    fn parse_rejects_other_versions_and_layouts() {
+        let file = TempFile::new("versions", 1);
+        let bytes = file.saved_bytes();
+
+        let newer = StateFile {
+            version: 2,
+            ..file.0.clone()
+        };
+        assert!(newer.parse::<Memory>(&bytes).is_err());
+        assert!(file.0.parse::<[u32; 3]>(&bytes).is_ok());
+        assert!(file.0.parse::<[u32; 4]>(&bytes).is_err());
+        assert!(file.0.parse::<[u8; 12]>(&bytes).is_err());
+    }
+
+    #[test]
+    fn save_every_waits_for_the_interval() {
+        let mut file = TempFile::new("interval", 1);
+        unsafe { file.0.save_every(10.0, 5.0, &MEMORY, (0.0, 0.0)) };
+        assert!(std::fs::metadata(&file.0.path).is_err());
+
+        unsafe { file.0.save_every(10.0, 10.0, &MEMORY, (0.0, 0.0)) };
+        assert!(std::fs::metadata(&file.0.path).is_ok());
+        assert_eq!(file.0.last_save, 10.0);
+
+        unsafe { file.0.save_every(10.0, 19.0, &MEMORY, (0.0, 0.0)) };
+        assert_eq!(file.0.last_save, 10.0);
+    }
+}
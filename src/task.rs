@@ -0,0 +1,361 @@
+//! Long running work which reports progress to the game while frames keep rendering.
+//!
+//! A task is a function called until it returns [`Step::Done`]. Each call does a slice of
+//! the work, checking [`TaskContext::should_yield`] and returning [`Step::Pending`] to be
+//! resumed where it left off.
+//!
+//! [`spawn`] runs the task on its own thread where the platform has threads, where it is
+//! never asked to yield. Otherwise, and always with [`spawn_cooperative`], the platform
+//! resumes the task on its own thread before each update for up to
+//! [`FRAME_BUDGET_NANOS`], so a web page stays responsive.
+//!
+//! ```ignore
+//! let mut rows = Vec::new();
+//! memory.terrain = Some(task::spawn(move |context: &TaskContext| {
+//!     while rows.len() < HEIGHT {
+//!         rows.push(generate_row(rows.len()));
+//!         context.set_progress(rows.len() as f32 / HEIGHT as f32);
+//!         if context.should_yield() {
+//!             return Step::Pending;
+//!         }
+//!     }
+//!     Step::Done(core::mem::take(&mut rows))
+//! }));
+//!
+//! // later, in `update_and_render`
+//! if let Some(rows) = memory.terrain.as_mut().and_then(Task::poll) { ... }
+//! ```
+//!
+//! A hot reloaded game dylib has its own cooperative tasks, which the platform only runs
+//! if they are exported with [`export_tasks!`](crate::export_tasks).
+
+#[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+extern crate std;
+
+use crate::debug::{Clock, PlatformClock};
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::cell::UnsafeCell;
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+/// Time given to cooperative tasks before each update, shared between them.
+pub const FRAME_BUDGET_NANOS: u64 = 4_000_000;
+
+/// Returned by a task after each slice of work.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Step<T> {
+    /// The task yielded and is resumed later.
+    Pending,
+    /// The task finished with a result for [`Task::poll`].
+    Done(T),
+}
+
+/// Passed to a running task.
+pub struct TaskContext {
+    progress: Arc<AtomicU32>,
+    // `PlatformClock` nanoseconds
+    deadline: u64,
+    now: fn() -> u64,
+}
+
+impl TaskContext {
+    /// Reports how far along the task is, from `0.0` to `1.0`.
+    pub fn set_progress(&self, progress: f32) {
+        self.progress
+            .store(progress.clamp(0.0, 1.0).to_bits(), Ordering::Relaxed);
+    }
+
+    /// Whether the task should return [`Step::Pending`] to let the frame continue.
+    ///
+    /// Always `false` on a thread.
+    pub fn should_yield(&self) -> bool {
+        (self.now)() >= self.deadline
+    }
+}
+
+/// Handle to a spawned task, polled from `update_and_render`.
+pub struct Task<T> {
+    progress: Arc<AtomicU32>,
+    result: TaskResult<T>,
+}
+
+enum TaskResult<T> {
+    #[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+    Thread(Option<std::thread::JoinHandle<T>>),
+    Cooperative(Arc<Lock<Slot<T>>>),
+}
+
+enum Slot<T> {
+    Running,
+    Done(T),
+    Taken,
+}
+
+impl<T> Task<T> {
+    /// The last progress reported with [`TaskContext::set_progress`].
+    pub fn progress(&self) -> f32 {
+        f32::from_bits(self.progress.load(Ordering::Relaxed))
+    }
+
+    /// Whether the task has finished, including after its result was taken.
+    pub fn is_finished(&self) -> bool {
+        match &self.result {
+            #[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+            TaskResult::Thread(handle) => handle.as_ref().is_none_or(|handle| handle.is_finished()),
+            TaskResult::Cooperative(slot) => slot.with(|slot| !matches!(slot, Slot::Running)),
+        }
+    }
+
+    /// Takes the task's result once it has finished.
+    ///
+    /// Returns `None` while the task is running and after the result has been taken.
+    /// Panics if the task panicked on a thread.
+    pub fn poll(&mut self) -> Option<T> {
+        match &mut self.result {
+            #[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+            TaskResult::Thread(handle) => {
+                if !handle.as_ref()?.is_finished() {
+                    return None;
+                }
+                Some(handle.take()?.join().expect("task panicked"))
+            }
+            TaskResult::Cooperative(slot) => slot.with(|slot| match slot {
+                Slot::Done(_) => match core::mem::replace(slot, Slot::Taken) {
+                    Slot::Done(result) => Some(result),
+                    _ => unreachable!(),
+                },
+                Slot::Running | Slot::Taken => None,
+            }),
+        }
+    }
+}
+
+/// Runs `task` on its own thread, or with [`spawn_cooperative`] on platforms without
+/// threads.
+pub fn spawn<T, F>(task: F) -> Task<T>
+where
+    T: Send + 'static,
+    F: FnMut(&TaskContext) -> Step<T> + Send + 'static,
+{
+    #[cfg(not(all(feature = "std", not(target_arch = "wasm32"))))]
+    return spawn_cooperative(task);
+
+    #[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+    {
+        let mut task = task;
+        let progress = Arc::new(AtomicU32::new(0.0f32.to_bits()));
+        let context = TaskContext {
+            progress: progress.clone(),
+            deadline: u64::MAX,
+            now: || 0,
+        };
+        let handle = std::thread::spawn(move || {
+            loop {
+                if let Step::Done(result) = task(&context) {
+                    return result;
+                }
+            }
+        });
+        Task {
+            progress,
+            result: TaskResult::Thread(Some(handle)),
+        }
+    }
+}
+
+/// Runs `task` on the platform's thread in slices before each update, even where
+/// [`spawn`] would use a thread.
+pub fn spawn_cooperative<T, F>(mut task: F) -> Task<T>
+where
+    T: Send + 'static,
+    F: FnMut(&TaskContext) -> Step<T> + Send + 'static,
+{
+    let progress = Arc::new(AtomicU32::new(0.0f32.to_bits()));
+    let slot = Arc::new(Lock::new(Slot::Running));
+    let result = slot.clone();
+    COOPERATIVE_TASKS.with(|tasks| {
+        tasks.push(Cooperative {
+            context: TaskContext {
+                progress: progress.clone(),
+                deadline: 0,
+                now: PlatformClock::now_nanos,
+            },
+            step: Box::new(move |context| match task(context) {
+                Step::Pending => false,
+                Step::Done(value) => {
+                    result.with(|result| *result = Slot::Done(value));
+                    true
+                }
+            }),
+        })
+    });
+    Task {
+        progress,
+        result: TaskResult::Cooperative(slot),
+    }
+}
+
+struct Cooperative {
+    context: TaskContext,
+    // `true` once the task is done
+    step: Box<dyn FnMut(&TaskContext) -> bool + Send>,
+}
+
+static COOPERATIVE_TASKS: Lock<Vec<Cooperative>> = Lock::new(Vec::new());
+
+// Called by the platform before each update, see `export_tasks`.
+#[doc(hidden)]
+pub extern "C" fn __run_cooperative(budget_nanos: u64) {
+    run_cooperative::<PlatformClock>(budget_nanos);
+}
+
+// Resumes every cooperative task once, in the order they were spawned. The budget is
+// shared, so later tasks may only get as far as their first `should_yield`.
+fn run_cooperative<C: Clock>(budget_nanos: u64) {
+    // Taken out so tasks can spawn more tasks, which first run on the next call.
+    let mut tasks = COOPERATIVE_TASKS.with(core::mem::take);
+    if tasks.is_empty() {
+        return;
+    }
+    let deadline = C::now_nanos().saturating_add(budget_nanos);
+    tasks.retain_mut(|task| {
+        task.context.deadline = deadline;
+        task.context.now = C::now_nanos;
+        !(task.step)(&task.context)
+    });
+    COOPERATIVE_TASKS.with(|queue| {
+        let spawned = core::mem::replace(queue, tasks);
+        queue.extend(spawned);
+    });
+}
+
+/// Exports the game dylib's cooperative tasks, so the platform runs them while hot
+/// reloading. Without it, tasks from [`task::spawn_cooperative`] in the dylib never run.
+///
+/// ```ignore
+/// glazer::export_tasks!();
+/// ```
+///
+/// [`task::spawn_cooperative`]: crate::task::spawn_cooperative
+#[macro_export]
+macro_rules! export_tasks {
+    () => {
+        #[doc(hidden)]
+        #[unsafe(no_mangle)]
+        pub extern "C" fn glazer_run_tasks(budget_nanos: u64) {
+            $crate::task::__run_cooperative(budget_nanos)
+        }
+    };
+}
+
+// A spin lock, `core` has no mutex. Cooperative tasks all run on the platform's thread,
+// so it is only contended while a handle is polled from another thread.
+struct Lock<T> {
+    locked: AtomicBool,
+    value: UnsafeCell<T>,
+}
+
+unsafe impl<T: Send> Sync for Lock<T> {}
+
+impl<T> Lock<T> {
+    const fn new(value: T) -> Self {
+        Self {
+            locked: AtomicBool::new(false),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    fn with<R>(&self, f: impl FnOnce(&mut T) -> R) -> R {
+        while self
+            .locked
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            core::hint::spin_loop();
+        }
+        let result = f(unsafe { &mut *self.value.get() });
+        self.locked.store(false, Ordering::Release);
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::sync::atomic::AtomicU64;
+
+    // Advances a millisecond every time it is read.
+    struct SteppingClock;
+
+    static NOW: AtomicU64 = AtomicU64::new(0);
+
+    impl Clock for SteppingClock {
+        fn now_nanos() -> u64 {
+            NOW.fetch_add(1_000_000, Ordering::Relaxed)
+        }
+    }
+
+    // Counts to `units`, yielding whenever asked between them.
+    fn counter(units: u32) -> impl FnMut(&TaskContext) -> Step<u32> + Send + 'static {
+        let mut done = 0;
+        move |context| {
+            loop {
+                done += 1;
+                context.set_progress(done as f32 / units as f32);
+                if done == units {
+                    return Step::Done(done);
+                }
+                if context.should_yield() {
+                    return Step::Pending;
+                }
+            }
+        }
+    }
+
+    // The only test which runs the shared cooperative queue.
+    #[test]
+    fn cooperative_tasks_run_in_slices() {
+        let mut long = spawn_cooperative(counter(10));
+        let mut short = spawn_cooperative(counter(1));
+        assert_eq!(long.progress(), 0.0);
+        assert!(!long.is_finished());
+
+        // Reading the deadline and three checks fit in 4ms, the fourth check yields.
+        run_cooperative::<SteppingClock>(4_000_000);
+        assert_eq!(long.progress(), 0.4);
+        assert_eq!(long.poll(), None);
+        // Resumed although the budget is spent.
+        assert!(short.is_finished());
+        assert_eq!(short.poll(), Some(1));
+        assert_eq!(short.poll(), None);
+        assert!(short.is_finished());
+
+        run_cooperative::<SteppingClock>(4_000_000);
+        assert_eq!(long.progress(), 0.8);
+        assert!(!long.is_finished());
+
+        run_cooperative::<SteppingClock>(4_000_000);
+        assert_eq!(long.progress(), 1.0);
+        assert!(long.is_finished());
+        assert_eq!(long.poll(), Some(10));
+        assert_eq!(long.poll(), None);
+        assert!(COOPERATIVE_TASKS.with(|tasks| tasks.is_empty()));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn threaded_tasks_never_yield() {
+        let mut task = spawn(counter(1000));
+        let result = loop {
+            if let Some(result) = task.poll() {
+                break result;
+            }
+            std::thread::yield_now();
+        };
+        assert_eq!(result, 1000);
+        assert_eq!(task.progress(), 1.0);
+        assert!(task.is_finished());
+        assert_eq!(task.poll(), None);
+    }
+}
@@ -61,6 +61,7 @@ fn game_loop(
     mut framebuffer: Vec<u8>,
 ) {
     let closure = Closure::once_into_js(move || {
+        crate::task::__run_cooperative(crate::task::FRAME_BUDGET_NANOS);
         update(PlatformState {
             frame_buffer: framebuffer.as_mut_slice(),
             width: 600,